@@ -1,21 +1,82 @@
 use std::{
-    collections::{BTreeMap, BTreeSet},
+    cmp::Reverse,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    fs::File,
+    io::{self, BufWriter, Write},
+    mem,
     path::{Path, PathBuf},
     sync::{
         Arc,
         mpsc::{Receiver, TryRecvError},
     },
-    time::{Duration, SystemTime, UNIX_EPOCH},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
 use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
 
 use crate::{
     Result,
-    audio::AudioEngine,
-    library::{self, DirectoryEntry, DirectoryEntryKind, ScanEvent, Track},
+    audio::{AudioEngine, GainMode},
+    library::{
+        self, DirectoryEntry, DirectoryEntryKind, LibraryDiff, LibraryWatcher, ScanEvent,
+        ScanScope, Track, compare_text,
+    },
+    history::{PlayHistory, PlayStats},
+    mpd::MpdServer,
+    playlist_file::{self, PlaylistFile, PlaylistFormat},
+    query::{Query, SearchText},
+    scrobble::{self, Scrobbler},
+    smart::{Rule, SmartPlaylist},
     storage,
 };
+#[cfg(unix)]
+use crate::control::{self, ControlCommand, ControlServer};
+#[cfg(feature = "mpris")]
+use crate::mpris;
+
+const SESSION_SAVE_INTERVAL: Duration = Duration::from_secs(30);
+const PLAYED_THRESHOLD: Duration = Duration::from_secs(240);
+
+#[derive(Clone, Debug, Default)]
+pub struct LaunchOptions {
+    pub volume: Option<u8>,
+    pub open: Option<PathBuf>,
+    pub mpd_port: Option<u16>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum RemoteCommand {
+    Play,
+    Pause,
+    PlayPause,
+    Stop,
+    Next,
+    Previous,
+    Seek(f64),
+    SetPosition(f64),
+    SetVolume(u8),
+    ChangeVolume(i16),
+    SetShuffle(bool),
+    ToggleShuffle,
+    SetRepeat(RepeatMode),
+    CycleRepeat,
+    Enqueue { path: PathBuf, next: bool },
+    PlayQueuePosition(usize),
+    RemoveQueuePosition(usize),
+    ClearQueue,
+    LoadPlaylist(String),
+}
+
+#[derive(Clone, Debug)]
+pub struct PlayerStatus {
+    pub state: PlaybackState,
+    pub track: Option<Track>,
+    pub position: Duration,
+    pub total: Option<Duration>,
+    pub volume: u8,
+    pub shuffle: bool,
+    pub repeat: RepeatMode,
+}
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum PlaybackState {
@@ -54,19 +115,25 @@ pub enum View {
     Songs,
     Albums,
     Artists,
+    Genres,
+    Years,
     Playlists,
     Folders,
+    Queue,
 }
 
 impl View {
-    pub const ALL: [Self; 7] = [
+    pub const ALL: [Self; 10] = [
         Self::Home,
         Self::Search,
         Self::Songs,
         Self::Albums,
         Self::Artists,
+        Self::Genres,
+        Self::Years,
         Self::Playlists,
         Self::Folders,
+        Self::Queue,
     ];
 
     pub fn label(self) -> &'static str {
@@ -76,8 +143,11 @@ impl View {
             Self::Songs => "Songs",
             Self::Albums => "Albums",
             Self::Artists => "Artists",
+            Self::Genres => "Genres",
+            Self::Years => "Years",
             Self::Playlists => "Playlists",
             Self::Folders => "Folders",
+            Self::Queue => "Queue",
         }
     }
 }
@@ -86,20 +156,53 @@ impl View {
 pub enum DetailView {
     Album(usize),
     Artist(usize),
+    Genre(usize),
+    Year(usize),
     Playlist(usize),
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum YearGrouping {
+    Year,
+    Decade,
+}
+
+impl YearGrouping {
+    fn key(self, track: &Track) -> String {
+        match (self, track.year) {
+            (_, None) => String::new(),
+            (Self::Year, Some(year)) => format!("{year:04}"),
+            (Self::Decade, Some(year)) => format!("{:04}s", year / 10 * 10),
+        }
+    }
+
+    fn name(self, track: &Track) -> Arc<str> {
+        match self.key(track) {
+            key if key.is_empty() => Arc::from("Unknown Year"),
+            key => Arc::from(key),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HomeSection {
+    Recent,
+    MostPlayed,
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum FolderFocus {
     Roots,
     Browser,
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
 pub enum SearchItem {
     Playlist(usize),
     Artist(usize),
     Album(usize),
+    Genre(usize),
+    Year(usize),
     Track(usize),
 }
 
@@ -118,6 +221,47 @@ pub struct Artist {
     pub album_count: usize,
 }
 
+#[derive(Clone, Debug)]
+pub struct ArtistCredits {
+    pub album_artists: bool,
+    pub separators: Vec<String>,
+}
+
+impl ArtistCredits {
+    pub fn new(settings: &storage::Settings) -> Self {
+        Self {
+            album_artists: settings.album_artists,
+            separators: settings.artist_separators.clone(),
+        }
+    }
+
+    fn names<'a>(&self, track: &'a Track) -> Vec<&'a str> {
+        let name = if self.album_artists {
+            track.album_artist.as_ref()
+        } else {
+            track.artist.as_ref()
+        };
+        library::split_artists(name, &self.separators)
+    }
+
+    fn keys(&self, track: &Track) -> Vec<String> {
+        let mut keys: Vec<String> = self
+            .names(track)
+            .into_iter()
+            .map(str::to_lowercase)
+            .collect();
+        keys.sort_unstable();
+        keys.dedup();
+        keys
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Category {
+    pub name: Arc<str>,
+    pub tracks: Vec<usize>,
+    pub album_count: usize,
+}
 
 #[derive(Clone, Debug)]
 pub struct Playlist {
@@ -125,12 +269,27 @@ pub struct Playlist {
     pub track_paths: Vec<PathBuf>,
     pub tracks: Vec<usize>,
     pub duration: Duration,
+    pub source: Option<PathBuf>,
+    pub smart: Option<SmartPlaylist>,
+    entries: Vec<playlist_file::Entry>,
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum TextInputKind {
     CreatePlaylist,
+    SaveSearch,
     RenamePlaylist(usize),
+    ImportPlaylistFile,
+    ExportPlaylistFile(usize),
+}
+
+impl TextInputKind {
+    fn max_length(self) -> usize {
+        match self {
+            Self::CreatePlaylist | Self::SaveSearch | Self::RenamePlaylist(_) => 80,
+            Self::ImportPlaylistFile | Self::ExportPlaylistFile(_) => 1024,
+        }
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -148,6 +307,43 @@ pub struct PlaylistPicker {
     pub source_label: String,
 }
 
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Upcoming {
+    Track {
+        position: Option<usize>,
+        track: usize,
+    },
+    Browser {
+        position: usize,
+        path: PathBuf,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ListenEnd {
+    Interrupted,
+    Finished,
+    Skipped,
+}
+
+#[derive(Clone, Debug)]
+struct Listen {
+    track: Track,
+    started_at: u64,
+    listened: Duration,
+    checked: Instant,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum SelectionKey {
+    Track(PathBuf),
+    Album(String),
+    Artist(String),
+    Genre(String),
+    Year(String),
+    Playlist(usize),
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum ScanPhase {
     Idle,
@@ -158,10 +354,16 @@ pub enum ScanPhase {
 pub struct App {
     pub roots: Vec<PathBuf>,
     pub tracks: Vec<Track>,
+    pub library_generation: u64,
     path_order: Vec<usize>,
     recent_indices_cache: Vec<usize>,
+    most_played_cache: Vec<usize>,
     pub albums: Vec<Album>,
     pub artists: Vec<Artist>,
+    pub artist_credits: ArtistCredits,
+    pub genres: Vec<Category>,
+    pub years: Vec<Category>,
+    pub year_grouping: YearGrouping,
     pub playlists: Vec<Playlist>,
 
     pub view: View,
@@ -170,6 +372,8 @@ pub struct App {
 
     pub search_query: String,
     pub search_results: Vec<SearchItem>,
+    search_text: Vec<SearchText>,
+    search_base: Option<Query>,
     pub search_editing: bool,
 
     pub current: Option<usize>,
@@ -182,6 +386,11 @@ pub struct App {
     pub queue: Vec<usize>,
     pub queue_pos: Option<usize>,
     pub recent_paths: Vec<PathBuf>,
+    pub home_section: HomeSection,
+    history: PlayHistory,
+    scrobbler: Scrobbler,
+    listen: Option<Listen>,
+    listen_end: ListenEnd,
 
     pub status: String,
     pub theme: Theme,
@@ -199,17 +408,32 @@ pub struct App {
     browser_queue: Vec<PathBuf>,
     browser_queue_pos: Option<usize>,
     browser_current: Option<Track>,
+    preloaded: Option<Upcoming>,
+    preload_failed: Option<Upcoming>,
 
     pub scan_phase: ScanPhase,
     scan_rx: Option<Receiver<ScanEvent>>,
     rescan_pending: bool,
+    watcher: Option<LibraryWatcher>,
+    watch_pending: BTreeSet<PathBuf>,
+    watch_deadline: Option<Instant>,
+    settings: storage::Settings,
+    pending_session: Option<storage::Session>,
+    session_saved: Instant,
+    mpd: Option<MpdServer>,
+    #[cfg(unix)]
+    control: Option<ControlServer>,
+    #[cfg(feature = "mpris")]
+    mpris: Option<mpris::MprisServer>,
+    quit_requested: bool,
     audio: AudioEngine,
 }
 
 impl App {
-    pub fn new() -> Self {
+    pub fn new(options: LaunchOptions) -> Self {
         let roots = storage::load_roots();
         let theme = storage::load_theme();
+        let settings = storage::load_settings();
         let playlists = storage::load_playlists()
             .into_iter()
             .map(|playlist| Playlist {
@@ -217,11 +441,15 @@ impl App {
                 track_paths: playlist.tracks,
                 tracks: Vec::new(),
                 duration: Duration::ZERO,
+                source: None,
+                smart: playlist.smart,
+                entries: Vec::new(),
             })
             .collect();
         let browser_dir = storage::home_dir();
         let browser_entries = library::read_directory_entries(&browser_dir).unwrap_or_default();
-        let volume = 70;
+        let session = storage::load_session();
+        let volume = options.volume.unwrap_or(session.volume);
         let view = if roots.is_empty() {
             View::Folders
         } else {
@@ -231,26 +459,39 @@ impl App {
         let mut app = Self {
             roots,
             tracks: Vec::new(),
+            library_generation: 0,
             path_order: Vec::new(),
             recent_indices_cache: Vec::new(),
+            most_played_cache: Vec::new(),
             albums: Vec::new(),
             artists: Vec::new(),
+            artist_credits: ArtistCredits::new(&settings),
+            genres: Vec::new(),
+            years: Vec::new(),
+            year_grouping: YearGrouping::Year,
             playlists,
             view,
             detail: None,
             selected: 0,
             search_query: String::new(),
             search_results: Vec::new(),
+            search_text: Vec::new(),
+            search_base: None,
             search_editing: false,
             current: None,
             state: PlaybackState::Stopped,
             volume,
-            shuffle: false,
-            repeat: RepeatMode::Off,
+            shuffle: session.shuffle,
+            repeat: session.repeat,
             queue_base: Vec::new(),
             queue: Vec::new(),
             queue_pos: None,
-            recent_paths: Vec::new(),
+            recent_paths: session.recent.clone(),
+            home_section: HomeSection::Recent,
+            history: PlayHistory::load(),
+            scrobbler: Scrobbler::new(settings.scrobble.clone(), storage::scrobbles_path()),
+            listen: None,
+            listen_end: ListenEnd::Interrupted,
             status: String::new(),
             theme,
             help_open: false,
@@ -265,19 +506,104 @@ impl App {
             browser_queue: Vec::new(),
             browser_queue_pos: None,
             browser_current: None,
+            preloaded: None,
+            preload_failed: None,
             scan_phase: ScanPhase::Idle,
             scan_rx: None,
             rescan_pending: false,
-            audio: AudioEngine::new(volume),
+            watcher: None,
+            watch_pending: BTreeSet::new(),
+            watch_deadline: None,
+            pending_session: (session.current.is_some() || !session.queue_base.is_empty())
+                .then_some(session),
+            session_saved: Instant::now(),
+            mpd: None,
+            #[cfg(unix)]
+            control: None,
+            #[cfg(feature = "mpris")]
+            mpris: None,
+            quit_requested: false,
+            audio: {
+                let mut audio = AudioEngine::new(volume);
+                audio.set_crossfade(
+                    Duration::from_secs_f64(settings.crossfade_seconds),
+                    settings.crossfade_curve,
+                );
+                audio.set_gain(
+                    settings.gain_mode,
+                    settings.gain_preamp,
+                    settings.prevent_clipping,
+                );
+                audio
+            },
+            settings,
         };
 
         if !app.roots.is_empty() {
             app.begin_scan();
+            app.restart_watcher();
+        }
+        if let Some(port) = options.mpd_port.or(app.settings.mpd_port) {
+            match MpdServer::start(port) {
+                Ok(server) => app.mpd = Some(server),
+                Err(error) => app.status = format!("MPD server unavailable on port {port}: {error}"),
+            }
+        }
+        #[cfg(unix)]
+        match ControlServer::start() {
+            Ok(server) => app.control = Some(server),
+            Err(error) => app.status = format!("Control socket unavailable: {error}"),
+        }
+        #[cfg(feature = "mpris")]
+        match mpris::MprisServer::start() {
+            Ok(server) => app.mpris = Some(server),
+            Err(error) => app.status = format!("MPRIS unavailable: {error}"),
+        }
+        if let Some(path) = options.open {
+            app.pending_session = None;
+            if let Err(error) = app.open_path(&path) {
+                app.status = error.to_string();
+            }
+        } else if app.roots.is_empty() {
+            if let Some(session) = app.pending_session.take() {
+                app.restore_session(session);
+            }
         }
 
         app
     }
 
+    fn open_path(&mut self, path: &Path) -> Result<()> {
+        let path = path.canonicalize().map_err(|error| {
+            io::Error::other(format!("cannot open {}: {error}", path.display()))
+        })?;
+
+        let (queue, selected) = if path.is_file() && library::is_supported_audio(&path) {
+            let parent = path.parent().map(Path::to_path_buf).unwrap_or_default();
+            let queue = library::read_directory_entries(&parent)?
+                .into_iter()
+                .filter_map(|entry| match entry.kind {
+                    DirectoryEntryKind::Track(track) => Some(track.path),
+                    _ => None,
+                })
+                .collect();
+            self.browser_dir = parent;
+            (queue, path.clone())
+        } else {
+            let queue = playable_paths(&path)?;
+            if path.is_dir() {
+                self.browser_dir = path.clone();
+            }
+            let first = queue[0].clone();
+            (queue, first)
+        };
+
+        self.browser_entries = library::read_directory_entries(&self.browser_dir).unwrap_or_default();
+        self.play_browser_queue(queue, &selected)?;
+        self.set_view(View::Queue);
+        Ok(())
+    }
+
     pub fn handle_key(&mut self, key: KeyEvent) -> bool {
         if key.modifiers.contains(KeyModifiers::CONTROL)
             && matches!(key.code, KeyCode::Char('c') | KeyCode::Char('q'))
@@ -356,6 +682,34 @@ impl App {
                 self.set_view(View::Folders);
                 Ok(())
             }
+            KeyCode::Char('8') => {
+                self.set_view(View::Queue);
+                Ok(())
+            }
+            KeyCode::Char('9') => {
+                self.set_view(View::Genres);
+                Ok(())
+            }
+            KeyCode::Char('0') => {
+                self.set_view(View::Years);
+                Ok(())
+            }
+            KeyCode::Up if self.view == View::Queue && key.modifiers.contains(KeyModifiers::SHIFT) => {
+                self.move_queue_item(-1);
+                Ok(())
+            }
+            KeyCode::Down if self.view == View::Queue && key.modifiers.contains(KeyModifiers::SHIFT) => {
+                self.move_queue_item(1);
+                Ok(())
+            }
+            KeyCode::Char('K') if self.view == View::Queue => {
+                self.move_queue_item(-1);
+                Ok(())
+            }
+            KeyCode::Char('J') if self.view == View::Queue => {
+                self.move_queue_item(1);
+                Ok(())
+            }
             KeyCode::Up | KeyCode::Char('k') => {
                 self.move_selection(-1);
                 Ok(())
@@ -392,6 +746,18 @@ impl App {
                 self.browser_up();
                 Ok(())
             }
+            KeyCode::Tab if self.view == View::Home => {
+                self.toggle_home_section();
+                Ok(())
+            }
+            KeyCode::Tab if self.view == View::Artists => {
+                self.toggle_album_artists();
+                Ok(())
+            }
+            KeyCode::Tab if self.view == View::Years => {
+                self.toggle_year_grouping();
+                Ok(())
+            }
             KeyCode::Enter => self.activate_selected(),
             KeyCode::Char(' ') => self.toggle_playback(),
             KeyCode::Char('n') => self.next_track(false),
@@ -418,6 +784,10 @@ impl App {
                 self.toggle_theme();
                 Ok(())
             }
+            KeyCode::Char('v') => {
+                self.cycle_gain_mode();
+                Ok(())
+            }
             KeyCode::Char('u') => {
                 self.begin_scan();
                 Ok(())
@@ -427,11 +797,36 @@ impl App {
                 Ok(())
             }
             KeyCode::Char('e') if self.view == View::Playlists => self.open_rename_playlist(),
+            KeyCode::Char('s') if self.view == View::Search => {
+                self.open_save_search();
+                Ok(())
+            }
+            KeyCode::Char('i') if self.view == View::Playlists => {
+                self.open_import_playlist();
+                Ok(())
+            }
+            KeyCode::Char('w') if self.view == View::Playlists => self.open_export_playlist(),
             KeyCode::Char('a') if self.view == View::Folders => self.add_browser_root(),
             KeyCode::Char('a') => self.open_playlist_picker(),
             KeyCode::Char('d') if self.view == View::Folders => self.remove_selected_root(),
             KeyCode::Char('d') if self.view == View::Playlists => self.remove_selected_playlist_track(),
             KeyCode::Char('D') if self.view == View::Playlists => self.delete_selected_playlist(),
+            KeyCode::Char('d') | KeyCode::Delete if self.view == View::Queue => {
+                self.remove_queue_item();
+                Ok(())
+            }
+            KeyCode::Char('c') if self.view == View::Queue => {
+                self.clear_queue();
+                Ok(())
+            }
+            KeyCode::Char('N') => {
+                self.enqueue_selection(true);
+                Ok(())
+            }
+            KeyCode::Char('A') => {
+                self.enqueue_selection(false);
+                Ok(())
+            }
             KeyCode::Char('~') if self.view == View::Folders => {
                 self.browser_dir = storage::home_dir();
                 self.refresh_browser();
@@ -449,28 +844,467 @@ impl App {
 
     pub fn tick(&mut self) -> bool {
         let mut changed = self.poll_scan();
-
-        if self.state == PlaybackState::Playing && self.audio.is_empty() {
+        changed |= self.poll_watcher();
+        changed |= self.poll_mpd();
+        #[cfg(unix)]
+        {
+            changed |= self.poll_control();
+        }
+        #[cfg(feature = "mpris")]
+        {
+            changed |= self.poll_mpris();
+        }
+        if let Some(message) = self.scrobbler.poll() {
+            self.status = message;
             changed = true;
-            if let Err(error) = self.next_track(true) {
+        }
+
+        self.update_listen();
+        if self.state == PlaybackState::Playing {
+            if self.audio.take_advanced() {
+                changed = true;
+                self.listen_end = ListenEnd::Finished;
+                self.finish_gapless_advance();
+            } else if self.audio.is_empty() {
+                changed = true;
+                self.listen_end = ListenEnd::Finished;
+                if let Err(error) = self.next_track(true) {
+                    self.status = error.to_string();
+                    self.state = PlaybackState::Stopped;
+                }
+            }
+            self.listen_end = ListenEnd::Interrupted;
+        }
+        if self.state != PlaybackState::Stopped {
+            self.sync_preload();
+        }
+        if self.state == PlaybackState::Playing
+            && self.session_saved.elapsed() >= SESSION_SAVE_INTERVAL
+        {
+            self.session_saved = Instant::now();
+            if let Err(error) = self.save_session() {
+                self.status = format!("Could not save session: {error}");
+                changed = true;
+            }
+        }
+
+        changed
+    }
+
+    pub fn handle_remote(&mut self, command: RemoteCommand) -> Result<()> {
+        match command {
+            RemoteCommand::Play if self.state == PlaybackState::Playing => Ok(()),
+            RemoteCommand::Pause if self.state != PlaybackState::Playing => Ok(()),
+            RemoteCommand::Play | RemoteCommand::PlayPause
+                if self.state == PlaybackState::Stopped
+                    && self.current_track().is_none()
+                    && !self.queue.is_empty() =>
+            {
+                self.jump_to_queue_item(self.queue_pos.unwrap_or(0))
+            }
+            RemoteCommand::Play | RemoteCommand::Pause | RemoteCommand::PlayPause => {
+                self.toggle_playback()
+            }
+            RemoteCommand::Stop => {
+                if self.state != PlaybackState::Stopped {
+                    self.stop();
+                    self.status = "Stopped".to_owned();
+                }
+                Ok(())
+            }
+            RemoteCommand::Next => self.next_track(false),
+            RemoteCommand::Previous => self.previous_track(),
+            RemoteCommand::Seek(offset) => {
+                self.seek_to(self.audio.position().as_secs_f64() + offset)
+            }
+            RemoteCommand::SetPosition(seconds) => self.seek_to(seconds),
+            RemoteCommand::SetVolume(volume) => {
+                self.change_volume(volume.min(100) as i16 - self.volume as i16);
+                Ok(())
+            }
+            RemoteCommand::ChangeVolume(delta) => {
+                self.change_volume(delta);
+                Ok(())
+            }
+            RemoteCommand::SetShuffle(shuffle) => {
+                if shuffle != self.shuffle {
+                    self.toggle_shuffle();
+                }
+                Ok(())
+            }
+            RemoteCommand::ToggleShuffle => {
+                self.toggle_shuffle();
+                Ok(())
+            }
+            RemoteCommand::SetRepeat(repeat) => {
+                self.repeat = repeat;
+                self.status = format!("Repeat {}", repeat.label());
+                Ok(())
+            }
+            RemoteCommand::CycleRepeat => {
+                self.cycle_repeat();
+                Ok(())
+            }
+            RemoteCommand::Enqueue { path, next } => {
+                let paths = playable_paths(&path)?;
+                let label = path
+                    .file_name()
+                    .map_or_else(|| path.display().to_string(), |name| {
+                        name.to_string_lossy().into_owned()
+                    });
+                self.enqueue_paths(paths, &label, next)
+            }
+            RemoteCommand::PlayQueuePosition(position) => {
+                if position >= self.active_queue_len() {
+                    return Err(io::Error::other("No such queue entry"));
+                }
+                self.jump_to_queue_item(position)
+            }
+            RemoteCommand::RemoveQueuePosition(position) => self.remove_queue_position(position),
+            RemoteCommand::ClearQueue => {
+                self.clear_queue();
+                Ok(())
+            }
+            RemoteCommand::LoadPlaylist(name) => {
+                let Some(playlist) = self
+                    .playlists
+                    .iter()
+                    .find(|playlist| playlist.name == name)
+                else {
+                    return Err(io::Error::other(format!("No playlist named {name}")));
+                };
+                let paths = playlist.track_paths.clone();
+                self.enqueue_paths(paths, &name, false)
+            }
+        }
+    }
+
+    pub fn player_status(&self) -> PlayerStatus {
+        PlayerStatus {
+            state: self.state,
+            track: self.current_track().cloned(),
+            position: Duration::from_secs_f64(self.position_seconds()),
+            total: self.audio.total(),
+            volume: self.volume,
+            shuffle: self.shuffle,
+            repeat: self.repeat,
+        }
+    }
+
+    fn poll_mpd(&mut self) -> bool {
+        let Some(server) = self.mpd.take() else {
+            return false;
+        };
+        let requests = server.requests();
+        let changed = !requests.is_empty();
+        for request in requests {
+            server.respond(self, request);
+        }
+        let mut server = server;
+        server.update(self);
+        self.mpd = Some(server);
+        changed
+    }
+
+    #[cfg(unix)]
+    fn poll_control(&mut self) -> bool {
+        let Some(server) = &self.control else {
+            return false;
+        };
+        let requests = server.requests();
+        let mut changed = false;
+        for request in requests {
+            let reply = match &request.command {
+                ControlCommand::Status => Ok(control::format_status(&self.player_status())),
+                ControlCommand::Scrobble => {
+                    self.scrobbler.retry_now();
+                    Ok(format!("queued\t{}", self.scrobbler.pending()))
+                }
+                ControlCommand::Quit => {
+                    self.quit_requested = true;
+                    Ok(String::new())
+                }
+                ControlCommand::Remote(command) => {
+                    changed = true;
+                    self.handle_remote(command.clone())
+                        .map(|()| String::new())
+                        .map_err(|error| error.to_string())
+                }
+            };
+            request.respond(reply);
+        }
+        changed
+    }
+
+    #[cfg(feature = "mpris")]
+    fn poll_mpris(&mut self) -> bool {
+        let Some(server) = &self.mpris else {
+            return false;
+        };
+        let commands = server.commands();
+        let changed = !commands.is_empty();
+        for command in commands {
+            if let Err(error) = self.handle_remote(command) {
                 self.status = error.to_string();
-                self.state = PlaybackState::Stopped;
             }
         }
 
+        let status = self.player_status();
+        if let Some(server) = &mut self.mpris {
+            server.publish(&status);
+        }
         changed
     }
 
+    pub fn save_session(&self) -> Result<()> {
+        let session = match &self.pending_session {
+            Some(pending) if self.state == PlaybackState::Stopped => storage::Session {
+                volume: self.volume,
+                shuffle: self.shuffle,
+                repeat: self.repeat,
+                recent: self.recent_paths.clone(),
+                ..pending.clone()
+            },
+            _ => self.session(),
+        };
+        storage::save_session(&session)
+    }
+
+    fn session(&self) -> storage::Session {
+        let paths = |indices: &[usize]| -> Vec<PathBuf> {
+            indices
+                .iter()
+                .filter_map(|index| self.tracks.get(*index))
+                .map(|track| track.path.clone())
+                .collect()
+        };
+        let folder = self.browser_current.is_some();
+        let (current, queue_pos, queue_base, queue) = if folder {
+            (
+                self.browser_current.as_ref().map(|track| track.path.clone()),
+                self.browser_queue_pos,
+                self.browser_queue_base.clone(),
+                self.browser_queue.clone(),
+            )
+        } else {
+            (
+                self.current
+                    .and_then(|index| self.tracks.get(index))
+                    .map(|track| track.path.clone()),
+                self.queue_pos,
+                paths(&self.queue_base),
+                paths(&self.queue),
+            )
+        };
+
+        storage::Session {
+            volume: self.volume,
+            shuffle: self.shuffle,
+            repeat: self.repeat,
+            folder,
+            current,
+            position: if self.state == PlaybackState::Stopped {
+                Duration::ZERO
+            } else {
+                self.audio.position()
+            },
+            queue_pos,
+            queue_base,
+            queue,
+            recent: self.recent_paths.clone(),
+        }
+    }
+
+    fn restore_session(&mut self, session: storage::Session) {
+        if self.state != PlaybackState::Stopped {
+            return;
+        }
+
+        let track = if session.folder {
+            self.browser_queue_base = session.queue_base;
+            self.browser_queue = session.queue;
+            let current = session.current.filter(|path| path.is_file());
+            self.browser_queue_pos = session
+                .queue_pos
+                .filter(|position| self.browser_queue.get(*position) == current.as_ref())
+                .or_else(|| {
+                    let current = current.as_ref()?;
+                    self.browser_queue.iter().position(|path| path == current)
+                });
+            current.map(Track::from_path)
+        } else {
+            let resolve = |paths: &[PathBuf]| -> Vec<usize> {
+                paths
+                    .iter()
+                    .filter_map(|path| find_track_index(&self.tracks, &self.path_order, path))
+                    .collect()
+            };
+            self.queue_base = resolve(&session.queue_base);
+            self.queue = resolve(&session.queue);
+            if self.queue.len() != self.queue_base.len() {
+                self.rebuild_queue_order();
+            }
+            self.current = session
+                .current
+                .as_deref()
+                .and_then(|path| find_track_index(&self.tracks, &self.path_order, path));
+            self.queue_pos = self.current.and_then(|current| {
+                session
+                    .queue_pos
+                    .filter(|position| self.queue.get(*position) == Some(&current))
+                    .or_else(|| self.queue.iter().position(|index| *index == current))
+            });
+            self.current.map(|index| self.tracks[index].clone())
+        };
+
+        let Some(track) = track else {
+            return;
+        };
+        match self.audio.cue_file(&track, session.position) {
+            Ok(()) => {
+                self.state = PlaybackState::Paused;
+                self.status = format!("Restored session: {}, paused", track.title);
+            }
+            Err(error) => self.status = error.to_string(),
+        }
+        if session.folder {
+            self.browser_current = Some(track);
+        }
+    }
+
+    fn upcoming_track(&self) -> Option<Upcoming> {
+        if self.browser_current.is_some() {
+            let current = self.browser_queue_pos?;
+            let position = if self.repeat == RepeatMode::One {
+                current
+            } else if current + 1 < self.browser_queue.len() {
+                current + 1
+            } else if self.repeat == RepeatMode::All {
+                0
+            } else {
+                return None;
+            };
+            let path = self.browser_queue.get(position)?.clone();
+            return Some(Upcoming::Browser { position, path });
+        }
+
+        let current = self.current?;
+        if self.repeat == RepeatMode::One {
+            return Some(Upcoming::Track {
+                position: self.queue_pos,
+                track: current,
+            });
+        }
+        let current_pos = self.current_queue_position()?;
+        let position = if current_pos + 1 < self.queue.len() {
+            current_pos + 1
+        } else if self.repeat == RepeatMode::All {
+            0
+        } else {
+            return None;
+        };
+        Some(Upcoming::Track {
+            position: Some(position),
+            track: *self.queue.get(position)?,
+        })
+    }
+
+    fn sync_preload(&mut self) {
+        let upcoming = self.upcoming_track();
+        if upcoming == self.preloaded
+            || (upcoming.is_some() && upcoming == self.preload_failed)
+            || !self.audio.clear_next()
+        {
+            return;
+        }
+        self.preloaded = None;
+        self.preload_failed = None;
+
+        let next = match &upcoming {
+            Some(Upcoming::Track { track, .. }) => self.tracks.get(*track).cloned(),
+            Some(Upcoming::Browser { path, .. }) => Some(Track::from_path(path.clone())),
+            None => None,
+        };
+        let Some(next) = next else {
+            return;
+        };
+        // A missing or undecodable next song is left to the normal advance
+        // instead of being reopened on every tick.
+        let crossfade = self.crossfades_into(&next);
+        match self.audio.queue_next(&next, crossfade) {
+            Ok(()) => self.preloaded = upcoming,
+            Err(_) => self.preload_failed = upcoming,
+        }
+    }
+
+    fn crossfades_into(&self, next: &Track) -> bool {
+        self.settings.crossfade_seconds > 0.0
+            && self
+                .current_track()
+                .is_none_or(|current| album_key(current) != album_key(next))
+    }
+
+    fn finish_gapless_advance(&mut self) {
+        match self.preloaded.take() {
+            Some(Upcoming::Track { position, track }) => {
+                self.track_started(track);
+                if position.is_some() {
+                    self.queue_pos = position;
+                }
+            }
+            Some(Upcoming::Browser { position, path }) => {
+                self.browser_track_started(position, Track::from_path(path))
+            }
+            None => {}
+        }
+    }
+
     pub fn poll_interval(&self) -> Duration {
         if self.scan_rx.is_some() {
             Duration::from_millis(50)
         } else if self.state == PlaybackState::Playing {
             Duration::from_millis(100)
+        } else if self.remote_active() {
+            Duration::from_millis(250)
+        } else if self.watcher.is_some() {
+            Duration::from_secs(1)
         } else {
             Duration::from_secs(30)
         }
     }
 
+    #[cfg(unix)]
+    pub fn attach_events(&self) -> Vec<control::AttachEvent> {
+        self.control
+            .as_ref()
+            .map(ControlServer::attach_events)
+            .unwrap_or_default()
+    }
+
+    #[cfg(unix)]
+    pub fn control_active(&self) -> bool {
+        self.control.is_some()
+    }
+
+    pub fn quit_requested(&self) -> bool {
+        self.quit_requested
+    }
+
+    fn remote_active(&self) -> bool {
+        if self.mpd.is_some() {
+            return true;
+        }
+        #[cfg(unix)]
+        if self.control.is_some() {
+            return true;
+        }
+        #[cfg(feature = "mpris")]
+        if self.mpris.is_some() {
+            return true;
+        }
+        false
+    }
+
     pub fn progress_epoch(&self) -> u64 {
         if self.state == PlaybackState::Playing {
             (self.audio.position().as_millis() / 500) as u64
@@ -501,7 +1335,7 @@ impl App {
             }
             KeyCode::Char(character)
                 if !key.modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT)
-                    && input.value.chars().count() < 80 =>
+                    && input.value.chars().count() < input.kind.max_length() =>
             {
                 input.value.push(character);
                 self.text_input = Some(input);
@@ -518,19 +1352,19 @@ impl App {
         match key.code {
             KeyCode::Esc => {}
             KeyCode::Up | KeyCode::Char('k') => {
-                picker.selected = move_index(picker.selected, self.playlists.len(), -1);
+                picker.selected = move_index(picker.selected, self.stored_playlist_count(), -1);
                 self.playlist_picker = Some(picker);
             }
             KeyCode::Down | KeyCode::Char('j') => {
-                picker.selected = move_index(picker.selected, self.playlists.len(), 1);
+                picker.selected = move_index(picker.selected, self.stored_playlist_count(), 1);
                 self.playlist_picker = Some(picker);
             }
             KeyCode::PageUp => {
-                picker.selected = move_index(picker.selected, self.playlists.len(), -10);
+                picker.selected = move_index(picker.selected, self.stored_playlist_count(), -10);
                 self.playlist_picker = Some(picker);
             }
             KeyCode::PageDown => {
-                picker.selected = move_index(picker.selected, self.playlists.len(), 10);
+                picker.selected = move_index(picker.selected, self.stored_playlist_count(), 10);
                 self.playlist_picker = Some(picker);
             }
             KeyCode::Char('c') => self.open_create_playlist(picker.track_paths),
@@ -556,6 +1390,21 @@ impl App {
         });
     }
 
+    fn open_save_search(&mut self) {
+        match Query::parse(&self.search_query) {
+            Ok(query) if query.is_empty() => self.status = "Type a search first".to_owned(),
+            Ok(_) => {
+                self.text_input = Some(TextInput {
+                    prompt: "Save search as smart playlist".to_owned(),
+                    value: String::new(),
+                    kind: TextInputKind::SaveSearch,
+                    pending_paths: Vec::new(),
+                });
+            }
+            Err(error) => self.status = format!("Invalid search: {error}"),
+        }
+    }
+
     fn open_rename_playlist(&mut self) -> Result<()> {
         let Some(index) = self.selected_playlist_index() else {
             self.status = "Select a playlist first".to_owned();
@@ -564,6 +1413,10 @@ impl App {
         let Some(playlist) = self.playlists.get(index) else {
             return Ok(());
         };
+        if playlist.source.is_some() {
+            self.status = read_only_status(playlist);
+            return Ok(());
+        }
 
         self.text_input = Some(TextInput {
             prompt: "Rename playlist".to_owned(),
@@ -574,7 +1427,147 @@ impl App {
         Ok(())
     }
 
+    fn open_import_playlist(&mut self) {
+        let source = self
+            .selected_playlist_index()
+            .and_then(|index| self.playlists[index].source.as_ref());
+        let value = match source {
+            Some(source) => source.to_string_lossy().into_owned(),
+            None => {
+                let mut value = self.browser_dir.to_string_lossy().into_owned();
+                if !value.ends_with('/') {
+                    value.push('/');
+                }
+                value
+            }
+        };
+        self.text_input = Some(TextInput {
+            prompt: "Import playlist file (M3U, PLS, XSPF)".to_owned(),
+            value,
+            kind: TextInputKind::ImportPlaylistFile,
+            pending_paths: Vec::new(),
+        });
+    }
+
+    fn open_export_playlist(&mut self) -> Result<()> {
+        let Some(index) = self.selected_playlist_index() else {
+            self.status = "Select a playlist first".to_owned();
+            return Ok(());
+        };
+        let file_name: String = self.playlists[index]
+            .name
+            .chars()
+            .map(|character| if matches!(character, '/' | '\\' | '\0') { '_' } else { character })
+            .collect();
+        let path = self
+            .browser_dir
+            .join(format!("{file_name}.{}", PlaylistFormat::M3u.extension()));
+
+        self.text_input = Some(TextInput {
+            prompt: "Export playlist to (.m3u8, .pls, .xspf)".to_owned(),
+            value: path.to_string_lossy().into_owned(),
+            kind: TextInputKind::ExportPlaylistFile(index),
+            pending_paths: Vec::new(),
+        });
+        Ok(())
+    }
+
+    fn import_playlist_file(&mut self, path: &Path) -> Result<()> {
+        let entries = playlist_file::read_entries(path)?;
+        let indices = match_playlist_entries(&self.tracks, &self.path_order, &entries);
+        if indices.is_empty() {
+            return Err(io::Error::other(format!(
+                "none of the {} entries in {} are in the library",
+                entries.len(),
+                path.display()
+            )));
+        }
+
+        let stem = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().trim().to_owned())
+            .filter(|stem| !stem.is_empty())
+            .unwrap_or_else(|| "Imported".to_owned());
+        let name = unique_playlist_name(
+            self.playlists.iter().map(|playlist| playlist.name.as_str()),
+            &stem,
+        );
+        let track_paths = indices
+            .iter()
+            .map(|index| self.tracks[*index].path.clone())
+            .collect();
+        self.selected = self.insert_stored_playlist(Playlist {
+            name: name.clone(),
+            track_paths,
+            tracks: Vec::new(),
+            duration: Duration::ZERO,
+            source: None,
+            smart: None,
+            entries: Vec::new(),
+        })?;
+        self.view = View::Playlists;
+        self.detail = None;
+        self.status = if indices.len() == entries.len() {
+            format!("Imported playlist '{name}' with {} songs", indices.len())
+        } else {
+            format!(
+                "Imported playlist '{name}' with {} of {} songs",
+                indices.len(),
+                entries.len()
+            )
+        };
+        Ok(())
+    }
+
+    fn export_playlist_file(&mut self, index: usize, path: &Path) -> Result<()> {
+        let Some(playlist) = self.playlists.get(index) else {
+            return Ok(());
+        };
+        let format = PlaylistFormat::from_path(path).ok_or_else(|| {
+            io::Error::other("Playlist files must end in .m3u, .m3u8, .pls, or .xspf")
+        })?;
+
+        let entries = playlist.track_paths.iter().map(|track_path| {
+            let track = find_track_index(&self.tracks, &self.path_order, track_path)
+                .map(|track| &self.tracks[track]);
+            (track_path.as_path(), track)
+        });
+        let mut writer = BufWriter::new(File::create(path)?);
+        let skipped = playlist_file::write(&mut writer, format, &playlist.name, entries)?;
+        writer.flush()?;
+        self.status = format!(
+            "Exported '{}' to {}",
+            playlist.name,
+            path.display()
+        );
+        if skipped > 0 {
+            self.status.push_str(&format!(
+                "; skipped {skipped} CUE sheet songs other players cannot open"
+            ));
+        }
+        Ok(())
+    }
+
     fn commit_text_input(&mut self, input: TextInput) -> Result<()> {
+        match input.kind {
+            TextInputKind::ImportPlaylistFile | TextInputKind::ExportPlaylistFile(_) => {
+                let path = input.value.trim();
+                if path.is_empty() {
+                    self.status = "Enter a playlist file path".to_owned();
+                    self.text_input = Some(input);
+                    return Ok(());
+                }
+                let path = expand_home(path);
+                return match input.kind {
+                    TextInputKind::ExportPlaylistFile(index) => self.export_playlist_file(index, &path),
+                    _ => self.import_playlist_file(&path),
+                };
+            }
+            TextInputKind::CreatePlaylist
+            | TextInputKind::SaveSearch
+            | TextInputKind::RenamePlaylist(_) => {}
+        }
+
         let name = input.value.trim().to_owned();
         if name.is_empty() {
             self.status = "Playlist name cannot be empty".to_owned();
@@ -584,7 +1577,7 @@ impl App {
 
         let ignored = match input.kind {
             TextInputKind::RenamePlaylist(index) => Some(index),
-            TextInputKind::CreatePlaylist => None,
+            _ => None,
         };
         if self.playlists.iter().enumerate().any(|(index, playlist)| {
             Some(index) != ignored && playlist.name.eq_ignore_ascii_case(&name)
@@ -602,18 +1595,17 @@ impl App {
                         track_paths.push(path);
                     }
                 }
-                self.playlists.push(Playlist {
+                self.selected = self.insert_stored_playlist(Playlist {
                     name: name.clone(),
                     track_paths,
                     tracks: Vec::new(),
                     duration: Duration::ZERO,
-                });
-                self.rebuild_playlist_indexes();
-                self.save_playlists()?;
-                self.rebuild_search();
+                    source: None,
+                    smart: None,
+                    entries: Vec::new(),
+                })?;
                 self.view = View::Playlists;
                 self.detail = None;
-                self.selected = self.playlists.len().saturating_sub(1);
                 let count = self.playlists[self.selected].track_paths.len();
                 self.status = if count == 0 {
                     format!("Created playlist '{name}'")
@@ -621,6 +1613,27 @@ impl App {
                     format!("Created playlist '{name}' with {count} songs")
                 };
             }
+            TextInputKind::SaveSearch => {
+                let query = Query::parse(&self.search_query).map_err(io::Error::other)?;
+                self.selected = self.insert_stored_playlist(Playlist {
+                    name: name.clone(),
+                    track_paths: Vec::new(),
+                    tracks: Vec::new(),
+                    duration: Duration::ZERO,
+                    source: None,
+                    smart: Some(SmartPlaylist {
+                        rules: vec![Rule::Query(query)],
+                        ..SmartPlaylist::default()
+                    }),
+                    entries: Vec::new(),
+                })?;
+                self.view = View::Playlists;
+                self.detail = None;
+                self.status = format!(
+                    "Saved search as smart playlist '{name}' with {} songs",
+                    self.playlists[self.selected].tracks.len()
+                );
+            }
             TextInputKind::RenamePlaylist(index) => {
                 if let Some(playlist) = self.playlists.get_mut(index) {
                     playlist.name = name.clone();
@@ -631,6 +1644,7 @@ impl App {
                 self.rebuild_search();
                 self.status = format!("Renamed playlist to '{name}'");
             }
+            TextInputKind::ImportPlaylistFile | TextInputKind::ExportPlaylistFile(_) => {}
         }
 
         Ok(())
@@ -655,7 +1669,7 @@ impl App {
             return Ok(());
         }
 
-        if self.playlists.is_empty() {
+        if self.stored_playlist_count() == 0 {
             self.open_create_playlist(track_paths);
         } else {
             self.playlist_picker = Some(PlaylistPicker {
@@ -670,8 +1684,8 @@ impl App {
     fn selected_tracks_for_playlist(&self) -> Option<(Vec<usize>, String)> {
         match self.view {
             View::Home => {
-                let recent = self.recent_indices();
-                let track = recent.get(self.selected).copied()?;
+                let rows = self.home_indices();
+                let track = rows.get(self.selected).copied()?;
                 Some((vec![track], self.tracks.get(track)?.title.to_string()))
             }
             View::Search => match *self.search_results.get(self.selected)? {
@@ -687,6 +1701,14 @@ impl App {
                     let album = self.albums.get(index)?;
                     Some((album.tracks.clone(), album.title.to_string()))
                 }
+                SearchItem::Genre(index) => {
+                    let genre = self.genres.get(index)?;
+                    Some((genre.tracks.clone(), genre.name.to_string()))
+                }
+                SearchItem::Year(index) => {
+                    let year = self.years.get(index)?;
+                    Some((year.tracks.clone(), year.name.to_string()))
+                }
                 SearchItem::Track(index) => {
                     Some((vec![index], self.tracks.get(index)?.title.to_string()))
                 }
@@ -721,6 +1743,19 @@ impl App {
                     Some((artist.tracks.clone(), artist.name.to_string()))
                 }
             }
+            View::Genres | View::Years => {
+                let category_index = match self.detail {
+                    Some(DetailView::Genre(index) | DetailView::Year(index)) => index,
+                    _ => self.selected,
+                };
+                let category = self.categories().get(category_index)?;
+                if self.detail.is_some() {
+                    let track = *category.tracks.get(self.selected)?;
+                    Some((vec![track], self.tracks.get(track)?.title.to_string()))
+                } else {
+                    Some((category.tracks.clone(), category.name.to_string()))
+                }
+            }
             View::Playlists => {
                 let playlist_index = self.selected_playlist_index()?;
                 let playlist = self.playlists.get(playlist_index)?;
@@ -731,6 +1766,13 @@ impl App {
                     Some((playlist.tracks.clone(), playlist.name.clone()))
                 }
             }
+            View::Queue => {
+                if self.browser_current.is_some() {
+                    return None;
+                }
+                let track = *self.queue.get(self.selected)?;
+                Some((vec![track], self.tracks.get(track)?.title.to_string()))
+            }
             View::Folders => None,
         }
     }
@@ -740,6 +1782,10 @@ impl App {
             let Some(playlist) = self.playlists.get_mut(index) else {
                 return Ok(());
             };
+            if playlist.source.is_some() || playlist.smart.is_some() {
+                self.status = read_only_status(playlist);
+                return Ok(());
+            }
             let name = playlist.name.clone();
             let mut added = 0;
             for path in paths {
@@ -766,6 +1812,14 @@ impl App {
             self.status = "Open a playlist to remove one of its songs".to_owned();
             return Ok(());
         };
+        if let Some(playlist) = self
+            .playlists
+            .get(playlist_index)
+            .filter(|playlist| playlist.source.is_some() || playlist.smart.is_some())
+        {
+            self.status = read_only_status(playlist);
+            return Ok(());
+        }
         let Some(track_index) = self
             .playlists
             .get(playlist_index)
@@ -792,6 +1846,10 @@ impl App {
         let Some(index) = self.selected_playlist_index() else {
             return Ok(());
         };
+        if self.playlists[index].source.is_some() {
+            self.status = read_only_status(&self.playlists[index]);
+            return Ok(());
+        }
         let name = self.playlists[index].name.clone();
         self.playlists.remove(index);
         self.detail = None;
@@ -815,15 +1873,102 @@ impl App {
         storage::save_playlists(
             self.playlists
                 .iter()
-                .map(|playlist| (playlist.name.as_str(), playlist.track_paths.as_slice())),
+                .filter(|playlist| playlist.source.is_none())
+                .map(|playlist| {
+                    (
+                        playlist.name.as_str(),
+                        playlist.track_paths.as_slice(),
+                        playlist.smart.as_ref(),
+                    )
+                }),
         )
     }
 
+    pub fn stored_playlist_count(&self) -> usize {
+        self.playlists
+            .iter()
+            .position(|playlist| playlist.source.is_some())
+            .unwrap_or(self.playlists.len())
+    }
+
+    fn insert_stored_playlist(&mut self, playlist: Playlist) -> Result<usize> {
+        let index = self.stored_playlist_count();
+        self.playlists.insert(index, playlist);
+        if let Some(DetailView::Playlist(detail)) = &mut self.detail {
+            if *detail >= index {
+                *detail += 1;
+            }
+        }
+        self.rebuild_playlist_indexes();
+        self.save_playlists()?;
+        self.rebuild_search();
+        Ok(index)
+    }
+
+    fn set_playlist_files(&mut self, files: Vec<PlaylistFile>) {
+        let stored = self.stored_playlist_count();
+        let detail_source = match self.detail {
+            Some(DetailView::Playlist(index)) if index >= stored => self.playlists[index].source.clone(),
+            _ => None,
+        };
+
+        self.playlists.truncate(stored);
+        for file in files {
+            let stem = file
+                .path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().trim().to_owned())
+                .filter(|stem| !stem.is_empty())
+                .unwrap_or_else(|| file.path.to_string_lossy().into_owned());
+            let name = unique_playlist_name(self.playlists.iter().map(|playlist| playlist.name.as_str()), &stem);
+            self.playlists.push(Playlist {
+                name,
+                track_paths: Vec::new(),
+                tracks: Vec::new(),
+                duration: Duration::ZERO,
+                source: Some(file.path),
+                smart: None,
+                entries: file.entries,
+            });
+        }
+
+        if let Some(source) = detail_source {
+            match self.playlists.iter().position(|playlist| playlist.source.as_ref() == Some(&source)) {
+                Some(index) => self.detail = Some(DetailView::Playlist(index)),
+                None => {
+                    self.detail = None;
+                    self.selected = 0;
+                }
+            }
+        }
+        if self.view == View::Playlists {
+            self.selected = self.selected.min(self.selection_len().saturating_sub(1));
+        }
+        self.rebuild_playlist_indexes();
+        self.rebuild_search();
+    }
+
     fn rebuild_playlist_indexes(&mut self) {
         let tracks = &self.tracks;
         let path_order = &self.path_order;
+        let history = &self.history;
 
         for playlist in &mut self.playlists {
+            if let Some(smart) = &playlist.smart {
+                playlist.tracks = smart.select(tracks, |path| history.stats(path));
+                playlist.track_paths = playlist.tracks.iter().map(|index| tracks[*index].path.clone()).collect();
+                playlist.duration = playlist.tracks.iter().fold(Duration::ZERO, |total, index| {
+                    total.saturating_add(tracks[*index].duration.unwrap_or_default())
+                });
+                continue;
+            }
+            if playlist.source.is_some() {
+                playlist.track_paths = resolve_playlist_entries(tracks, path_order, &playlist.entries)
+                    .into_iter()
+                    .zip(&playlist.entries)
+                    .map(|(index, entry)| index.map_or_else(|| entry.path.clone(), |index| tracks[index].path.clone()))
+                    .collect();
+            }
             playlist.tracks.clear();
             playlist.tracks.reserve(playlist.track_paths.len());
             for path in &playlist.track_paths {
@@ -864,7 +2009,7 @@ impl App {
                 if !key.modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) =>
             {
                 self.search_query.push(character);
-                self.rebuild_search();
+                self.refine_search();
             }
             _ => {}
         }
@@ -873,7 +2018,11 @@ impl App {
     fn set_view(&mut self, view: View) {
         self.view = view;
         self.detail = None;
-        self.selected = 0;
+        self.selected = if view == View::Queue {
+            self.active_queue_position().unwrap_or(0)
+        } else {
+            0
+        };
         if view != View::Search {
             self.search_editing = false;
         }
@@ -910,17 +2059,31 @@ impl App {
 
         loop {
             match receiver.try_recv() {
+                Ok(ScanEvent::Cached(tracks)) => {
+                    if self.tracks.is_empty() && !tracks.is_empty() {
+                        changed = true;
+                        self.apply_scan_result(tracks);
+                        self.status = format!(
+                            "Loaded {} cached songs, checking for changes...",
+                            self.tracks.len()
+                        );
+                    }
+                }
                 Ok(ScanEvent::Progress { done, total }) => {
                     changed = true;
                     self.scan_phase = ScanPhase::Reading { done, total };
                     self.status = format!("Reading metadata: {done}/{total}");
                 }
+                Ok(ScanEvent::PlaylistFiles(files)) => {
+                    changed = true;
+                    self.set_playlist_files(files);
+                }
                 Ok(ScanEvent::Finished(result)) => {
                     changed = true;
                     keep_receiver = false;
                     self.scan_phase = ScanPhase::Idle;
                     match result {
-                        Ok(tracks) if !self.roots.is_empty() => self.apply_scan_result(tracks),
+                        Ok(diff) if !self.roots.is_empty() => self.apply_library_diff(diff),
                         Ok(_) => self.status = "All library folders removed".to_owned(),
                         Err(error) => self.status = format!("Library scan failed: {error}"),
                     }
@@ -943,6 +2106,9 @@ impl App {
                 self.scan_phase = ScanPhase::Idle;
                 self.status = "Library scan stopped unexpectedly".to_owned();
             }
+            if let Some(session) = self.pending_session.take() {
+                self.restore_session(session);
+            }
             if self.rescan_pending {
                 self.rescan_pending = false;
                 self.begin_scan();
@@ -965,7 +2131,63 @@ impl App {
 
         self.scan_phase = ScanPhase::Discovering;
         self.status = "Discovering audio files...".to_owned();
-        self.scan_rx = Some(library::spawn_scan(self.roots.clone()));
+        let known = self.tracks.iter().map(|track| track.path.clone()).collect();
+        self.scan_rx = Some(library::spawn_scan(
+            self.roots.clone(),
+            known,
+            ScanScope::Full,
+            self.settings.scan_threads,
+        ));
+    }
+
+    fn restart_watcher(&mut self) {
+        self.watcher = None;
+        self.watch_pending.clear();
+        self.watch_deadline = None;
+        if self.roots.is_empty() {
+            return;
+        }
+        match LibraryWatcher::new(&self.roots) {
+            Ok(watcher) => self.watcher = Some(watcher),
+            Err(error) => self.status = format!("Library folders are not watched: {error}"),
+        }
+    }
+
+    fn poll_watcher(&mut self) -> bool {
+        let Some(watcher) = &self.watcher else {
+            return false;
+        };
+        if watcher.drain_into(&mut self.watch_pending) {
+            self.watch_deadline = Some(Instant::now() + Duration::from_secs(1));
+        }
+
+        let due = self
+            .watch_deadline
+            .is_some_and(|deadline| Instant::now() >= deadline);
+        if !due || self.scan_rx.is_some() || self.watch_pending.is_empty() {
+            return false;
+        }
+
+        let paths = mem::take(&mut self.watch_pending).into_iter().collect();
+        self.watch_deadline = None;
+        self.scan_phase = ScanPhase::Discovering;
+        self.status = "Updating changed library files...".to_owned();
+        let known = self.tracks.iter().map(|track| track.path.clone()).collect();
+        let playlists = self
+            .playlists
+            .iter()
+            .filter_map(|playlist| playlist.source.clone())
+            .collect();
+        self.scan_rx = Some(library::spawn_scan(
+            self.roots.clone(),
+            known,
+            ScanScope::Paths {
+                changed: paths,
+                playlists,
+            },
+            self.settings.scan_threads,
+        ));
+        true
     }
 
     fn apply_scan_result(&mut self, tracks: Vec<Track>) {
@@ -978,6 +2200,7 @@ impl App {
             .collect();
 
         self.tracks = tracks;
+        self.library_generation += 1;
         self.rebuild_indexes();
 
         let restored_current = old_current_path
@@ -1010,167 +2233,448 @@ impl App {
 
         self.selected = 0;
         self.root_selected = self.root_selected.min(self.roots.len().saturating_sub(1));
-        self.status = format!(
-            "Library ready: {} songs, {} albums, {} artists",
-            self.tracks.len(),
-            self.albums.len(),
-            self.artists.len()
-        );
+        self.set_library_status();
+    }
+
+    #[cfg(test)]
+    pub(crate) fn load_library(&mut self, tracks: Vec<Track>) {
+        self.apply_scan_result(tracks);
+    }
+
+    fn set_library_status(&mut self) {
+        self.status = if self.tracks.is_empty() {
+            "No supported audio files found in the selected folders".to_owned()
+        } else {
+            format!(
+                "Library ready: {} songs, {} albums, {} artists",
+                self.tracks.len(),
+                self.albums.len(),
+                self.artists.len()
+            )
+        };
+    }
 
+    fn apply_library_diff(&mut self, diff: LibraryDiff) {
         if self.tracks.is_empty() {
-            self.status = "No supported audio files found in the selected folders".to_owned();
+            self.apply_scan_result(diff.added);
+            return;
+        }
+        if diff.is_empty() {
+            self.set_library_status();
+            return;
         }
-    }
 
-    fn rebuild_indexes(&mut self) {
-        self.path_order.clear();
-        self.path_order.extend(0..self.tracks.len());
-        let tracks = &self.tracks;
-        self.path_order.sort_unstable_by(|left, right| {
-            tracks[*left].path.cmp(&tracks[*right].path)
-        });
+        let detail_key = self.detail.and_then(|detail| self.detail_key(detail));
+        let selection_key = self.row_key(self.selected);
+        let old_current = self.current;
+
+        let removed: HashSet<PathBuf> = diff.removed.into_iter().collect();
+        let changed: HashSet<PathBuf> = diff.changed.iter().map(|track| track.path.clone()).collect();
+        let mut incoming = diff.added;
+        incoming.extend(diff.changed);
+        incoming.sort_by(library::compare_tracks);
+
+        let old_tracks = mem::take(&mut self.tracks);
+        let mut remap = vec![None; old_tracks.len()];
+        let mut replaced = Vec::new();
+        let mut incoming_indices = Vec::with_capacity(incoming.len());
+        let mut incoming = incoming.into_iter().peekable();
+        let mut tracks = Vec::with_capacity(old_tracks.len() + incoming.len());
+        for (old_index, track) in old_tracks.into_iter().enumerate() {
+            if track.path.ancestors().any(|path| removed.contains(path)) {
+                continue;
+            }
+            if changed.contains(&track.path) {
+                replaced.push((old_index, track.path));
+                continue;
+            }
+            while let Some(next) =
+                incoming.next_if(|next| library::compare_tracks(next, &track).is_lt())
+            {
+                incoming_indices.push(tracks.len());
+                tracks.push(next);
+            }
+            remap[old_index] = Some(tracks.len());
+            tracks.push(track);
+        }
+        for next in incoming {
+            incoming_indices.push(tracks.len());
+            tracks.push(next);
+        }
+        self.tracks = tracks;
+        self.library_generation += 1;
+        self.rebuild_path_order();
 
-        let mut album_map: BTreeMap<String, Vec<usize>> = BTreeMap::new();
-        let mut artist_map: BTreeMap<String, Vec<usize>> = BTreeMap::new();
-
-        for (index, track) in self.tracks.iter().enumerate() {
-            let mut album_key = track.album_dir.to_string_lossy().to_lowercase();
-            album_key.push('\0');
-            album_key.push_str(&track.album.to_lowercase());
-            album_map.entry(album_key).or_default().push(index);
-            artist_map
-                .entry(track.artist.to_lowercase())
-                .or_default()
-                .push(index);
-        }
-
-        let mut albums: Vec<Album> = album_map
-            .into_values()
-            .map(|mut tracks| {
-                tracks.sort_by(|left, right| {
-                    compare_album_tracks(&self.tracks[*left], &self.tracks[*right])
-                });
-                let first = &self.tracks[tracks[0]];
-                let mut album_artist: Option<Arc<str>> = None;
-                let mut various_artists = false;
-                for index in &tracks {
-                    let candidate = &self.tracks[*index].album_artist;
-                    if candidate.trim().is_empty() {
-                        continue;
-                    }
-                    match &album_artist {
-                        None => album_artist = Some(Arc::clone(candidate)),
-                        Some(existing) if existing.as_ref() == candidate.as_ref() => {}
-                        Some(_) => {
-                            various_artists = true;
-                            break;
-                        }
-                    }
-                }
-                let artist = if various_artists {
-                    Arc::from("Various Artists")
-                } else {
-                    album_artist.unwrap_or_else(|| Arc::clone(&first.artist))
-                };
-                let duration = tracks.iter().fold(Duration::ZERO, |total, index| {
-                    total.saturating_add(self.tracks[*index].duration.unwrap_or_default())
-                });
-                Album {
-                    title: Arc::clone(&first.album),
-                    artist,
-                    tracks,
-                    duration,
-                }
-            })
+        let mut search_text: Vec<Option<SearchText>> = vec![None; self.tracks.len()];
+        for (text, next) in mem::take(&mut self.search_text).into_iter().zip(&remap) {
+            if let Some(next) = next {
+                search_text[*next] = Some(text);
+            }
+        }
+        self.search_text = search_text
+            .into_iter()
+            .zip(&self.tracks)
+            .map(|(text, track)| text.unwrap_or_else(|| SearchText::new(track)))
             .collect();
 
-        albums.sort_by_cached_key(|album| {
-            (album.artist.to_lowercase(), album.title.to_lowercase())
-        });
+        let albums_changed = patch_groups(
+            &mut self.albums,
+            &self.tracks,
+            &remap,
+            &incoming_indices,
+            |album| &mut album.tracks,
+            |album| album_key(&self.tracks[album.tracks[0]]),
+            |track| vec![album_key(track)],
+            |tracks, members, _| build_album(tracks, members),
+        );
+        if albums_changed {
+            sort_albums(&mut self.albums);
+        }
+        let artists_changed = patch_groups(
+            &mut self.artists,
+            &self.tracks,
+            &remap,
+            &incoming_indices,
+            |artist| &mut artist.tracks,
+            |artist| artist.name.to_lowercase(),
+            |track| self.artist_credits.keys(track),
+            |tracks, members, key| build_artist(tracks, members, &self.artist_credits, key),
+        );
+        if artists_changed {
+            sort_artists(&mut self.artists);
+        }
+        let genres_changed = patch_groups(
+            &mut self.genres,
+            &self.tracks,
+            &remap,
+            &incoming_indices,
+            |genre| &mut genre.tracks,
+            |genre| genre_key(&self.tracks[genre.tracks[0]]),
+            |track| vec![genre_key(track)],
+            |tracks, members, _| build_category(tracks, members, genre_name),
+        );
+        if genres_changed {
+            sort_categories(&mut self.genres, &self.tracks, genre_key);
+        }
+        let grouping = self.year_grouping;
+        let years_changed = patch_groups(
+            &mut self.years,
+            &self.tracks,
+            &remap,
+            &incoming_indices,
+            |year| &mut year.tracks,
+            |year| grouping.key(&self.tracks[year.tracks[0]]),
+            |track| vec![grouping.key(track)],
+            |tracks, members, _| build_category(tracks, members, |track| grouping.name(track)),
+        );
+        if years_changed {
+            sort_categories(&mut self.years, &self.tracks, |track| grouping.key(track));
+        }
+        self.rebuild_playlist_indexes();
 
-        let mut artists: Vec<Artist> = artist_map
-            .into_values()
-            .map(|mut tracks| {
-                tracks.sort_by(|left, right| {
-                    compare_artist_tracks(&self.tracks[*left], &self.tracks[*right])
-                });
-                let name = Arc::clone(&self.tracks[tracks[0]].artist);
-                let album_count = tracks
-                    .iter()
-                    .map(|index| {
-                        (
-                            self.tracks[*index].album_dir.as_ref(),
-                            self.tracks[*index].album.as_ref(),
-                        )
-                    })
-                    .collect::<BTreeSet<_>>()
-                    .len();
-                Artist {
-                    name,
-                    tracks,
-                    album_count,
+        let mut resolved = remap;
+        for (old_index, path) in replaced {
+            resolved[old_index] = find_track_index(&self.tracks, &self.path_order, &path);
+        }
+        self.current = old_current.and_then(|index| resolved[index]);
+        self.queue_base.retain_mut(|index| match resolved[*index] {
+            Some(next) => {
+                *index = next;
+                true
+            }
+            None => false,
+        });
+        self.queue.retain_mut(|index| match resolved[*index] {
+            Some(next) => {
+                *index = next;
+                true
+            }
+            None => false,
+        });
+        self.queue_pos = self
+            .current
+            .and_then(|current| self.queue.iter().position(|index| *index == current));
+        self.preload_failed = None;
+        if let Some(Upcoming::Track { track, .. }) = self.preloaded {
+            self.preloaded = match (resolved[track], self.upcoming_track()) {
+                (Some(next), Some(upcoming @ Upcoming::Track { track, .. })) if track == next => {
+                    Some(upcoming)
                 }
+                (Some(next), _) => Some(Upcoming::Track {
+                    position: None,
+                    track: next,
+                }),
+                (None, _) => None,
+            };
+        }
+        self.refresh_recent_indices();
+        if old_current.is_some() && self.current.is_none() {
+            self.stop();
+        }
+
+        self.rebuild_search();
+        self.detail = match detail_key {
+            Some(key) => self.detail_for_key(&key),
+            None => None,
+        };
+        self.selected = selection_key
+            .and_then(|key| {
+                (0..self.selection_len())
+                    .find(|position| self.row_key(*position).as_ref() == Some(&key))
             })
-            .collect();
+            .unwrap_or(0);
+        self.set_library_status();
+    }
+
+    fn detail_key(&self, detail: DetailView) -> Option<SelectionKey> {
+        match detail {
+            DetailView::Album(index) => self.albums.get(index).map(|album| {
+                SelectionKey::Album(album_key(&self.tracks[album.tracks[0]]))
+            }),
+            DetailView::Artist(index) => self
+                .artists
+                .get(index)
+                .map(|artist| SelectionKey::Artist(artist.name.to_lowercase())),
+            DetailView::Genre(index) => self
+                .genres
+                .get(index)
+                .map(|genre| SelectionKey::Genre(genre_key(&self.tracks[genre.tracks[0]]))),
+            DetailView::Year(index) => self.years.get(index).map(|year| {
+                SelectionKey::Year(self.year_grouping.key(&self.tracks[year.tracks[0]]))
+            }),
+            DetailView::Playlist(index) => Some(SelectionKey::Playlist(index)),
+        }
+    }
+
+    fn detail_for_key(&self, key: &SelectionKey) -> Option<DetailView> {
+        match key {
+            SelectionKey::Album(key) => self
+                .albums
+                .iter()
+                .position(|album| album_key(&self.tracks[album.tracks[0]]) == *key)
+                .map(DetailView::Album),
+            SelectionKey::Artist(key) => self
+                .artists
+                .iter()
+                .position(|artist| artist.name.to_lowercase() == *key)
+                .map(DetailView::Artist),
+            SelectionKey::Genre(key) => self
+                .genres
+                .iter()
+                .position(|genre| genre_key(&self.tracks[genre.tracks[0]]) == *key)
+                .map(DetailView::Genre),
+            SelectionKey::Year(key) => self
+                .years
+                .iter()
+                .position(|year| self.year_grouping.key(&self.tracks[year.tracks[0]]) == *key)
+                .map(DetailView::Year),
+            SelectionKey::Playlist(index) => Some(DetailView::Playlist(*index)),
+            SelectionKey::Track(_) => None,
+        }
+    }
+
+    fn row_key(&self, position: usize) -> Option<SelectionKey> {
+        let track_key = |index: usize| {
+            self.tracks
+                .get(index)
+                .map(|track| SelectionKey::Track(track.path.clone()))
+        };
+        match (self.view, self.detail) {
+            (View::Home, _) => track_key(*self.home_indices().get(position)?),
+            (View::Search, _) => match *self.search_results.get(position)? {
+                SearchItem::Playlist(index) => Some(SelectionKey::Playlist(index)),
+                SearchItem::Artist(index) => self.detail_key(DetailView::Artist(index)),
+                SearchItem::Album(index) => self.detail_key(DetailView::Album(index)),
+                SearchItem::Genre(index) => self.detail_key(DetailView::Genre(index)),
+                SearchItem::Year(index) => self.detail_key(DetailView::Year(index)),
+                SearchItem::Track(index) => track_key(index),
+            },
+            (View::Songs, _) => track_key(position),
+            (View::Albums, Some(DetailView::Album(index))) => {
+                track_key(*self.albums.get(index)?.tracks.get(position)?)
+            }
+            (View::Albums, _) => self.detail_key(DetailView::Album(position)),
+            (View::Artists, Some(DetailView::Artist(index))) => {
+                track_key(*self.artists.get(index)?.tracks.get(position)?)
+            }
+            (View::Artists, _) => self.detail_key(DetailView::Artist(position)),
+            (View::Genres | View::Years, Some(DetailView::Genre(index) | DetailView::Year(index))) => {
+                track_key(*self.categories().get(index)?.tracks.get(position)?)
+            }
+            (View::Genres, _) => self.detail_key(DetailView::Genre(position)),
+            (View::Years, _) => self.detail_key(DetailView::Year(position)),
+            (View::Playlists, Some(DetailView::Playlist(index))) => {
+                track_key(*self.playlists.get(index)?.tracks.get(position)?)
+            }
+            (View::Playlists, _) => self
+                .playlists
+                .get(position)
+                .map(|_| SelectionKey::Playlist(position)),
+            (View::Queue, _) if self.browser_current.is_none() => {
+                track_key(*self.queue.get(position)?)
+            }
+            (View::Folders | View::Queue, _) => None,
+        }
+    }
+
+    fn rebuild_indexes(&mut self) {
+        self.rebuild_path_order();
+        self.search_text = self.tracks.iter().map(SearchText::new).collect();
 
-        artists.sort_by_cached_key(|artist| artist.name.to_lowercase());
+        let (albums, artists) = group_library(&self.tracks, &self.artist_credits);
         self.albums = albums;
         self.artists = artists;
+        self.genres = group_categories(&self.tracks, genre_key, genre_name);
+        let grouping = self.year_grouping;
+        self.years = group_categories(
+            &self.tracks,
+            |track| grouping.key(track),
+            |track| grouping.name(track),
+        );
         self.rebuild_playlist_indexes();
         self.rebuild_search();
     }
 
-    fn rebuild_search(&mut self) {
-        self.search_results.clear();
+    pub fn categories(&self) -> &[Category] {
+        match self.view {
+            View::Years => &self.years,
+            _ => &self.genres,
+        }
+    }
+
+    fn toggle_album_artists(&mut self) {
+        self.artist_credits.album_artists = !self.artist_credits.album_artists;
+        self.artists = group_library(&self.tracks, &self.artist_credits).1;
+        self.detail = None;
         self.selected = 0;
+        self.status = if self.artist_credits.album_artists {
+            "Browsing album artists".to_owned()
+        } else {
+            "Browsing track artists".to_owned()
+        };
+        self.rebuild_search();
+    }
 
-        let tokens: Vec<String> = self
-            .search_query
-            .split_whitespace()
-            .map(str::to_lowercase)
-            .collect();
-        if tokens.is_empty() {
-            return;
-        }
+    fn toggle_year_grouping(&mut self) {
+        self.year_grouping = match self.year_grouping {
+            YearGrouping::Year => YearGrouping::Decade,
+            YearGrouping::Decade => YearGrouping::Year,
+        };
+        let grouping = self.year_grouping;
+        self.years = group_categories(
+            &self.tracks,
+            |track| grouping.key(track),
+            |track| grouping.name(track),
+        );
+        self.detail = None;
+        self.selected = 0;
+        self.rebuild_search();
+    }
 
-        for (index, playlist) in self.playlists.iter().enumerate() {
-            if matches_fields(&[playlist.name.as_str()], &tokens) {
-                self.search_results.push(SearchItem::Playlist(index));
-            }
-        }
-        for (index, artist) in self.artists.iter().enumerate() {
-            if matches_fields(&[artist.name.as_ref()], &tokens) {
-                self.search_results.push(SearchItem::Artist(index));
+    fn rebuild_path_order(&mut self) {
+        self.path_order.clear();
+        self.path_order.extend(0..self.tracks.len());
+        let tracks = &self.tracks;
+        self.path_order.sort_unstable_by(|left, right| {
+            tracks[*left].path.cmp(&tracks[*right].path)
+        });
+    }
+
+    fn rebuild_search(&mut self) {
+        self.search_base = None;
+        self.refine_search();
+    }
+
+    /// Ranks the search results for the current query. When the last exact
+    /// search already covers it, such as after typing one more letter, only
+    /// its results are rescored instead of the whole library.
+    fn refine_search(&mut self) {
+        let base = self.search_base.take();
+        let previous = mem::take(&mut self.search_results);
+        self.selected = 0;
+
+        let query = match Query::parse(&self.search_query) {
+            Ok(query) => query,
+            Err(error) => {
+                self.status = format!("Invalid search: {error}");
+                return;
             }
+        };
+        if self.status.starts_with("Invalid search: ") {
+            self.status.clear();
         }
-        for (index, album) in self.albums.iter().enumerate() {
-            if matches_fields(&[album.title.as_ref(), album.artist.as_ref()], &tokens) {
-                self.search_results.push(SearchItem::Album(index));
-            }
+        if query.is_empty() {
+            return;
         }
-        for (index, track) in self.tracks.iter().enumerate() {
-            let path = track.path.to_string_lossy();
-            if matches_fields(
-                &[
-                    track.title.as_ref(),
-                    track.artist.as_ref(),
-                    track.album_artist.as_ref(),
-                    track.album.as_ref(),
-                    path.as_ref(),
-                ],
-                &tokens,
-            ) {
-                self.search_results.push(SearchItem::Track(index));
-            }
+
+        let everything = || {
+            (0..self.playlists.len())
+                .map(SearchItem::Playlist)
+                .chain((0..self.artists.len()).map(SearchItem::Artist))
+                .chain((0..self.albums.len()).map(SearchItem::Album))
+                .chain((0..self.genres.len()).map(SearchItem::Genre))
+                .chain((0..self.years.len()).map(SearchItem::Year))
+                .chain((0..self.tracks.len()).map(SearchItem::Track))
+        };
+        let results = if base.is_some_and(|base| query.narrows(&base)) {
+            self.rank_search(&query, previous, false)
+        } else {
+            self.rank_search(&query, everything(), false)
+        };
+        // Typo-tolerant matching only runs when nothing matches as typed.
+        if results.is_empty() {
+            self.search_results = self.rank_search(&query, everything(), true);
+        } else {
+            self.search_results = results;
+            self.search_base = Some(query);
         }
     }
 
+    fn rank_search(
+        &self,
+        query: &Query,
+        items: impl IntoIterator<Item = SearchItem>,
+        fuzzy: bool,
+    ) -> Vec<SearchItem> {
+        let mut ranked: Vec<_> = items
+            .into_iter()
+            .filter_map(|item| {
+                let score = match item {
+                    SearchItem::Playlist(index) => {
+                        query.score_text(&[self.playlists[index].name.as_str()], fuzzy)
+                    }
+                    SearchItem::Artist(index) => {
+                        query.score_text(&[self.artists[index].name.as_ref()], fuzzy)
+                    }
+                    SearchItem::Album(index) => {
+                        let album = &self.albums[index];
+                        query.score_text(&[album.title.as_ref(), album.artist.as_ref()], fuzzy)
+                    }
+                    SearchItem::Genre(index) => {
+                        query.score_text(&[self.genres[index].name.as_ref()], fuzzy)
+                    }
+                    SearchItem::Year(index) => {
+                        query.score_text(&[self.years[index].name.as_ref()], fuzzy)
+                    }
+                    SearchItem::Track(index) => {
+                        query.score(self.search_text.get(index)?, &self.tracks[index], fuzzy)
+                    }
+                };
+                score.map(|score| (Reverse(score), item))
+            })
+            .collect();
+        // One ranking across every kind of result; collections come ahead of
+        // songs that score the same.
+        ranked.sort_unstable();
+        ranked.into_iter().map(|(_, item)| item).collect()
+    }
+
     fn activate_selected(&mut self) -> Result<()> {
         match self.view {
             View::Home => {
-                let recent = self.recent_indices();
-                if let Some(track) = recent.get(self.selected).copied() {
-                    let queue = recent.to_vec();
+                let rows = self.home_indices();
+                if let Some(track) = rows.get(self.selected).copied() {
+                    let queue = rows.to_vec();
                     self.play_queue(queue, track)?;
                 }
             }
@@ -1194,6 +2698,16 @@ impl App {
                         self.detail = Some(DetailView::Album(index));
                         self.selected = 0;
                     }
+                    SearchItem::Genre(index) => {
+                        self.view = View::Genres;
+                        self.detail = Some(DetailView::Genre(index));
+                        self.selected = 0;
+                    }
+                    SearchItem::Year(index) => {
+                        self.view = View::Years;
+                        self.detail = Some(DetailView::Year(index));
+                        self.selected = 0;
+                    }
                     SearchItem::Track(track) => {
                         let queue: Vec<usize> = self
                             .search_results
@@ -1245,6 +2759,26 @@ impl App {
                     }
                 }
             },
+            View::Genres | View::Years => match self.detail {
+                Some(DetailView::Genre(index) | DetailView::Year(index)) => {
+                    if let Some(category) = self.categories().get(index) {
+                        let queue = category.tracks.clone();
+                        if let Some(track) = queue.get(self.selected).copied() {
+                            self.play_queue(queue, track)?;
+                        }
+                    }
+                }
+                _ => {
+                    if self.selected < self.categories().len() {
+                        self.detail = Some(if self.view == View::Years {
+                            DetailView::Year(self.selected)
+                        } else {
+                            DetailView::Genre(self.selected)
+                        });
+                        self.selected = 0;
+                    }
+                }
+            },
             View::Playlists => match self.detail {
                 Some(DetailView::Playlist(playlist_index)) => {
                     if let Some(playlist) = self.playlists.get(playlist_index) {
@@ -1262,6 +2796,7 @@ impl App {
                 }
             },
             View::Folders => self.activate_folder()?,
+            View::Queue => self.jump_to_queue_item(self.selected)?,
         }
         Ok(())
     }
@@ -1281,26 +2816,42 @@ impl App {
     }
 
     fn play_track(&mut self, index: usize) -> Result<()> {
+        self.start_track(index, false)
+    }
+
+    fn start_track(&mut self, index: usize, skipped: bool) -> Result<()> {
         let Some(track) = self.tracks.get(index) else {
             return Ok(());
         };
+        let crossfade = skipped && self.settings.crossfade_skips && self.crossfades_into(track);
+        self.audio.play_file(track, crossfade)?;
+        self.preloaded = None;
+        self.preload_failed = None;
+        self.track_started(index);
+        Ok(())
+    }
+
+    fn track_started(&mut self, index: usize) {
+        let Some(track) = self.tracks.get(index) else {
+            return;
+        };
+        let track = track.clone();
         let path = track.path.clone();
         let title = track.title.clone();
 
-        self.audio.play_file(&path)?;
         self.browser_current = None;
         self.current = Some(index);
         self.queue_pos = self.queue.iter().position(|queued| *queued == index);
         self.state = PlaybackState::Playing;
         self.status = format!("Playing {title}");
 
+        self.start_listen(track);
         self.recent_paths.retain(|recent| recent != &path);
         self.recent_paths.insert(0, path);
         self.recent_paths.truncate(50);
         self.recent_indices_cache.retain(|recent| *recent != index);
         self.recent_indices_cache.insert(0, index);
         self.recent_indices_cache.truncate(50);
-        Ok(())
     }
 
     fn play_browser_queue(&mut self, queue: Vec<PathBuf>, selected_path: &Path) -> Result<()> {
@@ -1325,12 +2876,25 @@ impl App {
     }
 
     fn play_browser_at(&mut self, position: usize) -> Result<()> {
+        self.start_browser_at(position, false)
+    }
+
+    fn start_browser_at(&mut self, position: usize, skipped: bool) -> Result<()> {
         let Some(path) = self.browser_queue.get(position).cloned() else {
             return Ok(());
         };
-        let track = Track::from_path(path.clone());
+        let track = Track::from_path(path);
+        let crossfade = skipped && self.settings.crossfade_skips && self.crossfades_into(&track);
+        self.audio.play_file(&track, crossfade)?;
+        self.preloaded = None;
+        self.preload_failed = None;
+        self.browser_track_started(position, track);
+        Ok(())
+    }
 
-        self.audio.play_file(&path)?;
+    fn browser_track_started(&mut self, position: usize, track: Track) {
+        let path = track.path.clone();
+        self.start_listen(track.clone());
         self.current = None;
         self.browser_queue_pos = Some(position);
         self.browser_current = Some(track);
@@ -1344,7 +2908,6 @@ impl App {
         self.recent_paths.insert(0, path);
         self.recent_paths.truncate(50);
         self.refresh_recent_indices();
-        Ok(())
     }
 
     fn toggle_playback(&mut self) -> Result<()> {
@@ -1369,6 +2932,8 @@ impl App {
                         self.rebuild_queue_order();
                     }
                     self.play_track(current)?;
+                } else if self.view == View::Queue && !self.queue.is_empty() {
+                    self.jump_to_queue_item(self.selected)?;
                 } else if let Some((queue, track)) = self.queue_for_selection() {
                     self.play_queue(queue, track)?;
                 } else {
@@ -1382,7 +2947,7 @@ impl App {
     fn queue_for_selection(&self) -> Option<(Vec<usize>, usize)> {
         match self.view {
             View::Home => {
-                let queue = self.recent_indices();
+                let queue = self.home_indices();
                 let track = queue.get(self.selected).copied()?;
                 Some((queue.to_vec(), track))
             }
@@ -1415,6 +2980,16 @@ impl App {
                         let track = queue.first().copied()?;
                         Some((queue, track))
                     }
+                    SearchItem::Genre(index) => {
+                        let queue = self.genres.get(index)?.tracks.clone();
+                        let track = queue.first().copied()?;
+                        Some((queue, track))
+                    }
+                    SearchItem::Year(index) => {
+                        let queue = self.years.get(index)?.tracks.clone();
+                        let track = queue.first().copied()?;
+                        Some((queue, track))
+                    }
                 }
             }
             View::Songs => {
@@ -1448,6 +3023,19 @@ impl App {
                 }?;
                 Some((queue, track))
             }
+            View::Genres | View::Years => {
+                let category_index = match self.detail {
+                    Some(DetailView::Genre(index) | DetailView::Year(index)) => index,
+                    _ => self.selected,
+                };
+                let queue = self.categories().get(category_index)?.tracks.clone();
+                let track = if self.detail.is_some() {
+                    queue.get(self.selected).copied()
+                } else {
+                    queue.first().copied()
+                }?;
+                Some((queue, track))
+            }
             View::Playlists => {
                 let playlist_index = match self.detail {
                     Some(DetailView::Playlist(index)) => index,
@@ -1461,11 +3049,22 @@ impl App {
                 }?;
                 Some((queue, track))
             }
-            View::Folders => None,
+            View::Folders | View::Queue => None,
         }
     }
 
     fn next_track(&mut self, automatic: bool) -> Result<()> {
+        if !automatic {
+            self.listen_end = ListenEnd::Skipped;
+        }
+        let result = self.advance_track(automatic);
+        if !automatic {
+            self.listen_end = ListenEnd::Interrupted;
+        }
+        result
+    }
+
+    fn advance_track(&mut self, automatic: bool) -> Result<()> {
         if self.browser_current.is_some() {
             if automatic && self.repeat == RepeatMode::One {
                 return self.play_browser_at(self.browser_queue_pos.unwrap_or(0));
@@ -1484,7 +3083,7 @@ impl App {
                 self.status = "Folder queue finished".to_owned();
                 return Ok(());
             };
-            return self.play_browser_at(next_pos);
+            return self.start_browser_at(next_pos, !automatic);
         }
 
         if automatic && self.repeat == RepeatMode::One {
@@ -1501,11 +3100,7 @@ impl App {
             self.rebuild_queue_order();
         }
 
-        let current_pos = self
-            .current
-            .and_then(|current| self.queue.iter().position(|index| *index == current))
-            .or(self.queue_pos)
-            .unwrap_or(0);
+        let current_pos = self.current_queue_position().unwrap_or(0);
 
         let next_pos = if current_pos + 1 < self.queue.len() {
             current_pos + 1
@@ -1519,7 +3114,7 @@ impl App {
 
         self.queue_pos = Some(next_pos);
         let next_track = self.queue[next_pos];
-        self.play_track(next_track)
+        self.start_track(next_track, !automatic)
     }
 
     fn previous_track(&mut self) -> Result<()> {
@@ -1541,18 +3136,14 @@ impl App {
             } else {
                 0
             };
-            return self.play_browser_at(previous_pos);
+            return self.start_browser_at(previous_pos, true);
         }
 
         if self.queue.is_empty() {
             return Ok(());
         }
 
-        let current_pos = self
-            .current
-            .and_then(|current| self.queue.iter().position(|index| *index == current))
-            .or(self.queue_pos)
-            .unwrap_or(0);
+        let current_pos = self.current_queue_position().unwrap_or(0);
         let previous_pos = if current_pos > 0 {
             current_pos - 1
         } else if self.repeat == RepeatMode::All {
@@ -1563,11 +3154,24 @@ impl App {
 
         self.queue_pos = Some(previous_pos);
         let previous_track = self.queue[previous_pos];
-        self.play_track(previous_track)
+        self.start_track(previous_track, true)
+    }
+
+    fn current_queue_position(&self) -> Option<usize> {
+        let Some(current) = self.current else {
+            return self.queue_pos;
+        };
+        self.queue_pos
+            .filter(|position| self.queue.get(*position) == Some(&current))
+            .or_else(|| self.queue.iter().position(|index| *index == current))
+            .or(self.queue_pos)
     }
 
     fn stop(&mut self) {
+        self.end_listen();
         self.audio.stop();
+        self.preloaded = None;
+        self.preload_failed = None;
         self.state = PlaybackState::Stopped;
     }
 
@@ -1581,6 +3185,13 @@ impl App {
         Ok(())
     }
 
+    fn seek_to(&mut self, seconds: f64) -> Result<()> {
+        if self.state == PlaybackState::Stopped {
+            return Err(io::Error::other("Nothing is playing"));
+        }
+        self.audio.seek_to(seconds.max(0.0))
+    }
+
     fn change_volume(&mut self, delta: i16) {
         self.volume = (self.volume as i16 + delta).clamp(0, 100) as u8;
         self.audio.set_volume(self.volume);
@@ -1603,6 +3214,20 @@ impl App {
         self.status = format!("Repeat {}", self.repeat.label());
     }
 
+    fn cycle_gain_mode(&mut self) {
+        let mode = match self.audio.gain_mode() {
+            GainMode::Off => GainMode::Track,
+            GainMode::Track => GainMode::Album,
+            GainMode::Album => GainMode::Off,
+        };
+        self.audio.set_gain_mode(mode);
+        self.status = format!("ReplayGain {}", mode.label());
+    }
+
+    pub fn gain_mode(&self) -> GainMode {
+        self.audio.gain_mode()
+    }
+
     fn rebuild_queue_order(&mut self) {
         self.queue.clone_from(&self.queue_base);
         let current = self.current;
@@ -1637,6 +3262,204 @@ impl App {
             .and_then(|path| self.browser_queue.iter().position(|queued| queued == path));
     }
 
+    pub fn folder_queue(&self) -> Option<&[PathBuf]> {
+        self.browser_current
+            .as_ref()
+            .map(|_| self.browser_queue.as_slice())
+    }
+
+    pub fn active_queue_len(&self) -> usize {
+        self.folder_queue().map_or(self.queue.len(), <[PathBuf]>::len)
+    }
+
+    pub fn active_queue_position(&self) -> Option<usize> {
+        if self.browser_current.is_some() {
+            self.browser_queue_pos
+        } else {
+            self.current_queue_position()
+        }
+    }
+
+    fn jump_to_queue_item(&mut self, position: usize) -> Result<()> {
+        if self.browser_current.is_some() {
+            return self.start_browser_at(position, true);
+        }
+        let Some(track) = self.queue.get(position).copied() else {
+            return Ok(());
+        };
+        self.start_track(track, true)?;
+        self.queue_pos = Some(position);
+        Ok(())
+    }
+
+    fn move_queue_item(&mut self, delta: isize) {
+        let from = self.selected;
+        let to = move_index(from, self.selection_len(), delta);
+        if from == to {
+            return;
+        }
+        if self.browser_current.is_some() {
+            move_queue_entry(
+                &mut self.browser_queue_base,
+                &mut self.browser_queue,
+                &mut self.browser_queue_pos,
+                from,
+                to,
+                self.shuffle,
+            );
+        } else {
+            move_queue_entry(
+                &mut self.queue_base,
+                &mut self.queue,
+                &mut self.queue_pos,
+                from,
+                to,
+                self.shuffle,
+            );
+        }
+        self.selected = to;
+    }
+
+    fn remove_queue_item(&mut self) {
+        if let Err(error) = self.remove_queue_position(self.selected) {
+            self.status = error.to_string();
+            return;
+        }
+        self.selected = self.selected.min(self.selection_len().saturating_sub(1));
+    }
+
+    fn remove_queue_position(&mut self, position: usize) -> Result<()> {
+        if position >= self.active_queue_len() {
+            return Ok(());
+        }
+        if self.active_queue_position() == Some(position) && self.state != PlaybackState::Stopped {
+            return Err(io::Error::other(
+                "The playing song stays in the queue; skip it first",
+            ));
+        }
+        if self.browser_current.is_some() {
+            remove_queue_entry(
+                &mut self.browser_queue_base,
+                &mut self.browser_queue,
+                &mut self.browser_queue_pos,
+                position,
+                self.shuffle,
+            );
+        } else {
+            if self.queue_pos.is_none() {
+                self.queue_pos = self.current_queue_position();
+            }
+            remove_queue_entry(
+                &mut self.queue_base,
+                &mut self.queue,
+                &mut self.queue_pos,
+                position,
+                self.shuffle,
+            );
+        }
+        self.status = "Removed from queue".to_owned();
+        Ok(())
+    }
+
+    fn clear_queue(&mut self) {
+        let playing = self.state != PlaybackState::Stopped;
+        if self.browser_current.is_some() {
+            let keep = self
+                .browser_queue_pos
+                .filter(|_| playing)
+                .and_then(|position| self.browser_queue.get(position).cloned());
+            self.browser_queue = keep.into_iter().collect();
+            self.browser_queue_base.clone_from(&self.browser_queue);
+            self.browser_queue_pos = (!self.browser_queue.is_empty()).then_some(0);
+        } else {
+            let keep = self
+                .current_queue_position()
+                .filter(|_| playing)
+                .and_then(|position| self.queue.get(position).copied());
+            self.queue = keep.into_iter().collect();
+            self.queue_base.clone_from(&self.queue);
+            self.queue_pos = (!self.queue.is_empty()).then_some(0);
+        }
+        self.selected = 0;
+        self.status = "Queue cleared".to_owned();
+    }
+
+    fn enqueue_selection(&mut self, next: bool) {
+        let selection = if self.view == View::Folders {
+            match self.browser_entries.get(self.browser_selected).map(|entry| &entry.kind) {
+                Some(DirectoryEntryKind::Track(track)) if self.folder_focus == FolderFocus::Browser => {
+                    Some((vec![track.path.clone()], track.title.to_string()))
+                }
+                Some(DirectoryEntryKind::Playlist(path)) if self.folder_focus == FolderFocus::Browser => {
+                    let label = path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+                    playable_paths(path).ok().map(|paths| (paths, label))
+                }
+                _ => None,
+            }
+        } else {
+            self.selected_tracks_for_playlist().map(|(indices, label)| {
+                let paths = indices
+                    .iter()
+                    .filter_map(|index| self.tracks.get(*index))
+                    .map(|track| track.path.clone())
+                    .collect();
+                (paths, label)
+            })
+        };
+        let Some((paths, label)) = selection.filter(|(paths, _)| !paths.is_empty()) else {
+            self.status = "Select a song, album, artist, or playlist first".to_owned();
+            return;
+        };
+
+        if let Err(error) = self.enqueue_paths(paths, &label, next) {
+            self.status = error.to_string();
+        }
+    }
+
+    fn enqueue_paths(&mut self, paths: Vec<PathBuf>, label: &str, next: bool) -> Result<()> {
+        if self.browser_current.is_some() {
+            insert_queue_entries(
+                &mut self.browser_queue_base,
+                &mut self.browser_queue,
+                self.browser_queue_pos,
+                paths,
+                next,
+                self.shuffle,
+            );
+        } else {
+            let indices: Vec<usize> = paths
+                .iter()
+                .filter_map(|path| find_track_index(&self.tracks, &self.path_order, path))
+                .collect();
+            if indices.is_empty() {
+                if self.current.is_none() && self.queue.is_empty() {
+                    if let Some(first) = paths.first().cloned() {
+                        return self.play_browser_queue(paths, &first);
+                    }
+                }
+                return Err(io::Error::other(
+                    "Only library songs can join the library queue",
+                ));
+            }
+            let position = self.current_queue_position();
+            self.queue_pos = position;
+            insert_queue_entries(
+                &mut self.queue_base,
+                &mut self.queue,
+                position,
+                indices,
+                next,
+                self.shuffle,
+            );
+        }
+        self.status = if next {
+            format!("Playing {label} next")
+        } else {
+            format!("Added {label} to the queue")
+        };
+        Ok(())
+    }
+
     fn toggle_theme(&mut self) {
         self.theme = match self.theme {
             Theme::Dark => Theme::Light,
@@ -1690,7 +3513,7 @@ impl App {
 
     pub fn selection_len(&self) -> usize {
         match self.view {
-            View::Home => self.recent_indices().len(),
+            View::Home => self.home_indices().len(),
             View::Search => self.search_results.len(),
             View::Songs => self.tracks.len(),
             View::Albums => match self.detail {
@@ -1707,6 +3530,13 @@ impl App {
                     .map_or(0, |artist| artist.tracks.len()),
                 _ => self.artists.len(),
             },
+            View::Genres | View::Years => match self.detail {
+                Some(DetailView::Genre(index) | DetailView::Year(index)) => self
+                    .categories()
+                    .get(index)
+                    .map_or(0, |category| category.tracks.len()),
+                _ => self.categories().len(),
+            },
             View::Playlists => match self.detail {
                 Some(DetailView::Playlist(index)) => self
                     .playlists
@@ -1718,6 +3548,13 @@ impl App {
                 FolderFocus::Roots => self.roots.len(),
                 FolderFocus::Browser => self.browser_entries.len(),
             },
+            View::Queue => {
+                if self.browser_current.is_some() {
+                    self.browser_queue.len()
+                } else {
+                    self.queue.len()
+                }
+            }
         }
     }
 
@@ -1737,13 +3574,18 @@ impl App {
                             self.browser_dir = path;
                             self.refresh_browser();
                         }
+                        DirectoryEntryKind::Playlist(path) => {
+                            let queue = playable_paths(&path)?;
+                            let first = queue[0].clone();
+                            self.play_browser_queue(queue, &first)?;
+                        }
                         DirectoryEntryKind::Track(track) => {
                             let queue: Vec<PathBuf> = self
                                 .browser_entries
                                 .iter()
                                 .filter_map(|entry| match &entry.kind {
                                     DirectoryEntryKind::Track(track) => Some(track.path.clone()),
-                                    DirectoryEntryKind::Directory(_) => None,
+                                    _ => None,
                                 })
                                 .collect();
                             self.play_browser_queue(queue, &track.path)?;
@@ -1793,6 +3635,7 @@ impl App {
         self.root_selected = self.roots.len().saturating_sub(1);
         self.status = "Library folder added".to_owned();
         self.begin_scan();
+        self.restart_watcher();
         Ok(())
     }
 
@@ -1804,6 +3647,7 @@ impl App {
         self.roots.remove(self.root_selected);
         self.root_selected = self.root_selected.min(self.roots.len().saturating_sub(1));
         storage::save_roots(&self.roots)?;
+        self.restart_watcher();
 
         if self.roots.is_empty() {
             self.stop();
@@ -1812,6 +3656,8 @@ impl App {
             self.recent_indices_cache.clear();
             self.albums.clear();
             self.artists.clear();
+            self.genres.clear();
+            self.years.clear();
             self.rebuild_playlist_indexes();
             self.search_results.clear();
             self.queue.clear();
@@ -1831,16 +3677,150 @@ impl App {
             .or(self.browser_current.as_ref())
     }
 
+    pub fn library_track(&self, path: &Path) -> Option<&Track> {
+        find_track_index(&self.tracks, &self.path_order, path).map(|index| &self.tracks[index])
+    }
+
     pub fn recent_indices(&self) -> &[usize] {
         &self.recent_indices_cache
     }
 
+    pub fn most_played_indices(&self) -> &[usize] {
+        &self.most_played_cache
+    }
+
+    pub fn home_indices(&self) -> &[usize] {
+        match self.home_section {
+            HomeSection::Recent => self.recent_indices(),
+            HomeSection::MostPlayed => self.most_played_indices(),
+        }
+    }
+
+    pub fn play_stats(&self, index: usize) -> PlayStats {
+        self.tracks
+            .get(index)
+            .map(|track| self.history.stats(&track.path))
+            .unwrap_or_default()
+    }
+
+    pub fn finish_listening(&mut self) {
+        self.end_listen();
+    }
+
+    fn toggle_home_section(&mut self) {
+        self.home_section = match self.home_section {
+            HomeSection::Recent => HomeSection::MostPlayed,
+            HomeSection::MostPlayed => HomeSection::Recent,
+        };
+        self.selected = 0;
+    }
+
+    fn start_listen(&mut self, track: Track) {
+        self.end_listen();
+        self.listen = Some(Listen {
+            track,
+            started_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |duration| duration.as_secs()),
+            listened: Duration::ZERO,
+            checked: Instant::now(),
+        });
+    }
+
+    fn update_listen(&mut self) {
+        if let Some(listen) = &mut self.listen {
+            let now = Instant::now();
+            if self.state == PlaybackState::Playing {
+                listen.listened += now.saturating_duration_since(listen.checked);
+            }
+            listen.checked = now;
+        }
+    }
+
+    fn end_listen(&mut self) {
+        self.update_listen();
+        let Some(listen) = self.listen.take() else {
+            return;
+        };
+        let threshold = listen
+            .track
+            .duration
+            .map_or(PLAYED_THRESHOLD, |duration| duration / 2)
+            .min(PLAYED_THRESHOLD);
+        // Finishing only counts as a play when the song's length is unknown;
+        // otherwise seeking to the end would inflate the play count.
+        let reached = listen.listened >= threshold
+            || (self.listen_end == ListenEnd::Finished && listen.track.duration.is_none());
+        let skipped = match self.listen_end {
+            _ if reached => false,
+            ListenEnd::Skipped => true,
+            _ => return,
+        };
+        if listen.listened.is_zero() {
+            return;
+        }
+
+        let track = listen.track;
+        if !skipped
+            && reached
+            && track.tagged_artist
+            && track
+                .duration
+                .is_none_or(|duration| duration > scrobble::MIN_DURATION)
+        {
+            let album = if track.tagged_album {
+                track.album.as_ref()
+            } else {
+                ""
+            };
+            if let Err(error) = self.scrobbler.push(
+                listen.started_at,
+                &track.artist,
+                &track.title,
+                album,
+                track.duration,
+            ) {
+                self.status = format!("Could not queue scrobble: {error}");
+            }
+        }
+
+        let play = storage::Play {
+            path: track.path,
+            played_at: listen.started_at,
+            listened: listen.listened,
+            skipped,
+        };
+        if let Err(error) = self.history.record(&play) {
+            self.status = format!("Could not save play history: {error}");
+        }
+        self.refresh_most_played();
+    }
+
+    fn refresh_most_played(&mut self) {
+        let mut played: Vec<(usize, PlayStats)> = self
+            .history
+            .iter()
+            .filter(|(_, stats)| stats.plays > 0)
+            .filter_map(|(path, stats)| {
+                find_track_index(&self.tracks, &self.path_order, path).map(|index| (index, *stats))
+            })
+            .collect();
+        played.sort_by(|(_, left), (_, right)| {
+            right
+                .plays
+                .cmp(&left.plays)
+                .then_with(|| right.last_played.cmp(&left.last_played))
+        });
+        self.most_played_cache = played.into_iter().take(50).map(|(index, _)| index).collect();
+    }
+
     fn refresh_recent_indices(&mut self) {
         self.recent_indices_cache = self
             .recent_paths
             .iter()
             .filter_map(|path| find_track_index(&self.tracks, &self.path_order, path))
             .collect();
+        self.refresh_most_played();
     }
 
     pub fn position_seconds(&self) -> f64 {
@@ -1856,6 +3836,130 @@ impl App {
     }
 }
 
+fn playable_paths(path: &Path) -> Result<Vec<PathBuf>> {
+    let path = path.canonicalize().map_err(|error| {
+        io::Error::other(format!("cannot open {}: {error}", path.display()))
+    })?;
+
+    let paths = if path.is_dir() {
+        let mut paths = library::collect_audio_paths(std::slice::from_ref(&path))?;
+        paths.sort();
+        paths
+    } else if playlist_file::is_playlist(&path) {
+        playlist_file::read(&path)?
+            .into_iter()
+            .filter(|entry| entry.is_file() && library::is_supported_audio(entry))
+            .collect()
+    } else if path.is_file() && library::is_supported_audio(&path) {
+        vec![path.clone()]
+    } else {
+        return Err(io::Error::other(format!(
+            "{} is not a supported audio file, folder, or playlist",
+            path.display()
+        )));
+    };
+
+    if paths.is_empty() {
+        return Err(io::Error::other(format!(
+            "no playable songs in {}",
+            path.display()
+        )));
+    }
+    Ok(paths)
+}
+
+pub fn match_playlist_entries(
+    tracks: &[Track],
+    path_order: &[usize],
+    entries: &[playlist_file::Entry],
+) -> Vec<usize> {
+    resolve_playlist_entries(tracks, path_order, entries)
+        .into_iter()
+        .flatten()
+        .collect()
+}
+
+fn resolve_playlist_entries(
+    tracks: &[Track],
+    path_order: &[usize],
+    entries: &[playlist_file::Entry],
+) -> Vec<Option<usize>> {
+    let mut titles: Option<HashMap<String, Vec<usize>>> = None;
+    entries
+        .iter()
+        .map(|entry| {
+            find_track_index(tracks, path_order, &entry.path)
+                .or_else(|| find_track_index(tracks, path_order, entry.canonical.as_deref()?))
+                .or_else(|| {
+                    let title = entry.title.as_deref()?.to_lowercase();
+                    let titles = titles.get_or_insert_with(|| {
+                        let mut titles: HashMap<String, Vec<usize>> = HashMap::new();
+                        for (index, track) in tracks.iter().enumerate() {
+                            titles.entry(track.title.to_lowercase()).or_default().push(index);
+                        }
+                        titles
+                    });
+                    let (artist, bare_title) = match title.split_once(" - ") {
+                        Some((artist, bare_title)) => (Some(artist), bare_title),
+                        None => (None, title.as_str()),
+                    };
+                    let candidates = titles.get(bare_title).or_else(|| titles.get(&title))?;
+                    candidates.iter().copied().find(|index| {
+                        let track = &tracks[*index];
+                        let artist_matches = artist.is_none_or(|artist| {
+                            track.artist.to_lowercase() == artist || track.title.to_lowercase() == title
+                        });
+                        let duration_matches = match (entry.duration, track.duration) {
+                            (Some(expected), Some(actual)) => {
+                                expected.abs_diff(actual) <= Duration::from_secs(2)
+                            }
+                            _ => true,
+                        };
+                        artist_matches && duration_matches
+                    })
+                })
+        })
+        .collect()
+}
+
+fn read_only_status(playlist: &Playlist) -> String {
+    if playlist.smart.is_some() {
+        return format!("'{}' is a smart playlist; its songs follow its rules", playlist.name);
+    }
+    format!(
+        "'{}' is a playlist file and cannot be edited; press i to import a copy",
+        playlist.name
+    )
+}
+
+pub fn unique_playlist_name<'a>(
+    existing: impl IntoIterator<Item = &'a str> + Clone,
+    base: &str,
+) -> String {
+    let taken = |name: &str| {
+        existing
+            .clone()
+            .into_iter()
+            .any(|existing| existing.eq_ignore_ascii_case(name))
+    };
+    if !taken(base) {
+        return base.to_owned();
+    }
+    (2..)
+        .map(|number| format!("{base} ({number})"))
+        .find(|name| !taken(name))
+        .unwrap_or_else(|| base.to_owned())
+}
+
+fn expand_home(path: &str) -> PathBuf {
+    match path.strip_prefix('~') {
+        Some(rest) if rest.is_empty() || rest.starts_with('/') => {
+            storage::home_dir().join(rest.trim_start_matches('/'))
+        }
+        _ => PathBuf::from(path),
+    }
+}
+
 fn find_track_index(tracks: &[Track], path_order: &[usize], path: &Path) -> Option<usize> {
     path_order
         .binary_search_by(|index| tracks[*index].path.as_path().cmp(path))
@@ -1863,6 +3967,248 @@ fn find_track_index(tracks: &[Track], path_order: &[usize], path: &Path) -> Opti
         .map(|position| path_order[position])
 }
 
+pub fn group_library(tracks: &[Track], credits: &ArtistCredits) -> (Vec<Album>, Vec<Artist>) {
+    let mut album_map: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+    let mut artist_map: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+
+    for (index, track) in tracks.iter().enumerate() {
+        album_map.entry(album_key(track)).or_default().push(index);
+        for key in credits.keys(track) {
+            artist_map.entry(key).or_default().push(index);
+        }
+    }
+
+    let mut albums: Vec<Album> = album_map
+        .into_values()
+        .map(|members| build_album(tracks, members))
+        .collect();
+    sort_albums(&mut albums);
+
+    let mut artists: Vec<Artist> = artist_map
+        .into_iter()
+        .map(|(key, members)| build_artist(tracks, members, credits, &key))
+        .collect();
+    sort_artists(&mut artists);
+
+    (albums, artists)
+}
+
+fn album_key(track: &Track) -> String {
+    let mut key = track.album_dir.to_string_lossy().to_lowercase();
+    key.push('\0');
+    key.push_str(&track.album.to_lowercase());
+    key
+}
+
+fn build_album(all_tracks: &[Track], mut tracks: Vec<usize>) -> Album {
+    tracks.sort_by(|left, right| compare_album_tracks(&all_tracks[*left], &all_tracks[*right]));
+    let first = &all_tracks[tracks[0]];
+    let mut album_artist: Option<Arc<str>> = None;
+    let mut various_artists = false;
+    for index in &tracks {
+        let candidate = &all_tracks[*index].album_artist;
+        if candidate.trim().is_empty() {
+            continue;
+        }
+        match &album_artist {
+            None => album_artist = Some(Arc::clone(candidate)),
+            Some(existing) if existing.as_ref() == candidate.as_ref() => {}
+            Some(_) => {
+                various_artists = true;
+                break;
+            }
+        }
+    }
+    let artist = if various_artists {
+        Arc::from("Various Artists")
+    } else {
+        album_artist.unwrap_or_else(|| Arc::clone(&first.artist))
+    };
+    let duration = tracks.iter().fold(Duration::ZERO, |total, index| {
+        total.saturating_add(all_tracks[*index].duration.unwrap_or_default())
+    });
+    Album {
+        title: Arc::clone(&first.album),
+        artist,
+        tracks,
+        duration,
+    }
+}
+
+fn build_artist(
+    all_tracks: &[Track],
+    mut tracks: Vec<usize>,
+    credits: &ArtistCredits,
+    key: &str,
+) -> Artist {
+    tracks.sort_by(|left, right| compare_artist_tracks(&all_tracks[*left], &all_tracks[*right]));
+    let first = &all_tracks[tracks[0]];
+    let name = credits
+        .names(first)
+        .into_iter()
+        .find(|name| name.to_lowercase() == key)
+        .map_or_else(|| Arc::clone(&first.artist), Arc::from);
+    let album_count = album_count(all_tracks, &tracks);
+    Artist {
+        name,
+        tracks,
+        album_count,
+    }
+}
+
+fn group_categories(
+    tracks: &[Track],
+    key: impl Fn(&Track) -> String,
+    name: impl Fn(&Track) -> Arc<str>,
+) -> Vec<Category> {
+    let mut category_map: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+    for (index, track) in tracks.iter().enumerate() {
+        category_map.entry(key(track)).or_default().push(index);
+    }
+    let mut categories: Vec<Category> = category_map
+        .into_values()
+        .map(|members| build_category(tracks, members, &name))
+        .collect();
+    sort_categories(&mut categories, tracks, key);
+    categories
+}
+
+fn build_category(
+    all_tracks: &[Track],
+    mut tracks: Vec<usize>,
+    name: impl Fn(&Track) -> Arc<str>,
+) -> Category {
+    tracks.sort_by(|left, right| {
+        let (left, right) = (&all_tracks[*left], &all_tracks[*right]);
+        compare_text(left.album_artist.as_ref(), right.album_artist.as_ref())
+            .then_with(|| compare_artist_tracks(left, right))
+    });
+    Category {
+        name: name(&all_tracks[tracks[0]]),
+        album_count: album_count(all_tracks, &tracks),
+        tracks,
+    }
+}
+
+fn genre_key(track: &Track) -> String {
+    track
+        .genre
+        .as_deref()
+        .map_or_else(String::new, str::to_lowercase)
+}
+
+fn genre_name(track: &Track) -> Arc<str> {
+    track
+        .genre
+        .clone()
+        .unwrap_or_else(|| Arc::from("Unknown Genre"))
+}
+
+fn album_count(all_tracks: &[Track], tracks: &[usize]) -> usize {
+    tracks
+        .iter()
+        .map(|index| {
+            (
+                all_tracks[*index].album_dir.as_ref(),
+                all_tracks[*index].album.as_ref(),
+            )
+        })
+        .collect::<BTreeSet<_>>()
+        .len()
+}
+
+fn sort_categories(categories: &mut [Category], tracks: &[Track], key: impl Fn(&Track) -> String) {
+    categories.sort_by_cached_key(|category| {
+        let key = key(&tracks[category.tracks[0]]);
+        (key.is_empty(), key)
+    });
+}
+
+fn sort_albums(albums: &mut [Album]) {
+    albums.sort_by_cached_key(|album| (album.artist.to_lowercase(), album.title.to_lowercase()));
+}
+
+fn sort_artists(artists: &mut [Artist]) {
+    artists.sort_by_cached_key(|artist| artist.name.to_lowercase());
+}
+
+#[allow(clippy::too_many_arguments)]
+fn patch_groups<G>(
+    groups: &mut Vec<G>,
+    tracks: &[Track],
+    remap: &[Option<usize>],
+    incoming: &[usize],
+    members: impl Fn(&mut G) -> &mut Vec<usize>,
+    group_key: impl Fn(&G) -> String,
+    track_keys: impl Fn(&Track) -> Vec<String>,
+    build: impl Fn(&[Track], Vec<usize>, &str) -> G,
+) -> bool {
+    let mut touched = vec![false; groups.len()];
+    let mut keys = Vec::with_capacity(groups.len());
+    let mut positions = HashMap::with_capacity(groups.len());
+    for (position, group) in groups.iter_mut().enumerate() {
+        let indices = members(group);
+        let before = indices.len();
+        indices.retain_mut(|index| match remap[*index] {
+            Some(next) => {
+                *index = next;
+                true
+            }
+            None => false,
+        });
+        touched[position] = indices.len() != before;
+        let key = if indices.is_empty() {
+            String::new()
+        } else {
+            let key = group_key(group);
+            positions.insert(key.clone(), position);
+            key
+        };
+        keys.push(key);
+    }
+
+    let mut created: Vec<(String, Vec<usize>)> = Vec::new();
+    for index in incoming {
+        for track_key in track_keys(&tracks[*index]) {
+            match positions.get(&track_key) {
+                Some(position) if *position < groups.len() => {
+                    members(&mut groups[*position]).push(*index);
+                    touched[*position] = true;
+                }
+                Some(position) => created[*position - groups.len()].1.push(*index),
+                None => {
+                    positions.insert(track_key.clone(), groups.len() + created.len());
+                    created.push((track_key, vec![*index]));
+                }
+            }
+        }
+    }
+
+    let mut changed = !created.is_empty();
+    let mut position = 0;
+    groups.retain_mut(|group| {
+        let was_touched = touched[position];
+        let key = &keys[position];
+        position += 1;
+        if !was_touched {
+            return true;
+        }
+        changed = true;
+        let indices = mem::take(members(group));
+        if indices.is_empty() {
+            return false;
+        }
+        *group = build(tracks, indices, key);
+        true
+    });
+    groups.extend(
+        created
+            .into_iter()
+            .map(|(key, indices)| build(tracks, indices, &key)),
+    );
+    changed
+}
+
 fn compare_album_tracks(left: &Track, right: &Track) -> std::cmp::Ordering {
     left.disc_no
         .unwrap_or(0)
@@ -1881,39 +4227,74 @@ fn compare_artist_tracks(left: &Track, right: &Track) -> std::cmp::Ordering {
         .then_with(|| compare_album_tracks(left, right))
 }
 
-fn compare_text(left: &str, right: &str) -> std::cmp::Ordering {
-    if left.is_ascii() && right.is_ascii() {
-        for (left, right) in left.bytes().zip(right.bytes()) {
-            let ordering = left.to_ascii_lowercase().cmp(&right.to_ascii_lowercase());
-            if !ordering.is_eq() {
-                return ordering;
-            }
-        }
-        left.len().cmp(&right.len())
-    } else {
-        left.to_lowercase().cmp(&right.to_lowercase())
+fn move_queue_entry<T: Clone + PartialEq>(
+    base: &mut Vec<T>,
+    queue: &mut [T],
+    position: &mut Option<usize>,
+    from: usize,
+    to: usize,
+    shuffle: bool,
+) {
+    queue.swap(from, to);
+    if *position == Some(from) {
+        *position = Some(to);
+    } else if *position == Some(to) {
+        *position = Some(from);
+    }
+    if !shuffle {
+        base.clear();
+        base.extend_from_slice(queue);
+        return;
+    }
+    // The two entries also trade places in the unshuffled order, so turning
+    // shuffle off keeps the move.
+    let moved = base.iter().position(|entry| *entry == queue[to]);
+    let swapped = base.iter().position(|entry| *entry == queue[from]);
+    if let (Some(moved), Some(swapped)) = (moved, swapped) {
+        base.swap(moved, swapped);
     }
 }
 
-fn matches_fields(fields: &[&str], tokens: &[String]) -> bool {
-    tokens.iter().all(|token| {
-        fields.iter().any(|field| {
-            if field.is_ascii() && token.is_ascii() {
-                contains_ascii_case_insensitive(field.as_bytes(), token.as_bytes())
-            } else {
-                field.to_lowercase().contains(token)
-            }
-        })
-    })
+fn remove_queue_entry<T: Clone + PartialEq>(
+    base: &mut Vec<T>,
+    queue: &mut Vec<T>,
+    position: &mut Option<usize>,
+    at: usize,
+    shuffle: bool,
+) {
+    let removed = queue.remove(at);
+    match *position {
+        Some(current) if current > at => *position = Some(current - 1),
+        Some(current) if current == at => *position = None,
+        _ => {}
+    }
+    if !shuffle {
+        base.clone_from(queue);
+    } else if let Some(index) = base.iter().position(|entry| *entry == removed) {
+        base.remove(index);
+    }
 }
 
-fn contains_ascii_case_insensitive(haystack: &[u8], needle: &[u8]) -> bool {
-    if needle.is_empty() {
-        return true;
+fn insert_queue_entries<T: Clone + PartialEq>(
+    base: &mut Vec<T>,
+    queue: &mut Vec<T>,
+    position: Option<usize>,
+    entries: Vec<T>,
+    next: bool,
+    shuffle: bool,
+) {
+    let at = if next {
+        position.map_or(0, |position| position + 1).min(queue.len())
+    } else {
+        queue.len()
+    };
+    if shuffle {
+        base.extend(entries.iter().cloned());
+    }
+    queue.splice(at..at, entries);
+    if !shuffle {
+        base.clone_from(queue);
     }
-    haystack
-        .windows(needle.len())
-        .any(|window| window.eq_ignore_ascii_case(needle))
 }
 
 fn move_index(current: usize, len: usize, delta: isize) -> usize {
@@ -1944,3 +4325,23 @@ fn shuffle_slice<T>(values: &mut [T]) {
         values.swap(index, other);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::move_queue_entry;
+
+    #[test]
+    fn moving_a_shuffled_entry_also_moves_it_in_the_base_order() {
+        let mut base = vec![1, 2, 3, 4];
+        let mut queue = vec![3, 1, 4, 2];
+        let mut position = Some(1);
+        move_queue_entry(&mut base, &mut queue, &mut position, 1, 2, true);
+        assert_eq!(queue, [3, 4, 1, 2]);
+        assert_eq!(position, Some(2));
+        assert_eq!(base, [4, 2, 3, 1]);
+
+        move_queue_entry(&mut base, &mut queue, &mut position, 0, 1, false);
+        assert_eq!(queue, [4, 3, 1, 2]);
+        assert_eq!(base, queue);
+    }
+}
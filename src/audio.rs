@@ -1,21 +1,169 @@
-use std::{fs::File, io, path::Path, time::Duration};
+use std::{
+    f32::consts::FRAC_PI_2,
+    fs::File,
+    io,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, AtomicU8, AtomicU32, AtomicU64, Ordering},
+    },
+    time::Duration,
+};
 
 use rodio::{
-    Decoder, Player, Source,
+    ChannelCount, Decoder, Player, Sample, SampleRate, Source,
+    source::{SeekError, UniformSourceIterator},
     stream::{DeviceSinkBuilder, MixerDeviceSink},
 };
 
-use crate::Result;
+use crate::{
+    Result,
+    library::{ReplayGain, Track},
+};
 
 struct Backend {
     player: Player,
+    channels: ChannelCount,
+    sample_rate: SampleRate,
+    chain: Option<ChainHandle>,
     _device: MixerDeviceSink,
 }
 
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FadeCurve {
+    #[default]
+    EqualPower,
+    Linear,
+    SCurve,
+}
+
+impl FadeCurve {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "equal-power" => Some(Self::EqualPower),
+            "linear" => Some(Self::Linear),
+            "s-curve" => Some(Self::SCurve),
+            _ => None,
+        }
+    }
+
+    fn gain(self, progress: f32) -> f32 {
+        let progress = progress.clamp(0.0, 1.0);
+        match self {
+            Self::EqualPower => (progress * FRAC_PI_2).sin(),
+            Self::Linear => progress,
+            Self::SCurve => progress * progress * (3.0 - 2.0 * progress),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum GainMode {
+    #[default]
+    Off,
+    Track,
+    Album,
+}
+
+impl GainMode {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "off" => Some(Self::Off),
+            "track" => Some(Self::Track),
+            "album" => Some(Self::Album),
+            _ => None,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Off => "off",
+            Self::Track => "track",
+            Self::Album => "album",
+        }
+    }
+
+    fn from_u8(value: u8) -> Self {
+        match value {
+            1 => Self::Track,
+            2 => Self::Album,
+            _ => Self::Off,
+        }
+    }
+}
+
+#[derive(Default)]
+struct GainControl {
+    mode: AtomicU8,
+    preamp: AtomicU32,
+    prevent_clipping: AtomicBool,
+    version: AtomicU64,
+}
+
+impl GainControl {
+    fn factor(&self, gain: &ReplayGain) -> f32 {
+        let (db, peak) = match GainMode::from_u8(self.mode.load(Ordering::Relaxed)) {
+            GainMode::Off => return 1.0,
+            GainMode::Track => (
+                gain.track_gain.or(gain.album_gain),
+                gain.track_peak.or(gain.album_peak),
+            ),
+            GainMode::Album => (
+                gain.album_gain.or(gain.track_gain),
+                gain.album_peak.or(gain.track_peak),
+            ),
+        };
+        let Some(db) = db else {
+            return 1.0;
+        };
+
+        let preamp = f32::from_bits(self.preamp.load(Ordering::Relaxed));
+        let factor = 10f32.powf((db + preamp) / 20.0);
+        match peak {
+            Some(peak) if peak > 0.0 && self.prevent_clipping.load(Ordering::Relaxed) => {
+                factor.min(1.0 / peak)
+            }
+            _ => factor,
+        }
+    }
+}
+
+#[derive(Clone)]
+struct ChainHandle {
+    next: Arc<Mutex<Option<Entry>>>,
+    cut_in: Arc<AtomicBool>,
+    timeline: Arc<Timeline>,
+}
+
+#[derive(Default)]
+struct Timeline {
+    entry: AtomicU64,
+    samples: AtomicU64,
+}
+
+struct Entry {
+    id: u64,
+    crossfade: bool,
+    replay_gain: ReplayGain,
+    factor: f32,
+    total_samples: Option<u64>,
+    source: Box<dyn Source + Send>,
+}
+
+#[derive(Clone, Copy)]
+struct Loaded {
+    id: u64,
+    total: Option<Duration>,
+}
+
 pub struct AudioEngine {
     backend: Option<Backend>,
     volume: u8,
-    total: Option<Duration>,
+    crossfade: Duration,
+    fade_curve: FadeCurve,
+    gain: Arc<GainControl>,
+    next_id: u64,
+    current: Option<Loaded>,
+    upcoming: Option<Loaded>,
 }
 
 impl AudioEngine {
@@ -23,7 +171,12 @@ impl AudioEngine {
         Self {
             backend: None,
             volume,
-            total: None,
+            crossfade: Duration::ZERO,
+            fade_curve: FadeCurve::default(),
+            gain: Arc::new(GainControl::default()),
+            next_id: 0,
+            current: None,
+            upcoming: None,
         }
     }
 
@@ -36,6 +189,9 @@ impl AudioEngine {
             player.set_volume(self.volume as f32 / 100.0);
             self.backend = Some(Backend {
                 player,
+                channels: device.config().channel_count(),
+                sample_rate: device.config().sample_rate(),
+                chain: None,
                 _device: device,
             });
         }
@@ -43,23 +199,184 @@ impl AudioEngine {
         Ok(self.backend.as_mut().expect("backend was initialized"))
     }
 
-    pub fn play_file(&mut self, path: &Path) -> Result<()> {
+    pub fn set_crossfade(&mut self, length: Duration, curve: FadeCurve) {
+        self.crossfade = length;
+        self.fade_curve = curve;
+    }
+
+    pub fn set_gain(&mut self, mode: GainMode, preamp: f32, prevent_clipping: bool) {
+        self.gain.preamp.store(preamp.to_bits(), Ordering::Relaxed);
+        self.gain
+            .prevent_clipping
+            .store(prevent_clipping, Ordering::Relaxed);
+        self.set_gain_mode(mode);
+    }
+
+    pub fn set_gain_mode(&mut self, mode: GainMode) {
+        self.gain.mode.store(mode as u8, Ordering::Relaxed);
+        self.gain.version.fetch_add(1, Ordering::Release);
+    }
+
+    pub fn gain_mode(&self) -> GainMode {
+        GainMode::from_u8(self.gain.mode.load(Ordering::Relaxed))
+    }
+
+    fn load(&mut self, track: &Track, crossfade: bool) -> Result<(Entry, Loaded)> {
+        let path = track.file_path();
+        let factor = self.gain.factor(&track.replay_gain);
+        let backend = self.ensure_backend()?;
+        let (channels, sample_rate) = (backend.channels, backend.sample_rate);
+
         let file = File::open(path).map_err(|error| {
             io::Error::other(format!("failed to open {}: {error}", path.display()))
         })?;
         let source = Decoder::try_from(file).map_err(|error| {
             io::Error::other(format!("failed to decode {}: {error}", path.display()))
         })?;
-        let total = source.total_duration();
+        let file_total = source.total_duration();
+        let source = UniformSourceIterator::new(source, channels, sample_rate);
+        let (total, source): (_, Box<dyn Source + Send>) = match track.range {
+            Some(range) => {
+                let end = range.end.or(file_total);
+                let total = end.map(|end| end.saturating_sub(range.start));
+                let clip = Clip::new(source, range.start, total);
+                (total, Box::new(clip))
+            }
+            None => (file_total, Box::new(source)),
+        };
+        let total_samples = total.map(|total| samples_for(total, channels, sample_rate));
+
+        self.next_id += 1;
+        let id = self.next_id;
+        let entry = Entry {
+            id,
+            crossfade,
+            replay_gain: track.replay_gain,
+            factor,
+            total_samples,
+            source,
+        };
+        Ok((entry, Loaded { id, total }))
+    }
+
+    pub fn play_file(&mut self, track: &Track, crossfade: bool) -> Result<()> {
+        let fading = crossfade
+            && !self.crossfade.is_zero()
+            && !self.is_empty()
+            && self.backend.as_ref().is_some_and(|backend| backend.chain.is_some());
+        let (entry, loaded) = self.load(track, fading)?;
+        if fading {
+            if let Some(chain) = self.backend.as_ref().and_then(|backend| backend.chain.as_ref()) {
+                *chain.next.lock().unwrap_or_else(|error| error.into_inner()) = Some(entry);
+                chain.cut_in.store(true, Ordering::Release);
+            }
+            self.resume();
+            self.current = Some(loaded);
+            self.upcoming = None;
+            return Ok(());
+        }
+
+        self.start(entry, loaded, 0, false)
+    }
+
+    pub fn cue_file(&mut self, track: &Track, position: Duration) -> Result<()> {
+        let (mut entry, loaded) = self.load(track, false)?;
+        let position = loaded.total.map_or(position, |total| position.min(total));
+        let played = match entry.source.try_seek(position) {
+            Ok(()) => {
+                let backend = self.ensure_backend()?;
+                samples_for(position, backend.channels, backend.sample_rate)
+            }
+            Err(_) => 0,
+        };
+        self.start(entry, loaded, played, true)
+    }
 
+    fn start(&mut self, entry: Entry, loaded: Loaded, played: u64, paused: bool) -> Result<()> {
+        let (fade_length, fade_curve) = (self.crossfade, self.fade_curve);
+        let gain = Arc::clone(&self.gain);
         let backend = self.ensure_backend()?;
+
+        let timeline = Arc::new(Timeline::default());
+        timeline.entry.store(entry.id, Ordering::Relaxed);
+        timeline.samples.store(played, Ordering::Relaxed);
+        let handle = ChainHandle {
+            next: Arc::new(Mutex::new(None)),
+            cut_in: Arc::new(AtomicBool::new(false)),
+            timeline,
+        };
+        let chain = Chain {
+            channels: backend.channels,
+            sample_rate: backend.sample_rate,
+            fade_samples: samples_for(fade_length, backend.channels, backend.sample_rate),
+            fade_curve,
+            gain_version: gain.version.load(Ordering::Acquire),
+            gain,
+            current: Some(entry),
+            played,
+            emitted: 0,
+            fade: None,
+            handle: handle.clone(),
+        };
+
         backend.player.stop();
-        backend.player.append(source);
-        backend.player.play();
-        self.total = total;
+        backend.player.append(chain);
+        if paused {
+            backend.player.pause();
+        } else {
+            backend.player.play();
+        }
+        backend.chain = Some(handle);
+        self.current = Some(loaded);
+        self.upcoming = None;
+        Ok(())
+    }
+
+    pub fn queue_next(&mut self, track: &Track, crossfade: bool) -> Result<()> {
+        if self.backend.as_ref().is_none_or(|backend| backend.chain.is_none()) {
+            return Err(io::Error::other("nothing is playing"));
+        }
+        let (entry, loaded) = self.load(track, crossfade)?;
+        let Some(chain) = self.backend.as_ref().and_then(|backend| backend.chain.as_ref()) else {
+            return Err(io::Error::other("nothing is playing"));
+        };
+        *chain.next.lock().unwrap_or_else(|error| error.into_inner()) = Some(entry);
+        self.upcoming = Some(loaded);
         Ok(())
     }
 
+    pub fn clear_next(&mut self) -> bool {
+        let Some(chain) = self.backend.as_ref().and_then(|backend| backend.chain.as_ref()) else {
+            self.upcoming = None;
+            return true;
+        };
+        let removed = chain
+            .next
+            .lock()
+            .unwrap_or_else(|error| error.into_inner())
+            .take()
+            .is_some();
+        if removed || self.upcoming.is_none() {
+            self.upcoming = None;
+            return true;
+        }
+        false
+    }
+
+    pub fn take_advanced(&mut self) -> bool {
+        let Some(upcoming) = self.upcoming else {
+            return false;
+        };
+        let Some(chain) = self.backend.as_ref().and_then(|backend| backend.chain.as_ref()) else {
+            return false;
+        };
+        if chain.timeline.entry.load(Ordering::Relaxed) != upcoming.id {
+            return false;
+        }
+        self.current = self.upcoming.take();
+        true
+    }
+
     pub fn pause(&self) {
         if let Some(backend) = &self.backend {
             backend.player.pause();
@@ -76,7 +393,8 @@ impl AudioEngine {
         if let Some(backend) = self.backend.take() {
             backend.player.stop();
         }
-        self.total = None;
+        self.current = None;
+        self.upcoming = None;
     }
 
     pub fn set_volume(&mut self, volume: u8) {
@@ -87,13 +405,19 @@ impl AudioEngine {
     }
 
     pub fn position(&self) -> Duration {
-        self.backend
-            .as_ref()
-            .map_or(Duration::ZERO, |backend| backend.player.get_pos())
+        let Some(backend) = &self.backend else {
+            return Duration::ZERO;
+        };
+        let Some(chain) = &backend.chain else {
+            return Duration::ZERO;
+        };
+        let samples = chain.timeline.samples.load(Ordering::Relaxed);
+        let rate = backend.sample_rate.get() as u64 * backend.channels.get() as u64;
+        Duration::from_secs_f64(samples as f64 / rate as f64)
     }
 
     pub fn total(&self) -> Option<Duration> {
-        self.total
+        self.current.and_then(|current| current.total)
     }
 
     pub fn is_empty(&self) -> bool {
@@ -111,15 +435,281 @@ impl AudioEngine {
         let Some(backend) = &self.backend else {
             return Err(io::Error::other("audio backend is not active"));
         };
-        let target = match self.total {
+        let target = match self.total() {
             Some(total) => seconds.clamp(0.0, total.as_secs_f64()),
             None => seconds.max(0.0),
         };
 
+        let target = Duration::try_from_secs_f64(target)
+            .map_err(|_| io::Error::other(format!("invalid seek position: {seconds}")))?;
         backend
             .player
-            .try_seek(Duration::from_secs_f64(target))
+            .try_seek(target)
             .map_err(|error| io::Error::other(format!("seek failed: {error}")))?;
         Ok(())
     }
 }
+
+struct Chain {
+    channels: ChannelCount,
+    sample_rate: SampleRate,
+    fade_samples: u64,
+    fade_curve: FadeCurve,
+    gain: Arc<GainControl>,
+    gain_version: u64,
+    current: Option<Entry>,
+    played: u64,
+    emitted: u64,
+    fade: Option<Fade>,
+    handle: ChainHandle,
+}
+
+struct Fade {
+    incoming: Entry,
+    position: u64,
+    length: u64,
+}
+
+impl Chain {
+    fn take_next(&mut self, crossfade_only: bool) -> Option<Entry> {
+        let mut next = self
+            .handle
+            .next
+            .lock()
+            .unwrap_or_else(|error| error.into_inner());
+        if crossfade_only && !next.as_ref().is_some_and(|entry| entry.crossfade) {
+            return None;
+        }
+        let entry = next.take()?;
+        self.handle.cut_in.store(false, Ordering::Relaxed);
+        self.handle.timeline.samples.store(0, Ordering::Relaxed);
+        self.handle.timeline.entry.store(entry.id, Ordering::Relaxed);
+        Some(entry)
+    }
+
+    fn advance(&mut self) -> bool {
+        let Some(next) = self.take_next(false) else {
+            self.current = None;
+            return false;
+        };
+        self.current = Some(next);
+        self.played = 0;
+        true
+    }
+
+    fn refresh_gain(&mut self) {
+        let version = self.gain.version.load(Ordering::Acquire);
+        if version == self.gain_version {
+            return;
+        }
+        self.gain_version = version;
+        let entries = self
+            .current
+            .iter_mut()
+            .chain(self.fade.iter_mut().map(|fade| &mut fade.incoming));
+        for entry in entries {
+            entry.factor = self.gain.factor(&entry.replay_gain);
+        }
+    }
+
+    fn finish_fade(&mut self) {
+        if let Some(fade) = self.fade.take() {
+            self.current = Some(fade.incoming);
+            self.played = fade.position;
+        }
+    }
+
+    fn start_fade(&mut self) {
+        let channels = self.channels.get() as u64;
+        if self.current.is_none() || !self.emitted.is_multiple_of(channels) {
+            return;
+        }
+
+        let cut_in = self.handle.cut_in.swap(false, Ordering::Acquire);
+        if cut_in {
+            self.finish_fade();
+        } else if self.fade.is_some() || self.fade_samples == 0 {
+            return;
+        }
+
+        let remaining = self
+            .current
+            .as_ref()
+            .and_then(|current| current.total_samples)
+            .map(|total| total.saturating_sub(self.played));
+        if !cut_in {
+            let Some(remaining) = remaining else {
+                return;
+            };
+            let frame = self.played / channels;
+            if remaining > self.fade_samples || !frame.is_multiple_of(512) {
+                return;
+            }
+        }
+
+        let Some(incoming) = self.take_next(!cut_in) else {
+            return;
+        };
+        let length = remaining.map_or(self.fade_samples, |remaining| remaining.min(self.fade_samples));
+        let length = length / channels * channels;
+        if length == 0 {
+            self.current = Some(incoming);
+            self.played = 0;
+            return;
+        }
+        self.fade = Some(Fade {
+            incoming,
+            position: 0,
+            length,
+        });
+    }
+}
+
+impl Iterator for Chain {
+    type Item = Sample;
+
+    fn next(&mut self) -> Option<Sample> {
+        loop {
+            self.refresh_gain();
+            self.start_fade();
+            let current = self.current.as_mut()?;
+            let sample = current.source.next().map(|sample| sample * current.factor);
+
+            if let Some(fade) = self.fade.as_mut() {
+                let progress = fade.position as f32 / fade.length as f32;
+                let incoming = fade
+                    .incoming
+                    .source
+                    .next()
+                    .map_or(0.0, |sample| sample * fade.incoming.factor);
+                let outgoing = sample.unwrap_or(0.0);
+                fade.position += 1;
+                self.played += 1;
+                self.emitted += 1;
+                self.handle.timeline.samples.fetch_add(1, Ordering::Relaxed);
+                if sample.is_none() || fade.position >= fade.length {
+                    self.finish_fade();
+                }
+                return Some(
+                    outgoing * self.fade_curve.gain(1.0 - progress)
+                        + incoming * self.fade_curve.gain(progress),
+                );
+            }
+
+            if let Some(sample) = sample {
+                self.played += 1;
+                self.emitted += 1;
+                self.handle.timeline.samples.fetch_add(1, Ordering::Relaxed);
+                return Some(sample);
+            }
+            if !self.advance() {
+                return None;
+            }
+        }
+    }
+}
+
+impl Source for Chain {
+    fn current_span_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> ChannelCount {
+        self.channels
+    }
+
+    fn sample_rate(&self) -> SampleRate {
+        self.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+
+    fn try_seek(&mut self, position: Duration) -> std::result::Result<(), SeekError> {
+        self.finish_fade();
+        let Some(current) = self.current.as_mut() else {
+            return Ok(());
+        };
+        current.source.try_seek(position)?;
+        self.played = samples_for(position, self.channels, self.sample_rate);
+        self.handle
+            .timeline
+            .samples
+            .store(self.played, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+struct Clip<S> {
+    source: S,
+    start: Duration,
+    length: Option<u64>,
+    remaining: Option<u64>,
+}
+
+impl<S: Source> Clip<S> {
+    fn new(mut source: S, start: Duration, length: Option<Duration>) -> Self {
+        let (channels, sample_rate) = (source.channels(), source.sample_rate());
+        if !start.is_zero() && source.try_seek(start).is_err() {
+            for _ in 0..samples_for(start, channels, sample_rate) {
+                if source.next().is_none() {
+                    break;
+                }
+            }
+        }
+        let length = length.map(|length| samples_for(length, channels, sample_rate));
+        Self {
+            source,
+            start,
+            length,
+            remaining: length,
+        }
+    }
+}
+
+impl<S: Source> Iterator for Clip<S> {
+    type Item = Sample;
+
+    fn next(&mut self) -> Option<Sample> {
+        if let Some(remaining) = &mut self.remaining {
+            if *remaining == 0 {
+                return None;
+            }
+            *remaining -= 1;
+        }
+        self.source.next()
+    }
+}
+
+impl<S: Source> Source for Clip<S> {
+    fn current_span_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> ChannelCount {
+        self.source.channels()
+    }
+
+    fn sample_rate(&self) -> SampleRate {
+        self.source.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        let length = self.length?;
+        let rate = self.sample_rate().get() as u64 * self.channels().get() as u64;
+        Some(Duration::from_secs_f64(length as f64 / rate as f64))
+    }
+
+    fn try_seek(&mut self, position: Duration) -> std::result::Result<(), SeekError> {
+        self.source.try_seek(self.start + position)?;
+        let played = samples_for(position, self.channels(), self.sample_rate());
+        self.remaining = self.length.map(|length| length.saturating_sub(played));
+        Ok(())
+    }
+}
+
+fn samples_for(duration: Duration, channels: ChannelCount, sample_rate: SampleRate) -> u64 {
+    let frames = (duration.as_secs_f64() * sample_rate.get() as f64) as u64;
+    frames * channels.get() as u64
+}
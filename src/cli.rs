@@ -0,0 +1,852 @@
+use std::{
+    collections::HashMap,
+    ffi::OsString,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    Result,
+    app::{self, LaunchOptions, Theme},
+    history::PlayHistory,
+    library::{self, Track},
+    playlist_file::{self, PlaylistFormat},
+    scrobble::Scrobbler,
+    smart::{Rule, SmartPlaylist, Sort},
+    storage,
+};
+
+pub const USAGE: &str = "\
+Usage: musa [OPTIONS] [FILE|DIR|PLAYLIST]
+       musa [OPTIONS] scan
+       musa [OPTIONS] list tracks|albums|artists [--format tsv|json]
+       musa [OPTIONS] playlist list
+       musa [OPTIONS] playlist export NAME [OUTPUT] [--format m3u|pls|xspf]
+       musa [OPTIONS] playlist import FILE [NAME]
+       musa [OPTIONS] playlist smart NAME [RULE...] [--sort FIELD[:desc]] [--limit N]
+       musa [OPTIONS] scrobble
+       musa [OPTIONS] ctl COMMAND [ARGUMENT]
+       musa [OPTIONS] daemon [--detach]
+       musa [OPTIONS] attach
+
+Options:
+      --config-dir DIR   Read and write settings in DIR
+      --add-root DIR     Add DIR to the saved library folders (repeatable)
+      --rescan           Discard cached metadata and read every file again
+      --theme THEME      Save and use the dark or light theme
+      --volume PERCENT   Start at the given volume (0-100)
+      --mpd-port PORT    Serve the MPD protocol on localhost:PORT
+      --detach           Run the daemon in the background
+      --sort FIELD       Order a smart playlist; append :desc to reverse it
+      --limit N          Keep only the first N songs of a smart playlist
+  -h, --help             Show this help
+  -V, --version          Show the version";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ListKind {
+    Tracks,
+    Albums,
+    Artists,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Tsv,
+    Json,
+}
+
+#[derive(Debug)]
+pub enum Command {
+    Tui(LaunchOptions),
+    Scan,
+    List {
+        kind: ListKind,
+        format: Format,
+    },
+    PlaylistList,
+    PlaylistExport {
+        name: String,
+        output: Option<PathBuf>,
+        format: Option<PlaylistFormat>,
+    },
+    PlaylistImport {
+        file: PathBuf,
+        name: Option<String>,
+    },
+    PlaylistSmart {
+        name: String,
+        smart: SmartPlaylist,
+    },
+    Scrobble,
+    Ctl(Vec<OsString>),
+    Daemon {
+        options: LaunchOptions,
+        detach: bool,
+    },
+    Attach,
+    Help,
+    Version,
+}
+
+#[derive(Debug)]
+pub struct Invocation {
+    pub config_dir: Option<PathBuf>,
+    pub add_roots: Vec<PathBuf>,
+    pub rescan: bool,
+    pub theme: Option<Theme>,
+    pub command: Command,
+}
+
+pub fn parse(args: impl IntoIterator<Item = OsString>) -> std::result::Result<Invocation, String> {
+    let mut config_dir = None;
+    let mut add_roots = Vec::new();
+    let mut rescan = false;
+    let mut theme = None;
+    let mut volume = None;
+    let mut mpd_port = None;
+    let mut format = None;
+    let mut detach = false;
+    let mut sort = None;
+    let mut limit = None;
+    let mut positional: Vec<OsString> = Vec::new();
+    let mut help = false;
+    let mut version = false;
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if positional.first().is_some_and(|first| first == "ctl") {
+            positional.push(arg);
+            continue;
+        }
+        let Some(text) = arg
+            .to_str()
+            .filter(|text| text.starts_with('-') && *text != "-")
+        else {
+            positional.push(arg);
+            continue;
+        };
+        if text == "--" {
+            positional.extend(args.by_ref());
+            break;
+        }
+
+        let (name, inline) = match text.split_once('=') {
+            Some((name, value)) => (name.to_owned(), Some(OsString::from(value))),
+            None => (text.to_owned(), None),
+        };
+        let mut value = |name: &str| {
+            inline
+                .clone()
+                .or_else(|| args.next())
+                .ok_or_else(|| format!("{name} needs a value"))
+        };
+
+        match name.as_str() {
+            "-h" | "--help" => help = true,
+            "-V" | "--version" => version = true,
+            "--rescan" => rescan = true,
+            "--detach" => detach = true,
+            "--config-dir" => config_dir = Some(PathBuf::from(value(&name)?)),
+            "--add-root" => add_roots.push(PathBuf::from(value(&name)?)),
+            "--theme" => {
+                theme = Some(match value(&name)?.to_str() {
+                    Some("dark") => Theme::Dark,
+                    Some("light") => Theme::Light,
+                    _ => return Err("--theme must be dark or light".to_owned()),
+                });
+            }
+            "--volume" => {
+                let parsed = value(&name)?
+                    .to_str()
+                    .and_then(|text| text.trim_end_matches('%').parse::<u8>().ok())
+                    .filter(|volume| *volume <= 100);
+                volume = Some(parsed.ok_or("--volume must be between 0 and 100")?);
+            }
+            "--mpd-port" => {
+                let parsed = value(&name)?
+                    .to_str()
+                    .and_then(|text| text.parse::<u16>().ok())
+                    .filter(|port| *port != 0);
+                mpd_port = Some(parsed.ok_or("--mpd-port must be between 1 and 65535")?);
+            }
+            "--format" => {
+                format = Some(
+                    value(&name)?
+                        .to_str()
+                        .ok_or("--format must be tsv, json, m3u, pls, or xspf")?
+                        .to_ascii_lowercase(),
+                );
+            }
+            "--sort" => {
+                let text = value(&name)?;
+                sort = Some(Sort::parse(text.to_str().unwrap_or_default())?);
+            }
+            "--limit" => {
+                let parsed = value(&name)?
+                    .to_str()
+                    .and_then(|text| text.parse::<usize>().ok())
+                    .filter(|limit| *limit > 0);
+                limit = Some(parsed.ok_or("--limit must be a positive number")?);
+            }
+            _ => return Err(format!("unknown option {name}")),
+        }
+    }
+
+    let words: Vec<Option<&str>> = positional.iter().map(|arg| arg.to_str()).collect();
+    let command = if help {
+        Command::Help
+    } else if version {
+        Command::Version
+    } else {
+        match words.as_slice() {
+            [] => Command::Tui(LaunchOptions {
+                volume,
+                open: None,
+                mpd_port,
+            }),
+            [Some("scan")] => Command::Scan,
+            [Some("scrobble")] => Command::Scrobble,
+            [Some("help")] => Command::Help,
+            [Some("list"), Some(kind)] => Command::List {
+                kind: match *kind {
+                    "tracks" | "songs" => ListKind::Tracks,
+                    "albums" => ListKind::Albums,
+                    "artists" => ListKind::Artists,
+                    _ => {
+                        return Err(format!(
+                            "cannot list {kind}; use tracks, albums, or artists"
+                        ));
+                    }
+                },
+                format: match format.as_deref() {
+                    None | Some("tsv") => Format::Tsv,
+                    Some("json") => Format::Json,
+                    Some(_) => return Err("list --format must be tsv or json".to_owned()),
+                },
+            },
+            [Some("list"), ..] => return Err("list needs tracks, albums, or artists".to_owned()),
+            [Some("playlist"), Some("list")] => Command::PlaylistList,
+            [Some("playlist"), Some("export"), Some(name)] => Command::PlaylistExport {
+                name: (*name).to_owned(),
+                output: None,
+                format: playlist_format(format.as_deref())?,
+            },
+            [Some("playlist"), Some("export"), Some(name), _] => Command::PlaylistExport {
+                name: (*name).to_owned(),
+                output: Some(PathBuf::from(&positional[3])).filter(|path| path != Path::new("-")),
+                format: playlist_format(format.as_deref())?,
+            },
+            [Some("playlist"), Some("import"), _] => Command::PlaylistImport {
+                file: PathBuf::from(&positional[2]),
+                name: None,
+            },
+            [Some("playlist"), Some("import"), _, Some(name)] => Command::PlaylistImport {
+                file: PathBuf::from(&positional[2]),
+                name: Some((*name).to_owned()),
+            },
+            [Some("playlist"), Some("smart"), Some(name), rules @ ..] => Command::PlaylistSmart {
+                name: (*name).to_owned(),
+                smart: SmartPlaylist {
+                    rules: rules
+                        .iter()
+                        .map(|rule| Rule::parse(rule.ok_or("rules must be valid UTF-8")?))
+                        .collect::<std::result::Result<_, _>>()?,
+                    sort: sort.unwrap_or_default(),
+                    limit,
+                },
+            },
+            [Some("ctl"), ..] => Command::Ctl(positional[1..].to_vec()),
+            [Some("daemon")] => Command::Daemon {
+                options: LaunchOptions {
+                    volume,
+                    open: None,
+                    mpd_port,
+                },
+                detach,
+            },
+            [Some("attach")] => Command::Attach,
+            [Some("playlist"), ..] => {
+                return Err(
+                    "playlist needs list, export NAME [OUTPUT], import FILE [NAME], or smart NAME [RULE...]"
+                        .to_owned(),
+                );
+            }
+            [_] => Command::Tui(LaunchOptions {
+                volume,
+                open: Some(PathBuf::from(&positional[0])),
+                mpd_port,
+            }),
+            _ => return Err("too many arguments".to_owned()),
+        }
+    };
+
+    if format.is_some() && !matches!(command, Command::List { .. } | Command::PlaylistExport { .. }) {
+        return Err("--format only applies to list and playlist export".to_owned());
+    }
+    if detach && !matches!(command, Command::Daemon { .. }) {
+        return Err("--detach only applies to daemon".to_owned());
+    }
+    if (sort.is_some() || limit.is_some()) && !matches!(command, Command::PlaylistSmart { .. }) {
+        return Err("--sort and --limit only apply to playlist smart".to_owned());
+    }
+
+    Ok(Invocation {
+        config_dir,
+        add_roots,
+        rescan,
+        theme,
+        command,
+    })
+}
+
+pub fn apply(invocation: &Invocation) -> Result<()> {
+    if let Some(dir) = &invocation.config_dir {
+        storage::set_config_dir(dir.clone());
+    }
+
+    if !invocation.add_roots.is_empty() {
+        let mut roots = storage::load_roots();
+        for root in &invocation.add_roots {
+            let root = root.canonicalize().map_err(|error| {
+                io::Error::other(format!("cannot add {}: {error}", root.display()))
+            })?;
+            if !root.is_dir() {
+                return Err(io::Error::other(format!(
+                    "{} is not a folder",
+                    root.display()
+                )));
+            }
+            if !roots.contains(&root) {
+                roots.push(root);
+            }
+        }
+        roots.sort();
+        storage::save_roots(&roots)?;
+    }
+
+    if let Some(theme) = invocation.theme {
+        storage::save_theme(theme)?;
+    }
+    if invocation.rescan {
+        storage::clear_track_cache()?;
+    }
+    Ok(())
+}
+
+pub fn run(command: Command) -> Result<()> {
+    match command {
+        Command::Tui(_) => Ok(()),
+        Command::Help => {
+            println!("{USAGE}");
+            Ok(())
+        }
+        Command::Version => {
+            println!("musa {}", env!("CARGO_PKG_VERSION"));
+            Ok(())
+        }
+        Command::Scan => scan(),
+        Command::List { kind, format } => list(kind, format),
+        Command::PlaylistList => {
+            let playlists = storage::load_playlists();
+            let (tracks, history) = if playlists.iter().any(|playlist| playlist.smart.is_some()) {
+                (cached_tracks(), PlayHistory::load())
+            } else {
+                (Vec::new(), PlayHistory::default())
+            };
+            let mut output = io::stdout().lock();
+            for playlist in playlists {
+                writeln!(
+                    output,
+                    "{}\t{}",
+                    clean_field(&playlist.name),
+                    playlist_paths(&playlist, &tracks, &history).len()
+                )?;
+            }
+            Ok(())
+        }
+        Command::PlaylistExport {
+            name,
+            output,
+            format,
+        } => export_playlist(&name, output.as_deref(), format),
+        Command::PlaylistImport { file, name } => import_playlist(&file, name.as_deref()),
+        Command::PlaylistSmart { name, smart } => create_smart_playlist(&name, smart),
+        Command::Scrobble => scrobble(),
+        Command::Ctl(arguments) => ctl(&arguments),
+        Command::Daemon { .. } => detach_daemon(),
+        Command::Attach => attach(),
+    }
+}
+
+fn scrobble() -> Result<()> {
+    let settings = storage::load_settings().scrobble;
+    if settings.services().is_empty() {
+        return Err(io::Error::other(
+            "no scrobbling service is configured in settings.txt",
+        ));
+    }
+    // A running instance owns scrobbles.txt; flushing a second copy of the
+    // queue here would submit its batches twice and overwrite its file.
+    #[cfg(unix)]
+    {
+        use crate::control;
+
+        let path = storage::control_socket_path();
+        if control::is_running(&path) {
+            let reply = control::send(&path, "scrobble")?;
+            let queued = reply.strip_prefix("queued\t").unwrap_or(&reply);
+            println!("The running instance is submitting {queued} queued scrobbles");
+            return Ok(());
+        }
+    }
+    let mut scrobbler = Scrobbler::new(settings, storage::scrobbles_path());
+    let (sent, messages) = scrobbler.flush();
+    for message in &messages {
+        eprintln!("{message}");
+    }
+    println!("Submitted {sent} scrobbles, {} queued", scrobbler.pending());
+    Ok(())
+}
+
+#[cfg(unix)]
+fn detach_daemon() -> Result<()> {
+    use std::{
+        env,
+        os::unix::process::CommandExt,
+        process::{self, Stdio},
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use crate::control;
+
+    let path = storage::control_socket_path();
+    if control::is_running(&path) {
+        return Err(io::Error::other(format!(
+            "another instance is listening on {}",
+            path.display()
+        )));
+    }
+
+    let mut child = process::Command::new(env::current_exe()?)
+        .args(env::args_os().skip(1).filter(|arg| arg != "--detach"))
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .process_group(0)
+        .spawn()?;
+
+    let deadline = Instant::now() + Duration::from_secs(3);
+    while Instant::now() < deadline {
+        if let Some(status) = child.try_wait()? {
+            return Err(io::Error::other(format!("the daemon exited early ({status})")));
+        }
+        if control::is_running(&path) {
+            println!("musa daemon running as process {}", child.id());
+            return Ok(());
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+    Err(io::Error::other("the daemon did not start in time"))
+}
+
+#[cfg(unix)]
+fn attach() -> Result<()> {
+    crate::control::attach(&storage::control_socket_path())
+}
+
+#[cfg(not(unix))]
+fn detach_daemon() -> Result<()> {
+    Err(io::Error::other(
+        "daemon mode is only available on Unix-like systems",
+    ))
+}
+
+#[cfg(not(unix))]
+fn attach() -> Result<()> {
+    Err(io::Error::other(
+        "daemon mode is only available on Unix-like systems",
+    ))
+}
+
+#[cfg(unix)]
+fn ctl(arguments: &[OsString]) -> Result<()> {
+    use crate::control;
+
+    let Some((name, rest)) = arguments.split_first() else {
+        println!("{}", control::COMMANDS);
+        return Ok(());
+    };
+    let name = name.to_string_lossy();
+    if matches!(name.as_ref(), "help" | "-h" | "--help") {
+        println!("{}", control::COMMANDS);
+        return Ok(());
+    }
+
+    let mut line = name.into_owned();
+    if matches!(line.as_str(), "add" | "insert") && rest.len() == 1 {
+        let path = std::path::absolute(&rest[0])?;
+        line.push(' ');
+        line.push_str(&path.to_string_lossy());
+    } else {
+        for argument in rest {
+            line.push(' ');
+            line.push_str(&argument.to_string_lossy());
+        }
+    }
+
+    let reply = control::send(&storage::control_socket_path(), &line)?;
+    if !reply.is_empty() {
+        println!("{reply}");
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn ctl(_arguments: &[OsString]) -> Result<()> {
+    Err(io::Error::other(
+        "remote control is only available on Unix-like systems",
+    ))
+}
+
+fn load_library(progress: bool) -> Result<(Vec<Track>, library::LibraryDiff)> {
+    let roots = storage::load_roots();
+    if roots.is_empty() {
+        return Err(io::Error::other(
+            "no library folders saved; add one with --add-root DIR",
+        ));
+    }
+
+    let settings = storage::load_settings();
+    let mut stderr = io::stderr();
+    let result = library::load_library(&roots, settings.scan_threads, |done, total| {
+        if progress {
+            let _ = write!(stderr, "\rReading metadata: {done}/{total}");
+        }
+    });
+    if progress {
+        let _ = writeln!(stderr);
+    }
+    result
+}
+
+fn scan() -> Result<()> {
+    let (tracks, diff) = load_library(true)?;
+    println!(
+        "{} songs in library ({} added, {} changed, {} removed)",
+        tracks.len(),
+        diff.added.len(),
+        diff.changed.len(),
+        diff.removed.len()
+    );
+    Ok(())
+}
+
+fn list(kind: ListKind, format: Format) -> Result<()> {
+    let (tracks, _) = load_library(false)?;
+    let credits = app::ArtistCredits::new(&storage::load_settings());
+    let mut output = BufWriter::new(io::stdout().lock());
+
+    let rows: Vec<Vec<(&str, Value)>> = match kind {
+        ListKind::Tracks => tracks
+            .iter()
+            .map(|track| {
+                vec![
+                    (
+                        "path",
+                        Value::Text(track.path.to_string_lossy().into_owned()),
+                    ),
+                    ("title", Value::Text(track.title.to_string())),
+                    ("artist", Value::Text(track.artist.to_string())),
+                    ("album_artist", Value::Text(track.album_artist.to_string())),
+                    ("album", Value::Text(track.album.to_string())),
+                    ("disc", Value::Number(track.disc_no.map(f64::from))),
+                    ("track", Value::Number(track.track_no.map(f64::from))),
+                    (
+                        "duration",
+                        Value::Number(track.duration.map(|duration| duration.as_secs_f64())),
+                    ),
+                ]
+            })
+            .collect(),
+        ListKind::Albums => {
+            let (albums, _) = app::group_library(&tracks, &credits);
+            albums
+                .iter()
+                .map(|album| {
+                    vec![
+                        ("title", Value::Text(album.title.to_string())),
+                        ("artist", Value::Text(album.artist.to_string())),
+                        ("tracks", Value::Number(Some(album.tracks.len() as f64))),
+                        (
+                            "duration",
+                            Value::Number(Some(album.duration.as_secs_f64())),
+                        ),
+                    ]
+                })
+                .collect()
+        }
+        ListKind::Artists => {
+            let (_, artists) = app::group_library(&tracks, &credits);
+            artists
+                .iter()
+                .map(|artist| {
+                    vec![
+                        ("name", Value::Text(artist.name.to_string())),
+                        ("albums", Value::Number(Some(artist.album_count as f64))),
+                        ("tracks", Value::Number(Some(artist.tracks.len() as f64))),
+                    ]
+                })
+                .collect()
+        }
+    };
+
+    match format {
+        Format::Tsv => write_tsv(&mut output, &rows)?,
+        Format::Json => write_json(&mut output, &rows)?,
+    }
+    output.flush()
+}
+
+fn playlist_format(name: Option<&str>) -> std::result::Result<Option<PlaylistFormat>, String> {
+    name.map(|name| {
+        PlaylistFormat::from_name(name)
+            .ok_or_else(|| "playlist export --format must be m3u, pls, or xspf".to_owned())
+    })
+    .transpose()
+}
+
+fn import_playlist(file: &Path, name: Option<&str>) -> Result<()> {
+    let entries = playlist_file::read_entries(file)?;
+    let (tracks, _) = load_library(false)?;
+    let mut path_order: Vec<usize> = (0..tracks.len()).collect();
+    path_order.sort_by(|left, right| tracks[*left].path.cmp(&tracks[*right].path));
+
+    let indices = app::match_playlist_entries(&tracks, &path_order, &entries);
+    if indices.is_empty() {
+        return Err(io::Error::other(format!(
+            "none of the {} entries in {} are in the library",
+            entries.len(),
+            file.display()
+        )));
+    }
+
+    let mut playlists = storage::load_playlists();
+    let name = match name {
+        Some(name) => {
+            let name = name.trim();
+            if name.is_empty() {
+                return Err(io::Error::other("playlist name cannot be empty"));
+            }
+            if playlists
+                .iter()
+                .any(|playlist| playlist.name.eq_ignore_ascii_case(name))
+            {
+                return Err(io::Error::other(format!("playlist '{name}' already exists")));
+            }
+            name.to_owned()
+        }
+        None => {
+            let stem = file
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().trim().to_owned())
+                .filter(|stem| !stem.is_empty())
+                .unwrap_or_else(|| "Imported".to_owned());
+            app::unique_playlist_name(playlists.iter().map(|playlist| playlist.name.as_str()), &stem)
+        }
+    };
+
+    playlists.push(storage::StoredPlaylist {
+        name: name.clone(),
+        tracks: indices
+            .iter()
+            .map(|index| tracks[*index].path.clone())
+            .collect(),
+        smart: None,
+    });
+    save_stored_playlists(&playlists)?;
+    println!(
+        "Imported '{name}' with {} of {} songs",
+        indices.len(),
+        entries.len()
+    );
+    Ok(())
+}
+
+fn export_playlist(name: &str, output: Option<&Path>, format: Option<PlaylistFormat>) -> Result<()> {
+    let Some(playlist) = storage::load_playlists()
+        .into_iter()
+        .find(|playlist| playlist.name.eq_ignore_ascii_case(name))
+    else {
+        return Err(io::Error::other(format!("no playlist named '{name}'")));
+    };
+
+    let tracks = cached_tracks();
+    let paths = playlist_paths(&playlist, &tracks, &PlayHistory::load());
+    let cache: HashMap<&Path, &Track> = tracks
+        .iter()
+        .map(|track| (track.path.as_path(), track))
+        .collect();
+    let entries = paths
+        .iter()
+        .map(|path| (path.as_path(), cache.get(path.as_path()).copied()));
+
+    let skipped = match output {
+        Some(path) => {
+            let format = format
+                .or_else(|| PlaylistFormat::from_path(path))
+                .unwrap_or(PlaylistFormat::M3u);
+            let mut writer = BufWriter::new(File::create(path)?);
+            let skipped = playlist_file::write(&mut writer, format, &playlist.name, entries)?;
+            writer.flush()?;
+            skipped
+        }
+        None => {
+            let format = format.unwrap_or(PlaylistFormat::M3u);
+            let mut writer = BufWriter::new(io::stdout().lock());
+            let skipped = playlist_file::write(&mut writer, format, &playlist.name, entries)?;
+            writer.flush()?;
+            skipped
+        }
+    };
+    if skipped > 0 {
+        eprintln!("skipped {skipped} CUE sheet songs other players cannot open");
+    }
+    Ok(())
+}
+
+fn create_smart_playlist(name: &str, smart: SmartPlaylist) -> Result<()> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(io::Error::other("playlist name cannot be empty"));
+    }
+    let mut playlists = storage::load_playlists();
+    if playlists
+        .iter()
+        .any(|playlist| playlist.name.eq_ignore_ascii_case(name))
+    {
+        return Err(io::Error::other(format!("playlist '{name}' already exists")));
+    }
+
+    let playlist = storage::StoredPlaylist {
+        name: name.to_owned(),
+        tracks: Vec::new(),
+        smart: Some(smart),
+    };
+    let count = playlist_paths(&playlist, &cached_tracks(), &PlayHistory::load()).len();
+    playlists.push(playlist);
+    save_stored_playlists(&playlists)?;
+    println!("Created smart playlist '{name}' matching {count} songs");
+    Ok(())
+}
+
+fn save_stored_playlists(playlists: &[storage::StoredPlaylist]) -> Result<()> {
+    storage::save_playlists(playlists.iter().map(|playlist| {
+        (
+            playlist.name.as_str(),
+            playlist.tracks.as_slice(),
+            playlist.smart.as_ref(),
+        )
+    }))
+}
+
+fn cached_tracks() -> Vec<Track> {
+    storage::load_track_cache()
+        .into_iter()
+        .map(|entry| entry.track)
+        .collect()
+}
+
+fn playlist_paths(
+    playlist: &storage::StoredPlaylist,
+    tracks: &[Track],
+    history: &PlayHistory,
+) -> Vec<PathBuf> {
+    match &playlist.smart {
+        Some(smart) => smart
+            .select(tracks, |path| history.stats(path))
+            .into_iter()
+            .map(|index| tracks[index].path.clone())
+            .collect(),
+        None => playlist.tracks.clone(),
+    }
+}
+
+enum Value {
+    Text(String),
+    Number(Option<f64>),
+}
+
+fn write_tsv(output: &mut impl Write, rows: &[Vec<(&str, Value)>]) -> io::Result<()> {
+    let Some(first) = rows.first() else {
+        return Ok(());
+    };
+    let header: Vec<&str> = first.iter().map(|(name, _)| *name).collect();
+    writeln!(output, "{}", header.join("\t"))?;
+
+    for row in rows {
+        let fields: Vec<String> = row
+            .iter()
+            .map(|(_, value)| match value {
+                Value::Text(text) => clean_field(text),
+                Value::Number(Some(number)) => format_number(*number),
+                Value::Number(None) => String::new(),
+            })
+            .collect();
+        writeln!(output, "{}", fields.join("\t"))?;
+    }
+    Ok(())
+}
+
+fn write_json(output: &mut impl Write, rows: &[Vec<(&str, Value)>]) -> io::Result<()> {
+    writeln!(output, "[")?;
+    for (index, row) in rows.iter().enumerate() {
+        let fields: Vec<String> = row
+            .iter()
+            .map(|(name, value)| {
+                let value = match value {
+                    Value::Text(text) => json_string(text),
+                    Value::Number(Some(number)) => format_number(*number),
+                    Value::Number(None) => "null".to_owned(),
+                };
+                format!("{}: {value}", json_string(name))
+            })
+            .collect();
+        let separator = if index + 1 < rows.len() { "," } else { "" };
+        writeln!(output, "  {{{}}}{separator}", fields.join(", "))?;
+    }
+    writeln!(output, "]")
+}
+
+fn format_number(number: f64) -> String {
+    if number.fract() == 0.0 {
+        format!("{number:.0}")
+    } else {
+        format!("{number:.3}")
+    }
+}
+
+fn clean_field(text: &str) -> String {
+    text.replace(['\t', '\n', '\r'], " ")
+}
+
+pub fn json_string(text: &str) -> String {
+    let mut output = String::with_capacity(text.len() + 2);
+    output.push('"');
+    for character in text.chars() {
+        match character {
+            '"' => output.push_str("\\\""),
+            '\\' => output.push_str("\\\\"),
+            '\n' => output.push_str("\\n"),
+            '\r' => output.push_str("\\r"),
+            '\t' => output.push_str("\\t"),
+            character if character.is_control() => {
+                output.push_str(&format!("\\u{:04x}", character as u32));
+            }
+            character => output.push(character),
+        }
+    }
+    output.push('"');
+    output
+}
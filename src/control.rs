@@ -0,0 +1,471 @@
+use std::{
+    fs,
+    io::{self, BufRead, BufReader, Read, Write},
+    net::Shutdown,
+    os::unix::net::{UnixListener, UnixStream},
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+        mpsc::{self, Receiver, Sender, TryRecvError},
+    },
+    thread,
+    time::Duration,
+};
+
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+
+use crate::{
+    Result,
+    app::{PlaybackState, PlayerStatus, RemoteCommand, RepeatMode},
+    storage, ui,
+};
+
+const REPLY_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub const COMMANDS: &str = "\
+Commands:
+  status                 Print the playback state and current song
+  toggle                 Play or pause
+  play | pause | stop    Control playback
+  next | previous        Skip to the next or previous song
+  seek SECONDS           Seek to a position; +N or -N seeks relative to it
+  volume PERCENT         Set the volume; +N or -N changes it
+  shuffle [on|off]       Set shuffle, or toggle it without an argument
+  repeat [off|all|one]   Set the repeat mode, or cycle it without an argument
+  add PATH               Add a song, folder, or playlist to the end of the queue
+  insert PATH            Play a song, folder, or playlist next
+  scrobble               Submit queued scrobbles now
+  quit                   Stop the player and exit";
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ControlCommand {
+    Status,
+    Scrobble,
+    Quit,
+    Remote(RemoteCommand),
+}
+
+pub enum AttachEvent {
+    Attach {
+        id: u64,
+        output: UnixStream,
+        width: u16,
+        height: u16,
+    },
+    Key {
+        id: u64,
+        key: KeyEvent,
+    },
+    Resize {
+        id: u64,
+        width: u16,
+        height: u16,
+    },
+    Detach {
+        id: u64,
+    },
+}
+
+pub struct Request {
+    pub command: ControlCommand,
+    reply: Sender<std::result::Result<String, String>>,
+}
+
+impl Request {
+    pub fn respond(self, reply: std::result::Result<String, String>) {
+        let _ = self.reply.send(reply);
+    }
+}
+
+pub struct ControlServer {
+    path: PathBuf,
+    requests: Receiver<Request>,
+    attach_events: Receiver<AttachEvent>,
+}
+
+#[derive(Clone)]
+struct Channels {
+    requests: Sender<Request>,
+    attach_events: Sender<AttachEvent>,
+    attach_ids: Arc<AtomicU64>,
+}
+
+impl ControlServer {
+    pub fn start() -> Result<Self> {
+        let path = storage::control_socket_path();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        if path.exists() {
+            if UnixStream::connect(&path).is_ok() {
+                return Err(io::Error::other(format!(
+                    "another instance is listening on {}",
+                    path.display()
+                )));
+            }
+            fs::remove_file(&path)?;
+        }
+
+        let listener = UnixListener::bind(&path)?;
+        let (request_sender, requests) = mpsc::channel();
+        let (attach_sender, attach_events) = mpsc::channel();
+        let channels = Channels {
+            requests: request_sender,
+            attach_events: attach_sender,
+            attach_ids: Arc::new(AtomicU64::new(0)),
+        };
+        thread::Builder::new()
+            .name("musa-control".to_owned())
+            .spawn(move || {
+                for stream in listener.incoming().flatten() {
+                    let channels = channels.clone();
+                    let _ = thread::Builder::new()
+                        .name("musa-control-client".to_owned())
+                        .spawn(move || serve_client(stream, &channels));
+                }
+            })?;
+
+        Ok(Self {
+            path,
+            requests,
+            attach_events,
+        })
+    }
+
+    pub fn requests(&self) -> Vec<Request> {
+        self.requests.try_iter().collect()
+    }
+
+    pub fn attach_events(&self) -> Vec<AttachEvent> {
+        self.attach_events.try_iter().collect()
+    }
+}
+
+impl Drop for ControlServer {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+fn serve_client(stream: UnixStream, channels: &Channels) {
+    let Ok(mut writer) = stream.try_clone() else {
+        return;
+    };
+    let mut lines = BufReader::new(stream).lines();
+
+    while let Some(Ok(line)) = lines.next() {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        if let Some(size) = line.strip_prefix("attach") {
+            let Some((width, height)) = parse_size(size) else {
+                let _ = writeln!(writer, "ERR attach needs a terminal width and height");
+                continue;
+            };
+            if writeln!(writer, "OK").and_then(|()| writer.flush()).is_err() {
+                return;
+            }
+            let id = channels.attach_ids.fetch_add(1, Ordering::Relaxed);
+            let attach = AttachEvent::Attach {
+                id,
+                output: writer,
+                width,
+                height,
+            };
+            if channels.attach_events.send(attach).is_err() {
+                return;
+            }
+            for line in lines.by_ref() {
+                let Ok(line) = line else {
+                    break;
+                };
+                let event = match line.split_once(' ') {
+                    Some(("key", key)) => decode_key(key).map(|key| AttachEvent::Key { id, key }),
+                    Some(("resize", size)) => parse_size(size)
+                        .map(|(width, height)| AttachEvent::Resize { id, width, height }),
+                    _ => None,
+                };
+                if let Some(event) = event {
+                    if channels.attach_events.send(event).is_err() {
+                        return;
+                    }
+                }
+            }
+            let _ = channels.attach_events.send(AttachEvent::Detach { id });
+            return;
+        }
+
+        let reply = match parse_command(&line) {
+            Ok(command) => {
+                let (reply, response) = mpsc::channel();
+                if channels.requests.send(Request { command, reply }).is_err() {
+                    return;
+                }
+                response
+                    .recv_timeout(REPLY_TIMEOUT)
+                    .unwrap_or_else(|_| Err("the player did not respond".to_owned()))
+            }
+            Err(error) => Err(error),
+        };
+
+        let written = match reply {
+            Ok(body) if body.is_empty() => writeln!(writer, "OK"),
+            Ok(body) => writeln!(writer, "{body}\nOK"),
+            Err(error) => writeln!(writer, "ERR {}", single_line(&error)),
+        };
+        if written.and_then(|()| writer.flush()).is_err() {
+            return;
+        }
+    }
+}
+
+pub fn parse_command(line: &str) -> std::result::Result<ControlCommand, String> {
+    let line = line.trim();
+    let (name, argument) = match line.split_once(char::is_whitespace) {
+        Some((name, argument)) => (name, argument.trim()),
+        None => (line, ""),
+    };
+
+    let remote = match (name.to_ascii_lowercase().as_str(), argument) {
+        ("status", "") => return Ok(ControlCommand::Status),
+        ("scrobble", "") => return Ok(ControlCommand::Scrobble),
+        ("quit", "") => return Ok(ControlCommand::Quit),
+        ("toggle" | "play-pause", "") => RemoteCommand::PlayPause,
+        ("play", "") => RemoteCommand::Play,
+        ("pause", "") => RemoteCommand::Pause,
+        ("stop", "") => RemoteCommand::Stop,
+        ("next", "") => RemoteCommand::Next,
+        ("previous" | "prev", "") => RemoteCommand::Previous,
+        ("seek", argument) if !argument.is_empty() => {
+            let seconds = argument
+                .parse::<f64>()
+                .ok()
+                .filter(|seconds| seconds.is_finite() && seconds.abs() <= f64::from(u32::MAX))
+                .ok_or_else(|| format!("invalid seek position: {argument}"))?;
+            if argument.starts_with(['+', '-']) {
+                RemoteCommand::Seek(seconds)
+            } else {
+                RemoteCommand::SetPosition(seconds)
+            }
+        }
+        ("volume", argument) if !argument.is_empty() => {
+            let value: i16 = argument
+                .trim_end_matches('%')
+                .parse()
+                .map_err(|_| format!("invalid volume: {argument}"))?;
+            if argument.starts_with(['+', '-']) {
+                RemoteCommand::ChangeVolume(value)
+            } else {
+                RemoteCommand::SetVolume(value.clamp(0, 100) as u8)
+            }
+        }
+        ("shuffle", "") => RemoteCommand::ToggleShuffle,
+        ("shuffle", "on") => RemoteCommand::SetShuffle(true),
+        ("shuffle", "off") => RemoteCommand::SetShuffle(false),
+        ("repeat", "") => RemoteCommand::CycleRepeat,
+        ("repeat", "off") => RemoteCommand::SetRepeat(RepeatMode::Off),
+        ("repeat", "all") => RemoteCommand::SetRepeat(RepeatMode::All),
+        ("repeat", "one") => RemoteCommand::SetRepeat(RepeatMode::One),
+        ("add", argument) if !argument.is_empty() => RemoteCommand::Enqueue {
+            path: PathBuf::from(argument),
+            next: false,
+        },
+        ("insert", argument) if !argument.is_empty() => RemoteCommand::Enqueue {
+            path: PathBuf::from(argument),
+            next: true,
+        },
+        _ => return Err(format!("unknown command: {line}")),
+    };
+    Ok(ControlCommand::Remote(remote))
+}
+
+pub fn format_status(status: &PlayerStatus) -> String {
+    let state = match status.state {
+        PlaybackState::Playing => "playing",
+        PlaybackState::Paused => "paused",
+        PlaybackState::Stopped => "stopped",
+    };
+    let mut lines = vec![
+        format!("state\t{state}"),
+        format!("volume\t{}", status.volume),
+        format!("shuffle\t{}", if status.shuffle { "on" } else { "off" }),
+        format!("repeat\t{}", status.repeat.label()),
+    ];
+
+    if let Some(track) = &status.track {
+        lines.push(format!("title\t{}", single_line(&track.title)));
+        lines.push(format!("artist\t{}", single_line(&track.artist)));
+        lines.push(format!("album\t{}", single_line(&track.album)));
+        lines.push(format!("path\t{}", single_line(&track.path.to_string_lossy())));
+        lines.push(format!("position\t{:.1}", status.position.as_secs_f64()));
+        if let Some(total) = status.total.or(track.duration) {
+            lines.push(format!("duration\t{:.1}", total.as_secs_f64()));
+        }
+    }
+    lines.join("\n")
+}
+
+pub fn send(path: &Path, line: &str) -> Result<String> {
+    let mut stream = UnixStream::connect(path).map_err(|error| {
+        io::Error::other(format!(
+            "cannot reach a running musa at {}: {error}",
+            path.display()
+        ))
+    })?;
+    writeln!(stream, "{}", single_line(line))?;
+    stream.flush()?;
+
+    let mut body = Vec::new();
+    for response in BufReader::new(stream).lines() {
+        let response = response?;
+        if response == "OK" {
+            return Ok(body.join("\n"));
+        }
+        if let Some(error) = response.strip_prefix("ERR ") {
+            return Err(io::Error::other(error.to_owned()));
+        }
+        body.push(response);
+    }
+    Err(io::Error::other("connection closed without a reply"))
+}
+
+pub fn is_running(path: &Path) -> bool {
+    UnixStream::connect(path).is_ok()
+}
+
+pub fn attach(path: &Path) -> Result<()> {
+    let mut stream = UnixStream::connect(path).map_err(|error| {
+        io::Error::other(format!(
+            "cannot reach a running musa at {}: {error}",
+            path.display()
+        ))
+    })?;
+    let (width, height) = crossterm::terminal::size()?;
+    writeln!(stream, "attach {width} {height}")?;
+
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut reply = String::new();
+    reader.read_line(&mut reply)?;
+    if let Some(error) = reply.trim_end().strip_prefix("ERR ") {
+        return Err(io::Error::other(error.to_owned()));
+    }
+
+    let terminal = ui::Terminal::enter()?;
+    let (done, finished) = mpsc::channel::<()>();
+    thread::spawn(move || {
+        let mut output = io::stdout();
+        let mut buffer = [0; 16 * 1024];
+        loop {
+            match reader.read(&mut buffer) {
+                Ok(0) | Err(_) => break,
+                Ok(read) => {
+                    if output
+                        .write_all(&buffer[..read])
+                        .and_then(|()| output.flush())
+                        .is_err()
+                    {
+                        break;
+                    }
+                }
+            }
+        }
+        drop(done);
+    });
+
+    let result = loop {
+        if !matches!(finished.try_recv(), Err(TryRecvError::Empty)) {
+            break Ok(());
+        }
+        match event::poll(Duration::from_millis(50)) {
+            Ok(false) => continue,
+            Ok(true) => {}
+            Err(error) => break Err(error),
+        }
+        let message = match event::read() {
+            Ok(Event::Key(key))
+                if matches!(key.kind, KeyEventKind::Press | KeyEventKind::Repeat) =>
+            {
+                encode_key(&key).map(|key| format!("key {key}"))
+            }
+            Ok(Event::Resize(width, height)) => Some(format!("resize {width} {height}")),
+            Ok(_) => None,
+            Err(error) => break Err(error),
+        };
+        if let Some(message) = message {
+            if writeln!(stream, "{message}").is_err() {
+                break Ok(());
+            }
+        }
+    };
+
+    let _ = stream.shutdown(Shutdown::Both);
+    drop(terminal);
+    result
+}
+
+fn parse_size(text: &str) -> Option<(u16, u16)> {
+    let mut parts = text.split_whitespace().map(str::parse::<u16>);
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(Ok(width)), Some(Ok(height)), None) => Some((width, height)),
+        _ => None,
+    }
+}
+
+fn encode_key(key: &KeyEvent) -> Option<String> {
+    let code = match key.code {
+        KeyCode::Char(character) => format!("char:{}", character as u32),
+        KeyCode::F(number) => format!("f:{number}"),
+        KeyCode::Backspace => "backspace".to_owned(),
+        KeyCode::Enter => "enter".to_owned(),
+        KeyCode::Left => "left".to_owned(),
+        KeyCode::Right => "right".to_owned(),
+        KeyCode::Up => "up".to_owned(),
+        KeyCode::Down => "down".to_owned(),
+        KeyCode::Home => "home".to_owned(),
+        KeyCode::End => "end".to_owned(),
+        KeyCode::PageUp => "pageup".to_owned(),
+        KeyCode::PageDown => "pagedown".to_owned(),
+        KeyCode::Tab => "tab".to_owned(),
+        KeyCode::BackTab => "backtab".to_owned(),
+        KeyCode::Delete => "delete".to_owned(),
+        KeyCode::Insert => "insert".to_owned(),
+        KeyCode::Esc => "esc".to_owned(),
+        _ => return None,
+    };
+    Some(format!("{code} {}", key.modifiers.bits()))
+}
+
+fn decode_key(text: &str) -> Option<KeyEvent> {
+    let (code, modifiers) = text.split_once(' ')?;
+    let modifiers = KeyModifiers::from_bits_truncate(modifiers.parse().ok()?);
+    let code = match code.split_once(':') {
+        Some(("char", value)) => KeyCode::Char(char::from_u32(value.parse().ok()?)?),
+        Some(("f", value)) => KeyCode::F(value.parse().ok()?),
+        Some(_) => return None,
+        None => match code {
+            "backspace" => KeyCode::Backspace,
+            "enter" => KeyCode::Enter,
+            "left" => KeyCode::Left,
+            "right" => KeyCode::Right,
+            "up" => KeyCode::Up,
+            "down" => KeyCode::Down,
+            "home" => KeyCode::Home,
+            "end" => KeyCode::End,
+            "pageup" => KeyCode::PageUp,
+            "pagedown" => KeyCode::PageDown,
+            "tab" => KeyCode::Tab,
+            "backtab" => KeyCode::BackTab,
+            "delete" => KeyCode::Delete,
+            "insert" => KeyCode::Insert,
+            "esc" => KeyCode::Esc,
+            _ => return None,
+        },
+    };
+    Some(KeyEvent::new(code, modifiers))
+}
+
+fn single_line(text: &str) -> String {
+    text.replace(['\n', '\r'], " ")
+}
@@ -0,0 +1,332 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CueSheet {
+    pub title: Option<String>,
+    pub performer: Option<String>,
+    pub disc: Option<u32>,
+    pub album_gain: Option<f32>,
+    pub album_peak: Option<f32>,
+    pub files: Vec<CueFile>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CueFile {
+    pub name: String,
+    pub tracks: Vec<CueTrack>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CueTrack {
+    pub number: u32,
+    pub title: Option<String>,
+    pub performer: Option<String>,
+    pub track_gain: Option<f32>,
+    pub track_peak: Option<f32>,
+    pub start: Duration,
+    pub end: Option<Duration>,
+}
+
+pub fn is_cue_sheet(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("cue"))
+}
+
+pub fn sibling_sheets(audio: &Path) -> Vec<PathBuf> {
+    let mut candidates = vec![audio.with_extension("cue")];
+    if let Some(name) = audio.file_name() {
+        let mut name = name.to_os_string();
+        name.push(".cue");
+        candidates.push(audio.with_file_name(name));
+    }
+    candidates.retain(|candidate| candidate.is_file());
+    candidates
+}
+
+pub fn read_sheet(path: &Path) -> Option<CueSheet> {
+    let bytes = fs::read(path).ok()?;
+    let text = match String::from_utf8(bytes) {
+        Ok(text) => text,
+        Err(error) => error.into_bytes().iter().map(|byte| *byte as char).collect(),
+    };
+    Some(parse(&text))
+}
+
+pub fn parse(text: &str) -> CueSheet {
+    let mut sheet = CueSheet::default();
+    let mut skipping = false;
+    for line in text.trim_start_matches('\u{feff}').lines() {
+        let line = line.trim();
+        let (command, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let rest = rest.trim();
+        let command = command.to_ascii_uppercase();
+        // Lines of a skipped track must not land on the previous one.
+        if skipping && command != "FILE" && command != "TRACK" {
+            continue;
+        }
+        skipping = false;
+        let in_track = sheet
+            .files
+            .last()
+            .is_some_and(|file| !file.tracks.is_empty());
+        let track = sheet
+            .files
+            .last_mut()
+            .and_then(|file| file.tracks.last_mut());
+
+        match command.as_str() {
+            "FILE" => sheet.files.push(CueFile {
+                name: file_name(rest),
+                tracks: Vec::new(),
+            }),
+            "TRACK" => {
+                // Songs are keyed by their number within the file, so a track
+                // without a unique number cannot be told apart from the others.
+                let number = rest
+                    .split_whitespace()
+                    .next()
+                    .and_then(|number| number.parse::<u32>().ok())
+                    .filter(|number| *number > 0);
+                match (sheet.files.last_mut(), number) {
+                    (Some(file), Some(number))
+                        if file.tracks.iter().all(|track| track.number != number) =>
+                    {
+                        file.tracks.push(CueTrack {
+                            number,
+                            ..CueTrack::default()
+                        });
+                    }
+                    _ => skipping = true,
+                }
+            }
+            "TITLE" => match track {
+                Some(track) if in_track => track.title = non_empty(&unquote(rest)),
+                _ => sheet.title = non_empty(&unquote(rest)),
+            },
+            "PERFORMER" => match track {
+                Some(track) if in_track => track.performer = non_empty(&unquote(rest)),
+                _ => sheet.performer = non_empty(&unquote(rest)),
+            },
+            "INDEX" => {
+                let mut parts = rest.split_whitespace();
+                if let (Some("01" | "1"), Some(time), Some(track)) =
+                    (parts.next(), parts.next(), track)
+                {
+                    if let Some(start) = parse_time(time) {
+                        track.start = start;
+                    }
+                }
+            }
+            "REM" => {
+                let (key, value) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+                let value = unquote(value.trim());
+                match (key.to_ascii_uppercase().as_str(), track) {
+                    ("REPLAYGAIN_TRACK_GAIN", Some(track)) => track.track_gain = gain(&value),
+                    ("REPLAYGAIN_TRACK_PEAK", Some(track)) => track.track_peak = gain(&value),
+                    ("REPLAYGAIN_ALBUM_GAIN", _) => sheet.album_gain = gain(&value),
+                    ("REPLAYGAIN_ALBUM_PEAK", _) => sheet.album_peak = gain(&value),
+                    ("DISCNUMBER", _) => {
+                        sheet.disc = value
+                            .split('/')
+                            .next()
+                            .and_then(|disc| disc.trim().parse().ok());
+                    }
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+
+    for file in &mut sheet.files {
+        file.tracks.sort_by_key(|track| track.start);
+        let starts: Vec<Duration> = file.tracks.iter().map(|track| track.start).collect();
+        for (track, next) in file.tracks.iter_mut().zip(starts.iter().skip(1)) {
+            track.end = Some(*next);
+        }
+    }
+    sheet.files.retain(|file| !file.tracks.is_empty());
+    sheet
+}
+
+impl CueSheet {
+    pub fn tracks_for(&self, audio: &Path) -> Option<&[CueTrack]> {
+        let name = audio.file_name()?.to_string_lossy().to_lowercase();
+        let stem = audio.file_stem()?.to_string_lossy().to_lowercase();
+        let matching = self.files.iter().find(|file| {
+            let file = Path::new(&file.name);
+            file.file_name()
+                .is_some_and(|file_name| file_name.to_string_lossy().to_lowercase() == name)
+                || file
+                    .file_stem()
+                    .is_some_and(|file_stem| file_stem.to_string_lossy().to_lowercase() == stem)
+        });
+        match (matching, self.files.as_slice()) {
+            (Some(file), _) => Some(&file.tracks),
+            (None, [file]) => Some(&file.tracks),
+            _ => None,
+        }
+    }
+}
+
+fn parse_time(text: &str) -> Option<Duration> {
+    let mut parts = text.split(':').map(|part| part.parse::<u64>().ok());
+    let (Some(Some(minutes)), Some(Some(seconds)), Some(Some(frames)), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return None;
+    };
+    let millis = minutes
+        .checked_mul(60)?
+        .checked_add(seconds)?
+        .checked_mul(1000)?
+        .checked_add(frames.checked_mul(1000)? / 75)?;
+    Some(Duration::from_millis(millis))
+}
+
+fn file_name(rest: &str) -> String {
+    if let Some(quoted) = rest.strip_prefix('"') {
+        return quoted
+            .split_once('"')
+            .map_or(quoted, |(name, _)| name)
+            .to_owned();
+    }
+    match rest.rsplit_once(char::is_whitespace) {
+        Some((name, _kind)) => name.trim().to_owned(),
+        None => rest.to_owned(),
+    }
+}
+
+fn unquote(text: &str) -> String {
+    text.strip_prefix('"')
+        .map(|inner| inner.strip_suffix('"').unwrap_or(inner))
+        .unwrap_or(text)
+        .to_owned()
+}
+
+fn non_empty(text: &str) -> Option<String> {
+    let text = text.trim();
+    (!text.is_empty()).then(|| text.to_owned())
+}
+
+fn gain(text: &str) -> Option<f32> {
+    text.trim()
+        .trim_end_matches("dB")
+        .trim_end_matches("db")
+        .trim()
+        .parse()
+        .ok()
+        .filter(|value: &f32| value.is_finite())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process};
+
+    use super::*;
+
+    fn titles(tracks: &[CueTrack]) -> Vec<&str> {
+        tracks
+            .iter()
+            .map(|track| track.title.as_deref().unwrap_or(""))
+            .collect()
+    }
+
+    #[test]
+    fn parses_tracks_across_several_files() {
+        let sheet = parse(
+            "REM REPLAYGAIN_ALBUM_GAIN -6.5 dB
+PERFORMER \"Band\"
+TITLE \"Album\"
+FILE \"one.flac\" WAVE
+  TRACK 01 AUDIO
+    TITLE \"First\"
+    INDEX 00 00:00:00
+    INDEX 01 00:00:00
+  TRACK 02 AUDIO
+    TITLE \"Second\"
+    PERFORMER \"Guest\"
+    INDEX 01 03:15:37
+FILE \"two.flac\" WAVE
+  TRACK 03 AUDIO
+    TITLE \"Third\"
+    INDEX 01 00:00:00
+",
+        );
+        assert_eq!(sheet.title.as_deref(), Some("Album"));
+        assert_eq!(sheet.performer.as_deref(), Some("Band"));
+        assert_eq!(sheet.album_gain, Some(-6.5));
+        assert_eq!(sheet.files.len(), 2);
+
+        let one = sheet.tracks_for(Path::new("/music/one.flac")).unwrap();
+        assert_eq!(titles(one), ["First", "Second"]);
+        assert_eq!(one[0].end, Some(Duration::from_millis(195_493)));
+        assert_eq!(one[1].start, Duration::from_millis(195_493));
+        assert_eq!(one[1].end, None);
+        assert_eq!(one[1].performer.as_deref(), Some("Guest"));
+
+        let two = sheet.tracks_for(Path::new("/music/Two.FLAC")).unwrap();
+        assert_eq!(titles(two), ["Third"]);
+        assert_eq!((two[0].number, two[0].end), (3, None));
+        assert!(sheet.tracks_for(Path::new("/music/three.flac")).is_none());
+    }
+
+    #[test]
+    fn converts_frames_to_milliseconds() {
+        assert_eq!(parse_time("00:00:00"), Some(Duration::ZERO));
+        assert_eq!(parse_time("00:00:74"), Some(Duration::from_millis(986)));
+        assert_eq!(parse_time("01:02:03"), Some(Duration::from_millis(62_040)));
+        assert_eq!(parse_time("1:2"), None);
+        assert_eq!(parse_time("00:xx:00"), None);
+        assert_eq!(parse_time("99999999999999999:00:00"), None);
+    }
+
+    #[test]
+    fn skips_tracks_without_a_unique_number() {
+        let sheet = parse(
+            "FILE \"a.flac\" WAVE
+  TRACK 01 AUDIO
+    TITLE \"One\"
+    INDEX 01 00:00:00
+  TRACK xx AUDIO
+    TITLE \"Broken\"
+    INDEX 01 01:00:00
+  TRACK 01 AUDIO
+    TITLE \"Duplicate\"
+    INDEX 01 02:00:00
+  TRACK 02 AUDIO
+    TITLE \"Two\"
+    REM REPLAYGAIN_TRACK_GAIN nan
+    REM REPLAYGAIN_TRACK_PEAK inf
+    INDEX 01 03:00:00
+",
+        );
+        let tracks = &sheet.files[0].tracks;
+        assert_eq!(titles(tracks), ["One", "Two"]);
+        assert_eq!(tracks[0].end, Some(Duration::from_secs(180)));
+        assert_eq!((tracks[1].track_gain, tracks[1].track_peak), (None, None));
+    }
+
+    #[test]
+    fn reads_bom_and_latin1_sheets() {
+        let sheet = parse("\u{feff}TITLE \"Bom\"\nFILE \"a.wav\" WAVE\n  TRACK 01 AUDIO\n");
+        assert_eq!(sheet.title.as_deref(), Some("Bom"));
+
+        let path = env::temp_dir().join(format!("musa-cue-test-{}.cue", process::id()));
+        fs::write(
+            &path,
+            b"PERFORMER \"Bj\xf6rk\"\r\nFILE \"a.wav\" WAVE\r\n  TRACK 01 AUDIO\r\n    TITLE \"J\xf3ga\"\r\n",
+        )
+        .unwrap();
+        let sheet = read_sheet(&path);
+        let _ = fs::remove_file(&path);
+        let sheet = sheet.unwrap();
+        assert_eq!(sheet.performer.as_deref(), Some("Björk"));
+        assert_eq!(titles(&sheet.files[0].tracks), ["Jóga"]);
+    }
+}
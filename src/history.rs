@@ -0,0 +1,54 @@
+use std::{
+    collections::HashMap,
+    io,
+    path::{Path, PathBuf},
+};
+
+use crate::storage::{self, Play};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PlayStats {
+    pub plays: u32,
+    pub skips: u32,
+    pub last_played: Option<u64>,
+}
+
+#[derive(Debug, Default)]
+pub struct PlayHistory {
+    stats: HashMap<PathBuf, PlayStats>,
+}
+
+impl PlayHistory {
+    pub fn load() -> Self {
+        let mut history = Self::default();
+        for play in storage::load_history() {
+            history.add(&play);
+        }
+        history
+    }
+
+    pub fn record(&mut self, play: &Play) -> io::Result<()> {
+        self.add(play);
+        storage::append_play(play)
+    }
+
+    pub fn stats(&self, path: &Path) -> PlayStats {
+        self.stats.get(path).copied().unwrap_or_default()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&Path, &PlayStats)> {
+        self.stats
+            .iter()
+            .map(|(path, stats)| (path.as_path(), stats))
+    }
+
+    fn add(&mut self, play: &Play) {
+        let stats = self.stats.entry(play.path.clone()).or_default();
+        if play.skipped {
+            stats.skips += 1;
+        } else {
+            stats.plays += 1;
+            stats.last_played = stats.last_played.max(Some(play.played_at));
+        }
+    }
+}
@@ -1,21 +1,38 @@
 use std::{
-    collections::HashSet,
+    cmp::Ordering,
+    collections::{BTreeSet, HashMap, HashSet},
     ffi::OsStr,
-    fs, io,
+    fs::{self, File},
+    io,
+    num::NonZero,
+    panic::{self, AssertUnwindSafe},
     path::{Path, PathBuf},
     sync::{
-        Arc,
+        Arc, Mutex, PoisonError,
+        atomic::{AtomicUsize, Ordering as AtomicOrdering},
         mpsc::{self, Receiver, SyncSender},
     },
     thread,
-    time::Duration,
+    time::{Duration, UNIX_EPOCH},
 };
 
 use lofty::{
-    file::{AudioFile, TaggedFileExt},
+    config::ParseOptions,
+    file::{AudioFile, FileType, TaggedFile, TaggedFileExt},
+    flac::FlacFile,
     read_from_path,
     tag::{Accessor, ItemKey},
 };
+use notify::{
+    Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher,
+    event::{AccessKind, AccessMode, ModifyKind},
+};
+
+use crate::{
+    cue::{self, CueSheet, CueTrack},
+    playlist_file::{self, PlaylistFile},
+    storage,
+};
 
 #[derive(Clone, Debug)]
 pub struct Track {
@@ -25,28 +42,190 @@ pub struct Track {
     pub artist: Arc<str>,
     pub album_artist: Arc<str>,
     pub album: Arc<str>,
+    pub compilation: bool,
+    pub tagged_artist: bool,
+    pub tagged_album: bool,
     pub track_no: Option<u32>,
     pub disc_no: Option<u32>,
     pub duration: Option<Duration>,
+    pub genre: Option<Arc<str>>,
+    pub year: Option<u32>,
+    pub rating: Option<u8>,
+    pub modified: Option<u64>,
+    pub tags: TrackTags,
+    pub format: AudioFormat,
+    pub replay_gain: ReplayGain,
+    pub range: Option<TrackRange>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TrackTags {
+    pub composer: Option<Arc<str>>,
+    pub conductor: Option<Arc<str>>,
+    pub comment: Option<Arc<str>>,
+    pub label: Option<Arc<str>>,
+    pub date: Option<Arc<str>>,
+    pub track_total: Option<u32>,
+    pub disc_total: Option<u32>,
+    pub musicbrainz_recording_id: Option<Arc<str>>,
+    pub musicbrainz_release_id: Option<Arc<str>>,
+    pub musicbrainz_release_group_id: Option<Arc<str>>,
+    pub musicbrainz_artist_id: Option<Arc<str>>,
+    pub musicbrainz_album_artist_id: Option<Arc<str>>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AudioFormat {
+    pub codec: Option<Arc<str>>,
+    pub sample_rate: Option<u32>,
+    pub bit_depth: Option<u8>,
+    pub bitrate: Option<u32>,
+    pub channels: Option<u8>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TrackRange {
+    pub start: Duration,
+    pub end: Option<Duration>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ReplayGain {
+    pub track_gain: Option<f32>,
+    pub track_peak: Option<f32>,
+    pub album_gain: Option<f32>,
+    pub album_peak: Option<f32>,
 }
 
 impl Track {
     pub(crate) fn from_path(path: PathBuf) -> Self {
+        Self::read(path).0
+    }
+
+    pub(crate) fn read_all(path: PathBuf) -> Vec<Self> {
+        let (track, embedded_sheet) = Self::read(path);
+        let sheet = cue::sibling_sheets(&track.path)
+            .iter()
+            .find_map(|sheet| cue::read_sheet(sheet))
+            .filter(|sheet| sheet.tracks_for(&track.path).is_some())
+            .or_else(|| embedded_sheet.map(|text| cue::parse(&text)));
+        let Some((sheet, cue_tracks)) = sheet
+            .as_ref()
+            .and_then(|sheet| Some((sheet, sheet.tracks_for(&track.path)?)))
+        else {
+            return vec![track];
+        };
+        cue_tracks
+            .iter()
+            .map(|cue_track| track.cue_track(sheet, cue_track))
+            .collect()
+    }
+
+    pub fn file_path(&self) -> &Path {
+        match self.range {
+            Some(_) => self.path.parent().unwrap_or(&self.path),
+            None => &self.path,
+        }
+    }
+
+    fn cue_track(&self, sheet: &CueSheet, cue_track: &CueTrack) -> Self {
+        let length = match (cue_track.end, self.duration) {
+            (Some(end), _) => Some(end.saturating_sub(cue_track.start)),
+            (None, Some(total)) => Some(total.saturating_sub(cue_track.start)),
+            (None, None) => None,
+        };
+        let title = cue_track
+            .title
+            .clone()
+            .unwrap_or_else(|| format!("Track {:02}", cue_track.number));
+        let album_artist = sheet
+            .performer
+            .as_deref()
+            .map_or_else(|| Arc::clone(&self.album_artist), Arc::from);
+        let artist = cue_track
+            .performer
+            .as_deref()
+            .map_or_else(|| Arc::clone(&album_artist), Arc::from);
+
+        Self {
+            path: self.path.join(format!("{:02}", cue_track.number)),
+            album_dir: Arc::clone(&self.album_dir),
+            title: Arc::from(title),
+            artist,
+            album_artist,
+            album: sheet
+                .title
+                .as_deref()
+                .map_or_else(|| Arc::clone(&self.album), Arc::from),
+            compilation: self.compilation,
+            tagged_artist: self.tagged_artist
+                || sheet.performer.is_some()
+                || cue_track.performer.is_some(),
+            tagged_album: self.tagged_album || sheet.title.is_some(),
+            track_no: Some(cue_track.number),
+            disc_no: sheet.disc.or(self.disc_no),
+            duration: length,
+            genre: self.genre.clone(),
+            year: self.year,
+            rating: self.rating,
+            modified: self.modified,
+            tags: TrackTags {
+                track_total: sheet
+                    .tracks_for(self.file_path())
+                    .map(|tracks| tracks.len() as u32)
+                    .or(self.tags.track_total),
+                ..self.tags.clone()
+            },
+            format: self.format.clone(),
+            replay_gain: ReplayGain {
+                track_gain: cue_track.track_gain,
+                track_peak: cue_track.track_peak,
+                album_gain: sheet.album_gain.or(self.replay_gain.album_gain),
+                album_peak: sheet.album_peak.or(self.replay_gain.album_peak),
+            },
+            range: Some(TrackRange {
+                start: cue_track.start,
+                end: cue_track.end,
+            }),
+        }
+    }
+
+    fn read(path: PathBuf) -> (Self, Option<String>) {
         let fallback = FallbackMeta::from_path(&path);
 
         let mut title = None;
         let mut artist = None;
         let mut album_artist = None;
         let mut album = None;
+        let mut compilation = false;
         let mut track_no = None;
         let mut disc_no = None;
         let mut duration = None;
+        let mut genre = None;
+        let mut year = None;
+        let mut rating = None;
+        let mut tags = TrackTags::default();
+        let mut format = AudioFormat::default();
+        let mut replay_gain = ReplayGain::default();
+        let mut cue_sheet = None;
 
-        if let Ok(tagged) = read_from_path(&path) {
-            let parsed_duration = tagged.properties().duration();
+        if let Some((tagged, embedded_sheet)) = read_tagged(&path) {
+            cue_sheet = embedded_sheet;
+            let properties = tagged.properties();
+            let parsed_duration = properties.duration();
             if !parsed_duration.is_zero() {
                 duration = Some(parsed_duration);
             }
+            format = AudioFormat {
+                codec: codec_name(tagged.file_type(), properties.bit_depth()).map(Arc::from),
+                sample_rate: properties.sample_rate().filter(|rate| *rate > 0),
+                bit_depth: properties.bit_depth().filter(|depth| *depth > 0),
+                bitrate: properties
+                    .audio_bitrate()
+                    .or_else(|| properties.overall_bitrate())
+                    .filter(|bitrate| *bitrate > 0),
+                channels: properties.channels().filter(|channels| *channels > 0),
+            };
 
             if let Some(tag) = tagged.primary_tag().or_else(|| tagged.first_tag()) {
                 title = tag.title().map(|value| value.trim().to_owned());
@@ -57,44 +236,196 @@ impl Track {
                     .map(str::trim)
                     .filter(|value| !value.is_empty())
                     .map(str::to_owned);
+                compilation = tag
+                    .get_string(ItemKey::FlagCompilation)
+                    .is_some_and(flag_value);
                 track_no = tag.track();
                 disc_no = tag.disk();
+                genre = tag.genre().map(|value| value.trim().to_owned());
+                year = tag
+                    .get_string(ItemKey::Year)
+                    .or_else(|| tag.get_string(ItemKey::RecordingDate))
+                    .and_then(year_value);
+                rating = tag.get_string(ItemKey::Popularimeter).and_then(rating_value);
+                let text = |key| tag.get_string(key).and_then(text_value);
+                tags = TrackTags {
+                    composer: text(ItemKey::Composer),
+                    conductor: text(ItemKey::Conductor),
+                    comment: tag.comment().as_deref().and_then(text_value),
+                    label: text(ItemKey::Label).or_else(|| text(ItemKey::Publisher)),
+                    date: text(ItemKey::RecordingDate)
+                        .or_else(|| text(ItemKey::Year))
+                        .or_else(|| text(ItemKey::ReleaseDate)),
+                    track_total: tag.track_total(),
+                    disc_total: tag.disk_total(),
+                    musicbrainz_recording_id: text(ItemKey::MusicBrainzRecordingId),
+                    musicbrainz_release_id: text(ItemKey::MusicBrainzReleaseId),
+                    musicbrainz_release_group_id: text(ItemKey::MusicBrainzReleaseGroupId),
+                    musicbrainz_artist_id: text(ItemKey::MusicBrainzArtistId),
+                    musicbrainz_album_artist_id: text(ItemKey::MusicBrainzReleaseArtistId),
+                };
+                replay_gain = ReplayGain {
+                    track_gain: gain_value(tag.get_string(ItemKey::ReplayGainTrackGain)),
+                    track_peak: gain_value(tag.get_string(ItemKey::ReplayGainTrackPeak)),
+                    album_gain: gain_value(tag.get_string(ItemKey::ReplayGainAlbumGain)),
+                    album_peak: gain_value(tag.get_string(ItemKey::ReplayGainAlbumPeak)),
+                };
             }
         }
 
         let title = non_empty(title).unwrap_or(fallback.title);
-        let artist = non_empty(artist).unwrap_or(fallback.artist);
-        let album = non_empty(album).unwrap_or(fallback.album);
-        let album_artist = non_empty(album_artist).unwrap_or_else(|| artist.clone());
+        let artist = non_empty(artist);
+        let tagged_artist = artist.is_some();
+        let artist = artist.unwrap_or(fallback.artist);
+        let album = non_empty(album);
+        let tagged_album = album.is_some();
+        let album = album.unwrap_or(fallback.album);
+        let album_artist = non_empty(album_artist).unwrap_or_else(|| {
+            if compilation {
+                "Various Artists".to_owned()
+            } else {
+                artist.clone()
+            }
+        });
+        let modified = FileStamp::read(&path).map(|stamp| stamp.modified / 1_000_000_000);
 
-        Self {
+        let track = Self {
             path,
             album_dir: Arc::from(fallback.album_dir.into_boxed_path()),
             title: Arc::from(title),
             artist: Arc::from(artist),
             album_artist: Arc::from(album_artist),
             album: Arc::from(album),
+            compilation,
+            tagged_artist,
+            tagged_album,
             track_no: track_no.or(fallback.track_no),
             disc_no: disc_no.or(fallback.disc_no),
             duration,
+            genre: non_empty(genre).map(Arc::from),
+            year,
+            rating,
+            modified,
+            tags,
+            format,
+            replay_gain,
+            range: None,
+        };
+        (track, cue_sheet)
+    }
+}
+
+fn read_tagged(path: &Path) -> Option<(TaggedFile, Option<String>)> {
+    let is_flac = path
+        .extension()
+        .and_then(OsStr::to_str)
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("flac"));
+    if is_flac {
+        let flac = File::open(path)
+            .ok()
+            .and_then(|mut file| FlacFile::read_from(&mut file, ParseOptions::new()).ok());
+        if let Some(flac) = flac {
+            let cue_sheet = flac
+                .vorbis_comments()
+                .and_then(|comments| comments.get("CUESHEET"))
+                .map(str::to_owned);
+            return Some((TaggedFile::from(flac), cue_sheet));
+        }
+    }
+    read_from_path(path).ok().map(|tagged| (tagged, None))
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FileStamp {
+    pub size: u64,
+    pub modified: u64,
+}
+
+impl FileStamp {
+    fn read_with_cue(path: &Path) -> Option<Self> {
+        let mut stamp = Self::read(path)?;
+        for sheet in cue::sibling_sheets(path) {
+            if let Some(sheet) = Self::read(&sheet) {
+                stamp.size = stamp.size.wrapping_add(sheet.size);
+                stamp.modified ^= sheet.modified.rotate_left(17);
+            }
         }
+        Some(stamp)
+    }
+
+    fn read(path: &Path) -> Option<Self> {
+        let metadata = fs::metadata(path).ok()?;
+        let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |duration| duration.as_nanos() as u64);
+        Some(Self {
+            size: metadata.len(),
+            modified,
+        })
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct CachedTrack {
+    pub stamp: FileStamp,
+    pub track: Track,
+}
+
+#[derive(Debug, Default)]
+pub struct LibraryDiff {
+    pub added: Vec<Track>,
+    pub removed: Vec<PathBuf>,
+    pub changed: Vec<Track>,
+}
+
+impl LibraryDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
     }
 }
 
 #[derive(Debug)]
 pub enum ScanEvent {
+    Cached(Vec<Track>),
     Progress { done: usize, total: usize },
-    Finished(Result<Vec<Track>, String>),
+    PlaylistFiles(Vec<PlaylistFile>),
+    Finished(Result<LibraryDiff, String>),
+}
+
+#[derive(Clone, Debug)]
+pub enum ScanScope {
+    Full,
+    Paths {
+        changed: Vec<PathBuf>,
+        playlists: Vec<PathBuf>,
+    },
 }
 
-pub fn spawn_scan(roots: Vec<PathBuf>) -> Receiver<ScanEvent> {
+pub fn spawn_scan(
+    roots: Vec<PathBuf>,
+    known: Vec<PathBuf>,
+    scope: ScanScope,
+    threads: usize,
+) -> Receiver<ScanEvent> {
     let (sender, receiver) = mpsc::sync_channel(4);
     let worker_sender = sender.clone();
     let spawn = thread::Builder::new()
         .name("musa-library-scan".to_owned())
         .stack_size(512 * 1024)
         .spawn(move || {
-            let result = scan(&roots, &worker_sender).map_err(|error| error.to_string());
+            let reader = TagReader {
+                threads,
+                sender: &worker_sender,
+            };
+            let result = match scope {
+                ScanScope::Full => scan(&roots, known, &reader),
+                ScanScope::Paths { changed, playlists } => {
+                    refresh(&roots, known, changed, &playlists, &reader)
+                }
+            };
+            let result = result.map_err(|error| error.to_string());
             let _ = worker_sender.send(ScanEvent::Finished(result));
         });
 
@@ -106,23 +437,348 @@ pub fn spawn_scan(roots: Vec<PathBuf>) -> Receiver<ScanEvent> {
     receiver
 }
 
-fn scan(roots: &[PathBuf], sender: &SyncSender<ScanEvent>) -> io::Result<Vec<Track>> {
+pub fn load_library(
+    roots: &[PathBuf],
+    threads: usize,
+    mut progress: impl FnMut(usize, usize),
+) -> io::Result<(Vec<Track>, LibraryDiff)> {
+    let receiver = spawn_scan(roots.to_vec(), Vec::new(), ScanScope::Full, threads);
+    let mut tracks = Vec::new();
+    for event in receiver {
+        match event {
+            ScanEvent::Cached(cached) => tracks = cached,
+            ScanEvent::Progress { done, total } => progress(done, total),
+            ScanEvent::PlaylistFiles(_) => {}
+            ScanEvent::Finished(result) => {
+                let diff = result.map_err(io::Error::other)?;
+                let replaced: HashSet<&Path> = diff
+                    .removed
+                    .iter()
+                    .map(PathBuf::as_path)
+                    .chain(diff.changed.iter().map(|track| track.path.as_path()))
+                    .collect();
+                tracks.retain(|track| !replaced.contains(track.path.as_path()));
+                tracks.extend(diff.changed.iter().cloned());
+                tracks.extend(diff.added.iter().cloned());
+                sort_tracks(&mut tracks);
+                return Ok((tracks, diff));
+            }
+        }
+    }
+    Err(io::Error::other("library scan stopped unexpectedly"))
+}
+
+fn scan(roots: &[PathBuf], known: Vec<PathBuf>, reader: &TagReader) -> io::Result<LibraryDiff> {
+    let mut cache = load_cache_under(roots);
+
+    let mut known: HashSet<PathBuf> = known.into_iter().collect();
+    if known.is_empty() && !cache.is_empty() {
+        let mut cached: Vec<Track> = cache
+            .values()
+            .flatten()
+            .map(|entry| entry.track.clone())
+            .collect();
+        intern_repeated_metadata(&mut cached);
+        sort_tracks(&mut cached);
+        known = cached.iter().map(|track| track.path.clone()).collect();
+        let _ = reader.sender.send(ScanEvent::Cached(cached));
+    }
+
     let mut paths = collect_audio_paths(roots)?;
     paths.sort_unstable();
     paths.dedup();
 
-    let total = paths.len();
-    let mut tracks = Vec::with_capacity(total);
-    for (index, path) in paths.into_iter().enumerate() {
-        tracks.push(Track::from_path(path));
-        let done = index + 1;
-        if done == total || done % 64 == 0 {
-            let _ = sender.try_send(ScanEvent::Progress { done, total });
+    let mut diff = LibraryDiff::default();
+    let entries = reader.read(paths, &mut cache, &mut known, &mut diff);
+    let _ = storage::save_track_cache(&entries);
+    drop(entries);
+
+    send_playlist_files(roots, reader.sender)?;
+    diff.removed = known.into_iter().collect();
+    finish_diff(&mut diff);
+    Ok(diff)
+}
+
+fn refresh(
+    roots: &[PathBuf],
+    known: Vec<PathBuf>,
+    changed_paths: Vec<PathBuf>,
+    playlists: &[PathBuf],
+    reader: &TagReader,
+) -> io::Result<LibraryDiff> {
+    let mut cache = load_cache_under(roots);
+    let mut known: HashSet<PathBuf> = known.into_iter().collect();
+    let mut diff = LibraryDiff::default();
+    let mut paths = Vec::new();
+    let mut playlists_changed = changed_paths.iter().any(|path| {
+        playlist_file::is_playlist(path)
+            || playlists.iter().any(|playlist| playlist.starts_with(path))
+    });
+
+    for path in changed_paths {
+        if !roots.iter().any(|root| path.starts_with(root)) {
+            continue;
+        }
+        match fs::symlink_metadata(&path) {
+            Ok(metadata) if metadata.is_dir() => {
+                let directory = std::slice::from_ref(&path);
+                paths.extend(collect_audio_paths(directory)?);
+                playlists_changed =
+                    playlists_changed || !collect_playlist_paths(directory)?.is_empty();
+            }
+            Ok(metadata) if metadata.is_file() && is_supported_audio(&path) => paths.push(path),
+            Ok(metadata) if metadata.is_file() && cue::is_cue_sheet(&path) => {
+                if let Some(parent) = path.parent() {
+                    paths.extend(collect_audio_paths(&[parent.to_path_buf()])?);
+                }
+            }
+            Ok(_) => {}
+            Err(error) if error.kind() == io::ErrorKind::NotFound && cue::is_cue_sheet(&path) => {
+                if let Some(parent) = path.parent().filter(|parent| parent.is_dir()) {
+                    paths.extend(collect_audio_paths(&[parent.to_path_buf()])?);
+                }
+            }
+            Err(error) if error.kind() == io::ErrorKind::NotFound => {
+                cache.retain(|cached, _| !cached.starts_with(&path));
+                diff.removed.push(path);
+            }
+            Err(error) => return Err(error),
         }
     }
+    paths.sort_unstable();
+    paths.dedup();
+
+    let mut entries = reader.read(paths, &mut cache, &mut known, &mut diff);
+    entries.extend(cache.into_values().flatten());
+    let _ = storage::save_track_cache(&entries);
+    drop(entries);
 
-    intern_repeated_metadata(&mut tracks);
+    if playlists_changed {
+        send_playlist_files(roots, reader.sender)?;
+    }
+    finish_diff(&mut diff);
+    Ok(diff)
+}
 
+fn send_playlist_files(roots: &[PathBuf], sender: &SyncSender<ScanEvent>) -> io::Result<()> {
+    let mut paths = collect_playlist_paths(roots)?;
+    paths.sort_unstable();
+    paths.dedup();
+    let playlists = paths
+        .into_iter()
+        .filter_map(|path| {
+            let entries = playlist_file::read_entries(&path).ok()?;
+            Some(PlaylistFile { path, entries })
+        })
+        .collect();
+    let _ = sender.send(ScanEvent::PlaylistFiles(playlists));
+    Ok(())
+}
+
+fn load_cache_under(roots: &[PathBuf]) -> HashMap<PathBuf, Vec<CachedTrack>> {
+    let mut cache: HashMap<PathBuf, Vec<CachedTrack>> = HashMap::new();
+    for entry in storage::load_track_cache() {
+        if roots.iter().any(|root| entry.track.path.starts_with(root)) {
+            cache
+                .entry(entry.track.file_path().to_path_buf())
+                .or_default()
+                .push(entry);
+        }
+    }
+    cache
+}
+
+type FileRead = (Option<FileStamp>, Option<Vec<Track>>);
+
+struct TagReader<'a> {
+    threads: usize,
+    sender: &'a SyncSender<ScanEvent>,
+}
+
+impl TagReader<'_> {
+    fn read(
+        &self,
+        paths: Vec<PathBuf>,
+        cache: &mut HashMap<PathBuf, Vec<CachedTrack>>,
+        known: &mut HashSet<PathBuf>,
+        diff: &mut LibraryDiff,
+    ) -> Vec<CachedTrack> {
+        let mut results = self.read_parallel(&paths, cache);
+        let mut entries = Vec::with_capacity(paths.len());
+        for (index, path) in paths.into_iter().enumerate() {
+            // A file whose read never finished is dropped rather than served
+            // from a cache entry whose stamp was never checked.
+            let (stamp, parsed) = results[index].take().unwrap_or((None, Some(Vec::new())));
+            let cached = cache.remove(&path).unwrap_or_default();
+            let (tracks, reread) = match parsed {
+                Some(tracks) => (tracks, true),
+                None if !cached.is_empty() => {
+                    (cached.iter().map(|entry| entry.track.clone()).collect(), false)
+                }
+                None => (Track::read_all(path), true),
+            };
+            for stale in cached {
+                if !tracks.iter().any(|track| track.path == stale.track.path)
+                    && known.remove(&stale.track.path)
+                {
+                    diff.removed.push(stale.track.path);
+                }
+            }
+            for track in tracks {
+                let was_known = known.remove(&track.path);
+                if !was_known {
+                    diff.added.push(track.clone());
+                } else if reread {
+                    diff.changed.push(track.clone());
+                }
+                if let Some(stamp) = stamp {
+                    entries.push(CachedTrack { stamp, track });
+                }
+            }
+        }
+        entries
+    }
+
+    fn read_parallel(
+        &self,
+        paths: &[PathBuf],
+        cache: &HashMap<PathBuf, Vec<CachedTrack>>,
+    ) -> Vec<Option<FileRead>> {
+        let total = paths.len();
+        let threads = match self.threads {
+            0 => thread::available_parallelism().map_or(1, NonZero::get),
+            threads => threads,
+        }
+        .clamp(1, total.div_ceil(64).max(1));
+
+        let next = AtomicUsize::new(0);
+        let done = AtomicUsize::new(0);
+        let reported = Mutex::new(0);
+        let read_one = |path: &Path| {
+            let stamp = FileStamp::read_with_cue(path);
+            let fresh = stamp.is_some_and(|stamp| {
+                cache.get(path).is_some_and(|entries| {
+                    !entries.is_empty() && entries.iter().all(|entry| entry.stamp == stamp)
+                })
+            });
+            let tracks = (!fresh).then(|| Track::read_all(path.to_path_buf()));
+            (stamp, tracks)
+        };
+        let worker = |sender: SyncSender<ScanEvent>| {
+            let mut output = Vec::new();
+            loop {
+                let index = next.fetch_add(1, AtomicOrdering::Relaxed);
+                let Some(path) = paths.get(index) else {
+                    break;
+                };
+                // A tag parser that panics on a malformed file only loses that
+                // file; the rest of this worker's results are kept.
+                if let Ok(read) = panic::catch_unwind(AssertUnwindSafe(|| read_one(path))) {
+                    output.push((index, read));
+                }
+                let finished = done.fetch_add(1, AtomicOrdering::Relaxed) + 1;
+                if finished == total || finished.is_multiple_of(64) {
+                    // Workers race to report, so send the counter's current value
+                    // under the lock and only when it moved forward.
+                    let mut reported = reported.lock().unwrap_or_else(PoisonError::into_inner);
+                    let current = done.load(AtomicOrdering::Relaxed);
+                    if current > *reported {
+                        *reported = current;
+                        let _ = sender.try_send(ScanEvent::Progress {
+                            done: current,
+                            total,
+                        });
+                    }
+                }
+            }
+            output
+        };
+
+        let mut results: Vec<Option<FileRead>> = Vec::new();
+        results.resize_with(total, || None);
+        thread::scope(|scope| {
+            let handles: Vec<_> = (1..threads)
+                .filter_map(|number| {
+                    let sender = self.sender.clone();
+                    thread::Builder::new()
+                        .name(format!("musa-tag-reader-{number}"))
+                        .stack_size(512 * 1024)
+                        .spawn_scoped(scope, || worker(sender))
+                        .ok()
+                })
+                .collect();
+            for (index, result) in worker(self.sender.clone()) {
+                results[index] = Some(result);
+            }
+            for handle in handles {
+                if let Ok(output) = handle.join() {
+                    for (index, result) in output {
+                        results[index] = Some(result);
+                    }
+                }
+            }
+        });
+        results
+    }
+}
+
+fn finish_diff(diff: &mut LibraryDiff) {
+    intern_repeated_metadata(&mut diff.added);
+    intern_repeated_metadata(&mut diff.changed);
+    sort_tracks(&mut diff.added);
+    sort_tracks(&mut diff.changed);
+}
+
+pub struct LibraryWatcher {
+    _watcher: RecommendedWatcher,
+    events: Receiver<notify::Result<Event>>,
+}
+
+impl LibraryWatcher {
+    pub fn new(roots: &[PathBuf]) -> io::Result<Self> {
+        let (sender, events) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(sender).map_err(io::Error::other)?;
+        for root in roots {
+            watcher
+                .watch(root, RecursiveMode::Recursive)
+                .map_err(|error| io::Error::other(format!("{}: {error}", root.display())))?;
+        }
+        Ok(Self {
+            _watcher: watcher,
+            events,
+        })
+    }
+
+    pub fn drain_into(&self, changed: &mut BTreeSet<PathBuf>) -> bool {
+        let mut received = false;
+        while let Ok(event) = self.events.try_recv() {
+            let Ok(event) = event else {
+                continue;
+            };
+            let relevant = match event.kind {
+                EventKind::Create(_) | EventKind::Remove(_) => true,
+                EventKind::Modify(kind) => !matches!(kind, ModifyKind::Metadata(_)),
+                EventKind::Access(kind) => kind == AccessKind::Close(AccessMode::Write),
+                EventKind::Any | EventKind::Other => false,
+            };
+            if !relevant {
+                continue;
+            }
+            for path in event.paths {
+                if is_supported_audio(&path)
+                    || playlist_file::is_playlist(&path)
+                    || cue::is_cue_sheet(&path)
+                    || !path.is_file()
+                {
+                    received |= changed.insert(path);
+                }
+            }
+        }
+        received
+    }
+}
+
+fn sort_tracks(tracks: &mut [Track]) {
     tracks.sort_by_cached_key(|track| {
         (
             track.artist.to_lowercase(),
@@ -133,8 +789,71 @@ fn scan(roots: &[PathBuf], sender: &SyncSender<ScanEvent>) -> io::Result<Vec<Tra
             track.path.clone(),
         )
     });
+}
 
-    Ok(tracks)
+pub fn compare_tracks(left: &Track, right: &Track) -> Ordering {
+    compare_text(&left.artist, &right.artist)
+        .then_with(|| compare_text(&left.album, &right.album))
+        .then_with(|| left.disc_no.unwrap_or(0).cmp(&right.disc_no.unwrap_or(0)))
+        .then_with(|| {
+            left.track_no
+                .unwrap_or(u32::MAX)
+                .cmp(&right.track_no.unwrap_or(u32::MAX))
+        })
+        .then_with(|| compare_text(&left.title, &right.title))
+        .then_with(|| left.path.cmp(&right.path))
+}
+
+pub fn split_artists<'a>(name: &'a str, separators: &[String]) -> Vec<&'a str> {
+    let mut names = Vec::new();
+    let mut start = 0;
+    let mut cursor = 0;
+    while cursor < name.len() {
+        let rest = &name[cursor..];
+        let separator = separators.iter().find(|separator| {
+            let Some(candidate) = rest.get(..separator.len()) else {
+                return false;
+            };
+            let word = separator.starts_with(|character: char| character.is_alphanumeric());
+            // `/` only splits before a space so names like AC/DC stay whole.
+            let spaced = word || *separator == "/";
+            candidate.eq_ignore_ascii_case(separator)
+                && (!spaced
+                    || rest[separator.len()..]
+                        .chars()
+                        .next()
+                        .is_none_or(char::is_whitespace))
+                && (!word || name[..cursor].ends_with(char::is_whitespace))
+        });
+        match separator {
+            Some(separator) => {
+                names.push(name[start..cursor].trim());
+                cursor += separator.len();
+                start = cursor;
+            }
+            None => cursor += rest.chars().next().map_or(1, char::len_utf8),
+        }
+    }
+    names.push(name[start..].trim());
+    names.retain(|name| !name.is_empty());
+    if names.is_empty() {
+        names.push(name.trim());
+    }
+    names
+}
+
+pub fn compare_text(left: &str, right: &str) -> Ordering {
+    if left.is_ascii() && right.is_ascii() {
+        for (left, right) in left.bytes().zip(right.bytes()) {
+            let ordering = left.to_ascii_lowercase().cmp(&right.to_ascii_lowercase());
+            if !ordering.is_eq() {
+                return ordering;
+            }
+        }
+        left.len().cmp(&right.len())
+    } else {
+        left.to_lowercase().cmp(&right.to_lowercase())
+    }
 }
 
 fn intern_repeated_metadata(tracks: &mut [Track]) {
@@ -144,6 +863,23 @@ fn intern_repeated_metadata(tracks: &mut [Track]) {
         intern_arc(&mut strings, &mut track.artist);
         intern_arc(&mut strings, &mut track.album_artist);
         intern_arc(&mut strings, &mut track.album);
+        for value in [
+            &mut track.genre,
+            &mut track.tags.composer,
+            &mut track.tags.conductor,
+            &mut track.tags.label,
+            &mut track.tags.date,
+            &mut track.tags.musicbrainz_release_id,
+            &mut track.tags.musicbrainz_release_group_id,
+            &mut track.tags.musicbrainz_artist_id,
+            &mut track.tags.musicbrainz_album_artist_id,
+            &mut track.format.codec,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            intern_arc(&mut strings, value);
+        }
         intern_path(&mut directories, &mut track.album_dir);
     }
 }
@@ -164,7 +900,15 @@ fn intern_path(pool: &mut HashSet<Arc<Path>>, value: &mut Arc<Path>) {
     }
 }
 
-fn collect_audio_paths(roots: &[PathBuf]) -> io::Result<Vec<PathBuf>> {
+pub fn collect_audio_paths(roots: &[PathBuf]) -> io::Result<Vec<PathBuf>> {
+    collect_paths(roots, is_supported_audio)
+}
+
+pub fn collect_playlist_paths(roots: &[PathBuf]) -> io::Result<Vec<PathBuf>> {
+    collect_paths(roots, playlist_file::is_playlist)
+}
+
+fn collect_paths(roots: &[PathBuf], wanted: fn(&Path) -> bool) -> io::Result<Vec<PathBuf>> {
     let mut output = Vec::new();
     let mut pending = Vec::with_capacity(roots.len().max(16));
     pending.extend(roots.iter().cloned());
@@ -188,7 +932,7 @@ fn collect_audio_paths(roots: &[PathBuf]) -> io::Result<Vec<PathBuf>> {
             continue;
         }
         if file_type.is_file() {
-            if is_supported_audio(&path) {
+            if wanted(&path) {
                 output.push(path);
             }
             continue;
@@ -213,7 +957,7 @@ fn collect_audio_paths(roots: &[PathBuf]) -> io::Result<Vec<PathBuf>> {
             let child = entry.path();
             if file_type.is_dir() {
                 pending.push(child);
-            } else if file_type.is_file() && is_supported_audio(&child) {
+            } else if file_type.is_file() && wanted(&child) {
                 output.push(child);
             }
         }
@@ -245,15 +989,21 @@ pub fn read_directory_entries(path: &Path) -> io::Result<Vec<DirectoryEntry>> {
             let track = Track::from_path(path.clone());
             entries.push(DirectoryEntry {
                 name,
-                kind: DirectoryEntryKind::Track(track),
+                kind: DirectoryEntryKind::Track(Box::new(track)),
+            });
+        } else if file_type.is_file() && playlist_file::is_playlist(&path) {
+            entries.push(DirectoryEntry {
+                name,
+                kind: DirectoryEntryKind::Playlist(path),
             });
         }
     }
 
     entries.sort_by_cached_key(|entry| match &entry.kind {
         DirectoryEntryKind::Directory(_) => (0, 0, 0, entry.name.to_lowercase()),
+        DirectoryEntryKind::Playlist(_) => (1, 0, 0, entry.name.to_lowercase()),
         DirectoryEntryKind::Track(track) => (
-            1,
+            2,
             track.disc_no.unwrap_or(0),
             track.track_no.unwrap_or(u32::MAX),
             track.title.to_lowercase(),
@@ -265,7 +1015,8 @@ pub fn read_directory_entries(path: &Path) -> io::Result<Vec<DirectoryEntry>> {
 #[derive(Clone, Debug)]
 pub enum DirectoryEntryKind {
     Directory(PathBuf),
-    Track(Track),
+    Playlist(PathBuf),
+    Track(Box<Track>),
 }
 
 #[derive(Clone, Debug)]
@@ -284,6 +1035,68 @@ pub fn is_supported_audio(path: &Path) -> bool {
         .any(|candidate| extension.eq_ignore_ascii_case(candidate))
 }
 
+fn gain_value(value: Option<&str>) -> Option<f32> {
+    let value = value?.trim();
+    let value = value
+        .strip_suffix("dB")
+        .or_else(|| value.strip_suffix("db"))
+        .unwrap_or(value);
+    value.trim().parse().ok().filter(|value: &f32| value.is_finite())
+}
+
+fn year_value(value: &str) -> Option<u32> {
+    value
+        .split(|character: char| !character.is_ascii_digit())
+        .find(|part| part.len() == 4)?
+        .parse()
+        .ok()
+        .filter(|year| *year > 0)
+}
+
+fn rating_value(value: &str) -> Option<u8> {
+    let value = value.trim();
+    let rating = match value.split('|').nth(1) {
+        Some(stars) => stars.trim().parse::<u32>().ok()?,
+        None => match value.parse::<f32>().ok()? {
+            rating if rating <= 5.0 => rating.round() as u32,
+            rating if rating <= 100.0 => (rating / 20.0).round() as u32,
+            rating => (rating / 51.0).round() as u32,
+        },
+    };
+    u8::try_from(rating.min(5)).ok().filter(|rating| *rating > 0)
+}
+
+fn flag_value(value: &str) -> bool {
+    let value = value.trim();
+    value
+        .parse::<u32>()
+        .map_or_else(|_| value.eq_ignore_ascii_case("true"), |flag| flag > 0)
+}
+
+fn text_value(value: &str) -> Option<Arc<str>> {
+    let value = value.trim();
+    (!value.is_empty()).then(|| Arc::from(value))
+}
+
+fn codec_name(file_type: FileType, bit_depth: Option<u8>) -> Option<&'static str> {
+    Some(match file_type {
+        FileType::Aac => "AAC",
+        FileType::Aiff => "AIFF",
+        FileType::Ape => "APE",
+        FileType::Flac => "FLAC",
+        FileType::Mpeg => "MP3",
+        FileType::Mp4 if bit_depth.is_some() => "ALAC",
+        FileType::Mp4 => "AAC",
+        FileType::Mpc => "Musepack",
+        FileType::Opus => "Opus",
+        FileType::Vorbis => "Vorbis",
+        FileType::Speex => "Speex",
+        FileType::Wav => "WAV",
+        FileType::WavPack => "WavPack",
+        _ => return None,
+    })
+}
+
 fn non_empty(value: Option<String>) -> Option<String> {
     value.filter(|value| !value.trim().is_empty())
 }
@@ -1,19 +1,67 @@
 mod app;
 mod audio;
+mod cli;
+#[cfg(unix)]
+mod control;
+mod cue;
+mod history;
 mod library;
+mod mpd;
+#[cfg(feature = "mpris")]
+mod mpris;
+mod playlist_file;
+mod query;
+mod scrobble;
+mod smart;
 mod storage;
 mod ui;
 
-use std::io;
+use std::{env, io, process::ExitCode};
+#[cfg(unix)]
+use std::{net::Shutdown, os::unix::net::UnixStream, thread, time::Duration};
 
-use app::App;
+use app::{App, LaunchOptions};
+use cli::Command;
 use crossterm::event::{self, Event, KeyEventKind};
+#[cfg(unix)]
+use control::AttachEvent;
+#[cfg(unix)]
+use ui::Screen;
 use ui::Terminal;
 
 type Result<T> = io::Result<T>;
 
-fn main() -> Result<()> {
-    let mut app = App::new();
+fn main() -> ExitCode {
+    let invocation = match cli::parse(env::args_os().skip(1)) {
+        Ok(invocation) => invocation,
+        Err(error) => {
+            eprintln!("musa: {error}\n\n{}", cli::USAGE);
+            return ExitCode::from(2);
+        }
+    };
+
+    let result = cli::apply(&invocation).and_then(|()| match invocation.command {
+        Command::Tui(options) => run_tui(options),
+        #[cfg(unix)]
+        Command::Daemon {
+            options,
+            detach: false,
+        } => run_daemon(options),
+        command => cli::run(command),
+    });
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) if error.kind() == io::ErrorKind::BrokenPipe => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("musa: {error}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run_tui(options: LaunchOptions) -> Result<()> {
+    let mut app = App::new(options);
     let mut terminal = Terminal::enter()?;
 
     let mut redraw = true;
@@ -21,6 +69,15 @@ fn main() -> Result<()> {
 
     loop {
         redraw |= app.tick();
+        if app.quit_requested() {
+            break;
+        }
+        #[cfg(unix)]
+        for event in app.attach_events() {
+            if let AttachEvent::Attach { output, .. } = event {
+                let _ = output.shutdown(Shutdown::Both);
+            }
+        }
 
         let next_progress_epoch = app.progress_epoch();
         if next_progress_epoch != progress_epoch {
@@ -51,5 +108,115 @@ fn main() -> Result<()> {
         }
     }
 
-    Ok(())
+    drop(terminal);
+    app.finish_listening();
+    app.save_session()
+}
+
+#[cfg(unix)]
+struct Attached {
+    id: u64,
+    screen: Screen<UnixStream>,
+    width: u16,
+    height: u16,
+}
+
+#[cfg(unix)]
+fn run_daemon(options: LaunchOptions) -> Result<()> {
+    let mut app = App::new(options);
+    if !app.control_active() {
+        return Err(io::Error::other(app.status.clone()));
+    }
+
+    let mut attached: Option<Attached> = None;
+    let mut redraw = false;
+    let mut progress_epoch = app.progress_epoch();
+    let mut status = app.status.clone();
+
+    while !app.quit_requested() {
+        redraw |= app.tick();
+
+        for event in app.attach_events() {
+            match event {
+                AttachEvent::Attach {
+                    id,
+                    output,
+                    width,
+                    height,
+                } => {
+                    if let Some(previous) = attached.take() {
+                        let _ = previous.screen.get_ref().shutdown(Shutdown::Both);
+                    }
+                    attached = Some(Attached {
+                        id,
+                        screen: Screen::new(output),
+                        width,
+                        height,
+                    });
+                    redraw = true;
+                }
+                AttachEvent::Key { id, key } => {
+                    if attached.as_ref().is_some_and(|client| client.id == id) {
+                        if app.handle_key(key) {
+                            if let Some(client) = attached.take() {
+                                let _ = client.screen.get_ref().shutdown(Shutdown::Both);
+                            }
+                        }
+                        redraw = true;
+                    }
+                }
+                AttachEvent::Resize { id, width, height } => {
+                    if let Some(client) = attached.as_mut().filter(|client| client.id == id) {
+                        client.width = width;
+                        client.height = height;
+                        redraw = true;
+                    }
+                }
+                AttachEvent::Detach { id } => {
+                    if attached.as_ref().is_some_and(|client| client.id == id) {
+                        attached = None;
+                    }
+                }
+            }
+        }
+
+        if app.status != status {
+            status = app.status.clone();
+            if !status.is_empty() {
+                eprintln!("musa: {status}");
+            }
+        }
+
+        let next_progress_epoch = app.progress_epoch();
+        if next_progress_epoch != progress_epoch {
+            progress_epoch = next_progress_epoch;
+            redraw = true;
+        }
+
+        if redraw {
+            if let Some(client) = &mut attached {
+                if client
+                    .screen
+                    .draw(&app, client.width, client.height)
+                    .is_err()
+                {
+                    attached = None;
+                }
+            }
+            redraw = false;
+        }
+
+        let interval = if attached.is_some() {
+            Duration::from_millis(20)
+        } else {
+            app.poll_interval().min(Duration::from_millis(250))
+        };
+        thread::sleep(interval);
+    }
+
+    if let Some(client) = attached {
+        let _ = client.screen.get_ref().shutdown(Shutdown::Both);
+    }
+    app.finish_listening();
+    app.save_session()
 }
@@ -0,0 +1,1279 @@
+use std::{
+    collections::{BTreeSet, hash_map::DefaultHasher},
+    fmt::Write as _,
+    hash::{Hash, Hasher},
+    io::{self, BufRead, BufReader, Write},
+    mem,
+    net::{Ipv4Addr, Shutdown, SocketAddr, TcpListener, TcpStream},
+    path::{Path, PathBuf},
+    sync::{
+        Arc, Mutex,
+        mpsc::{self, Receiver, RecvTimeoutError, Sender},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    Result,
+    app::{App, PlaybackState, RemoteCommand, RepeatMode},
+    library::{self, DirectoryEntryKind, Track},
+};
+
+const GREETING: &str = "OK MPD 0.23.5";
+const REPLY_TIMEOUT: Duration = Duration::from_secs(10);
+const IDLE_POLL: Duration = Duration::from_millis(100);
+const SUBSYSTEMS: [&str; 6] = [
+    "database",
+    "stored_playlist",
+    "playlist",
+    "player",
+    "mixer",
+    "options",
+];
+const TAG_TYPES: [&str; 6] = ["Artist", "AlbumArtist", "Album", "Title", "Track", "Disc"];
+const COMMANDS: &[&str] = &[
+    "add",
+    "addid",
+    "clear",
+    "close",
+    "command_list_begin",
+    "command_list_end",
+    "command_list_ok_begin",
+    "commands",
+    "consume",
+    "count",
+    "currentsong",
+    "decoders",
+    "delete",
+    "deleteid",
+    "find",
+    "getvol",
+    "idle",
+    "list",
+    "listplaylist",
+    "listplaylistinfo",
+    "listplaylists",
+    "load",
+    "lsinfo",
+    "next",
+    "noidle",
+    "notcommands",
+    "outputs",
+    "pause",
+    "ping",
+    "play",
+    "playid",
+    "playlistid",
+    "playlistinfo",
+    "plchanges",
+    "plchangesposid",
+    "previous",
+    "random",
+    "repeat",
+    "search",
+    "seek",
+    "seekcur",
+    "seekid",
+    "setvol",
+    "single",
+    "stats",
+    "status",
+    "stop",
+    "tagtypes",
+];
+
+const ACK_ARG: u32 = 2;
+const ACK_UNKNOWN: u32 = 5;
+const ACK_NO_EXIST: u32 = 50;
+const ACK_SYSTEM: u32 = 52;
+
+pub struct Request {
+    commands: Vec<Vec<String>>,
+    list_ok: bool,
+    reply: Sender<String>,
+}
+
+struct Ack {
+    code: u32,
+    message: String,
+}
+
+impl Ack {
+    fn new(code: u32, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+impl From<io::Error> for Ack {
+    fn from(error: io::Error) -> Self {
+        Self::new(ACK_SYSTEM, error.to_string())
+    }
+}
+
+type Response = std::result::Result<(), Ack>;
+
+#[derive(Default, PartialEq, Eq)]
+struct Snapshot {
+    values: [u64; SUBSYSTEMS.len()],
+}
+
+pub struct MpdServer {
+    requests: Receiver<Request>,
+    events: Arc<Mutex<[u64; SUBSYSTEMS.len()]>>,
+    snapshot: Option<Snapshot>,
+    playlist_version: u64,
+    started: Instant,
+}
+
+impl MpdServer {
+    pub fn start(port: u16) -> Result<Self> {
+        Self::serve(TcpListener::bind(SocketAddr::from((Ipv4Addr::LOCALHOST, port)))?)
+    }
+
+    fn serve(listener: TcpListener) -> Result<Self> {
+        let (sender, requests) = mpsc::channel();
+        let events = Arc::new(Mutex::new([0; SUBSYSTEMS.len()]));
+
+        let client_events = Arc::clone(&events);
+        thread::Builder::new()
+            .name("musa-mpd".to_owned())
+            .spawn(move || {
+                for stream in listener.incoming().flatten() {
+                    let sender = sender.clone();
+                    let events = Arc::clone(&client_events);
+                    let _ = thread::Builder::new()
+                        .name("musa-mpd-client".to_owned())
+                        .spawn(move || serve_client(stream, &sender, &events));
+                }
+            })?;
+
+        Ok(Self {
+            requests,
+            events,
+            snapshot: None,
+            playlist_version: 1,
+            started: Instant::now(),
+        })
+    }
+
+    pub fn requests(&self) -> Vec<Request> {
+        self.requests.try_iter().collect()
+    }
+
+    pub fn respond(&self, app: &mut App, request: Request) {
+        let mut output = String::new();
+        for (index, command) in request.commands.iter().enumerate() {
+            let name = command.first().map_or("", String::as_str);
+            if let Err(ack) = self.execute(app, name, &command[1..], &mut output) {
+                let _ = writeln!(
+                    output,
+                    "ACK [{}@{index}] {{{name}}} {}",
+                    ack.code, ack.message
+                );
+                let _ = request.reply.send(output);
+                return;
+            }
+            if request.list_ok {
+                output.push_str("list_OK\n");
+            }
+        }
+        output.push_str("OK\n");
+        let _ = request.reply.send(output);
+    }
+
+    pub fn update(&mut self, app: &App) {
+        let snapshot = snapshot(app);
+        if let Some(previous) = &self.snapshot {
+            if *previous == snapshot {
+                return;
+            }
+            let mut events = self
+                .events
+                .lock()
+                .unwrap_or_else(|error| error.into_inner());
+            for (index, counter) in events.iter_mut().enumerate() {
+                if previous.values[index] != snapshot.values[index] {
+                    *counter += 1;
+                    if SUBSYSTEMS[index] == "playlist" {
+                        self.playlist_version += 1;
+                    }
+                }
+            }
+        }
+        self.snapshot = Some(snapshot);
+    }
+
+    fn execute(&self, app: &mut App, name: &str, args: &[String], output: &mut String) -> Response {
+        match name {
+            "ping" => Ok(()),
+            "commands" => {
+                for command in COMMANDS {
+                    let _ = writeln!(output, "command: {command}");
+                }
+                Ok(())
+            }
+            "notcommands" | "decoders" | "urlhandlers" => Ok(()),
+            "tagtypes" => {
+                if args.is_empty() {
+                    for tag in TAG_TYPES {
+                        let _ = writeln!(output, "tagtype: {tag}");
+                    }
+                }
+                Ok(())
+            }
+            "outputs" => {
+                output.push_str("outputid: 0\noutputname: Musa\nplugin: musa\noutputenabled: 1\n");
+                Ok(())
+            }
+            "status" => {
+                write_status(app, self.playlist_version, output);
+                Ok(())
+            }
+            "currentsong" => {
+                let Some(position) = current_position(app) else {
+                    return Ok(());
+                };
+                if let Some((path, track)) = queue_entries(app).into_iter().nth(position) {
+                    write_song(output, &path, track, Some(position));
+                }
+                Ok(())
+            }
+            "stats" => {
+                let (albums, artists) = (app.albums.len(), app.artists.len());
+                let playtime: f64 = app
+                    .tracks
+                    .iter()
+                    .filter_map(|track| track.duration)
+                    .map(|duration| duration.as_secs_f64())
+                    .sum();
+                let _ = writeln!(output, "artists: {artists}");
+                let _ = writeln!(output, "albums: {albums}");
+                let _ = writeln!(output, "songs: {}", app.tracks.len());
+                let _ = writeln!(output, "uptime: {}", self.started.elapsed().as_secs());
+                let _ = writeln!(output, "playtime: {}", self.started.elapsed().as_secs());
+                let _ = writeln!(output, "db_playtime: {}", playtime as u64);
+                Ok(())
+            }
+            "play" => match args.first() {
+                Some(position) => remote(app, RemoteCommand::PlayQueuePosition(parse(position)?)),
+                None => remote(app, RemoteCommand::Play),
+            },
+            "playid" => match args.first() {
+                Some(id) => remote(app, RemoteCommand::PlayQueuePosition(position_for_id(id)?)),
+                None => remote(app, RemoteCommand::Play),
+            },
+            "pause" => match args.first().map(String::as_str) {
+                Some("1") => remote(app, RemoteCommand::Pause),
+                Some("0") => remote(app, RemoteCommand::Play),
+                _ => remote(app, RemoteCommand::PlayPause),
+            },
+            "stop" => remote(app, RemoteCommand::Stop),
+            "next" => remote(app, RemoteCommand::Next),
+            "previous" => remote(app, RemoteCommand::Previous),
+            "seek" | "seekid" => {
+                let [target, time] = args else {
+                    return Err(Ack::new(ACK_ARG, "wrong number of arguments"));
+                };
+                let position = if name == "seek" {
+                    parse(target)?
+                } else {
+                    position_for_id(target)?
+                };
+                if current_position(app) != Some(position) || app.state == PlaybackState::Stopped {
+                    remote(app, RemoteCommand::PlayQueuePosition(position))?;
+                }
+                remote(app, RemoteCommand::SetPosition(parse_seconds(time)?))
+            }
+            "seekcur" => {
+                let [time] = args else {
+                    return Err(Ack::new(ACK_ARG, "wrong number of arguments"));
+                };
+                let seconds = parse_seconds(time)?;
+                if time.starts_with(['+', '-']) {
+                    remote(app, RemoteCommand::Seek(seconds))
+                } else {
+                    remote(app, RemoteCommand::SetPosition(seconds))
+                }
+            }
+            "setvol" => {
+                let volume: u8 = parse(argument(args)?)?;
+                remote(app, RemoteCommand::SetVolume(volume.min(100)))
+            }
+            "volume" => remote(app, RemoteCommand::ChangeVolume(parse(argument(args)?)?)),
+            "getvol" => {
+                let _ = writeln!(output, "volume: {}", app.volume);
+                Ok(())
+            }
+            "random" => remote(app, RemoteCommand::SetShuffle(parse_flag(args)?)),
+            "repeat" => {
+                let repeat = match (parse_flag(args)?, app.repeat) {
+                    (false, _) => RepeatMode::Off,
+                    (true, RepeatMode::One) => RepeatMode::One,
+                    (true, _) => RepeatMode::All,
+                };
+                remote(app, RemoteCommand::SetRepeat(repeat))
+            }
+            "single" => {
+                let repeat = match (parse_flag(args)?, app.repeat) {
+                    (true, _) => RepeatMode::One,
+                    (false, RepeatMode::One) => RepeatMode::All,
+                    (false, repeat) => repeat,
+                };
+                remote(app, RemoteCommand::SetRepeat(repeat))
+            }
+            "consume" => match parse_flag(args)? {
+                false => Ok(()),
+                true => Err(Ack::new(ACK_ARG, "consume mode is not supported")),
+            },
+            "playlistinfo" | "playlistid" | "plchanges" => {
+                let entries = queue_entries(app);
+                let range = match (name, args.first()) {
+                    ("playlistinfo", Some(range)) => parse_range(range, entries.len())?,
+                    ("playlistid", Some(id)) => {
+                        let position = position_for_id(id)?;
+                        position..position + 1
+                    }
+                    _ => 0..entries.len(),
+                };
+                if range.end > entries.len() {
+                    return Err(Ack::new(ACK_ARG, "Bad song index"));
+                }
+                for (position, (path, track)) in entries.into_iter().enumerate() {
+                    if range.contains(&position) {
+                        write_song(output, &path, track, Some(position));
+                    }
+                }
+                Ok(())
+            }
+            "plchangesposid" => {
+                for position in 0..app.active_queue_len() {
+                    let _ = writeln!(output, "cpos: {position}\nId: {}", position + 1);
+                }
+                Ok(())
+            }
+            "add" | "addid" => {
+                let uri = argument(args)?;
+                let path = uri_path(uri);
+                if !path.is_absolute() {
+                    return Err(Ack::new(ACK_NO_EXIST, "No such directory"));
+                }
+                remote(app, RemoteCommand::Enqueue { path, next: false })?;
+                if name == "addid" {
+                    let _ = writeln!(output, "Id: {}", app.active_queue_len());
+                }
+                Ok(())
+            }
+            "clear" => remote(app, RemoteCommand::ClearQueue),
+            "delete" | "deleteid" => {
+                let len = app.active_queue_len();
+                let range = if name == "delete" {
+                    parse_range(argument(args)?, len)?
+                } else {
+                    let position = position_for_id(argument(args)?)?;
+                    position..position + 1
+                };
+                if range.end > len || range.is_empty() {
+                    return Err(Ack::new(ACK_ARG, "Bad song index"));
+                }
+                for position in range.rev() {
+                    remote(app, RemoteCommand::RemoveQueuePosition(position))?;
+                }
+                Ok(())
+            }
+            "find" | "search" | "count" => {
+                let filter = Filter::parse(args, name == "search")?;
+                let matches = app.tracks.iter().filter(|track| filter.matches(track));
+                if name == "count" {
+                    let (songs, playtime) = matches.fold((0, 0.0), |(songs, playtime), track| {
+                        let seconds = track
+                            .duration
+                            .map_or(0.0, |duration| duration.as_secs_f64());
+                        (songs + 1, playtime + seconds)
+                    });
+                    let _ = writeln!(output, "songs: {songs}\nplaytime: {}", playtime as u64);
+                } else {
+                    for track in matches {
+                        write_song(output, &track.path, Some(track), None);
+                    }
+                }
+                Ok(())
+            }
+            "list" => {
+                let Some((tag, rest)) = args.split_first() else {
+                    return Err(Ack::new(ACK_ARG, "too few arguments for \"list\""));
+                };
+                let tag = Tag::parse(tag)?;
+                let (filter_args, group) = match rest.iter().position(|arg| arg == "group") {
+                    Some(index) => (
+                        &rest[..index],
+                        rest.get(index + 1).map(|tag| Tag::parse(tag)).transpose()?,
+                    ),
+                    None => (rest, None),
+                };
+                let filter = if tag == Tag::Album && filter_args.len() == 1 {
+                    Filter::parse(&["artist".to_owned(), filter_args[0].clone()], false)?
+                } else {
+                    Filter::parse(filter_args, false)?
+                };
+
+                let values: BTreeSet<(String, String)> = app
+                    .tracks
+                    .iter()
+                    .filter(|track| filter.matches(track))
+                    .map(|track| {
+                        let group = group.map(|group| group.value(track)).unwrap_or_default();
+                        (group, tag.value(track))
+                    })
+                    .collect();
+                let mut last_group = None;
+                for (group_value, value) in values {
+                    if let Some(group) = group {
+                        if last_group.as_ref() != Some(&group_value) {
+                            let _ = writeln!(output, "{}: {group_value}", group.name());
+                            last_group = Some(group_value);
+                        }
+                    }
+                    let _ = writeln!(output, "{}: {value}", tag.name());
+                }
+                Ok(())
+            }
+            "lsinfo" => {
+                let uri = args.first().map(String::as_str).unwrap_or("");
+                if uri.is_empty() || uri == "/" {
+                    for root in &app.roots {
+                        let _ = writeln!(output, "directory: {}", root.display());
+                    }
+                    for playlist in &app.playlists {
+                        let _ = writeln!(output, "playlist: {}", playlist.name);
+                    }
+                    return Ok(());
+                }
+
+                let path = uri_path(uri);
+                if path.is_file() {
+                    write_song(output, &path, app.library_track(&path), None);
+                    return Ok(());
+                }
+                let entries = library::read_directory_entries(&path)
+                    .map_err(|_| Ack::new(ACK_NO_EXIST, "No such directory"))?;
+                for entry in entries {
+                    match entry.kind {
+                        DirectoryEntryKind::Directory(directory) => {
+                            let _ = writeln!(output, "directory: {}", directory.display());
+                        }
+                        DirectoryEntryKind::Playlist(playlist) => {
+                            let _ = writeln!(output, "playlist: {}", playlist.display());
+                        }
+                        DirectoryEntryKind::Track(track) => {
+                            let track = app.library_track(&track.path).cloned().unwrap_or(*track);
+                            write_song(output, &track.path, Some(&track), None);
+                        }
+                    }
+                }
+                Ok(())
+            }
+            "listplaylists" => {
+                for playlist in &app.playlists {
+                    let _ = writeln!(output, "playlist: {}", playlist.name);
+                }
+                Ok(())
+            }
+            "listplaylist" | "listplaylistinfo" => {
+                let requested = argument(args)?;
+                let Some(playlist) = app
+                    .playlists
+                    .iter()
+                    .find(|playlist| playlist.name == *requested)
+                else {
+                    return Err(Ack::new(ACK_NO_EXIST, "No such playlist"));
+                };
+                for path in &playlist.track_paths {
+                    if name == "listplaylist" {
+                        let _ = writeln!(output, "file: {}", path.display());
+                    } else {
+                        write_song(output, path, app.library_track(path), None);
+                    }
+                }
+                Ok(())
+            }
+            "load" => remote(app, RemoteCommand::LoadPlaylist(argument(args)?.clone())),
+            "" => Err(Ack::new(ACK_UNKNOWN, "No command given")),
+            _ => Err(Ack::new(ACK_UNKNOWN, format!("unknown command \"{name}\""))),
+        }
+    }
+}
+
+fn serve_client(
+    stream: TcpStream,
+    requests: &Sender<Request>,
+    events: &Mutex<[u64; SUBSYSTEMS.len()]>,
+) {
+    let Ok(reader) = stream.try_clone() else {
+        return;
+    };
+    let mut writer = stream;
+    if writeln!(writer, "{GREETING}").is_err() {
+        return;
+    }
+
+    let (line_sender, lines) = mpsc::channel();
+    thread::spawn(move || {
+        for line in BufReader::new(reader).lines() {
+            let Ok(line) = line else {
+                return;
+            };
+            if line_sender.send(line).is_err() {
+                return;
+            }
+        }
+    });
+
+    let counters = || *events.lock().unwrap_or_else(|error| error.into_inner());
+    let mut seen = counters();
+    let mut list: Option<(Vec<Vec<String>>, bool)> = None;
+
+    while let Ok(line) = lines.recv() {
+        let command = match tokenize(&line) {
+            Ok(command) => command,
+            Err(message) => {
+                let _ = writeln!(writer, "ACK [{ACK_ARG}@0] {{}} {message}");
+                continue;
+            }
+        };
+        let name = command.first().map_or("", String::as_str);
+
+        if let Some((commands, list_ok)) = &mut list {
+            if name != "command_list_end" {
+                commands.push(command);
+                continue;
+            }
+            let (commands, list_ok) = (mem::take(commands), *list_ok);
+            list = None;
+            if !forward(requests, &mut writer, commands, list_ok) {
+                return;
+            }
+            continue;
+        }
+
+        let written = match name {
+            "close" => {
+                let _ = writer.shutdown(Shutdown::Both);
+                return;
+            }
+            "noidle" => Ok(()),
+            "command_list_begin" | "command_list_ok_begin" => {
+                list = Some((Vec::new(), name == "command_list_ok_begin"));
+                Ok(())
+            }
+            "idle" => {
+                let wanted: Vec<usize> = SUBSYSTEMS
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, subsystem)| {
+                        command.len() == 1 || command[1..].iter().any(|arg| arg == *subsystem)
+                    })
+                    .map(|(index, _)| index)
+                    .collect();
+                let mut response = String::new();
+                loop {
+                    let current = counters();
+                    let changed: Vec<usize> = wanted
+                        .iter()
+                        .copied()
+                        .filter(|index| current[*index] != seen[*index])
+                        .collect();
+                    if !changed.is_empty() {
+                        for index in changed {
+                            let _ = writeln!(response, "changed: {}", SUBSYSTEMS[index]);
+                        }
+                        seen = current;
+                        break;
+                    }
+                    match lines.recv_timeout(IDLE_POLL) {
+                        Ok(line) if line.trim() == "noidle" => break,
+                        Ok(_) | Err(RecvTimeoutError::Disconnected) => return,
+                        Err(RecvTimeoutError::Timeout) => {}
+                    }
+                }
+                response.push_str("OK\n");
+                writer.write_all(response.as_bytes())
+            }
+            _ => {
+                if !forward(requests, &mut writer, vec![command], false) {
+                    return;
+                }
+                Ok(())
+            }
+        };
+        if written.and_then(|()| writer.flush()).is_err() {
+            return;
+        }
+    }
+}
+
+fn forward(
+    requests: &Sender<Request>,
+    writer: &mut TcpStream,
+    commands: Vec<Vec<String>>,
+    list_ok: bool,
+) -> bool {
+    let (reply, response) = mpsc::channel();
+    if requests
+        .send(Request {
+            commands,
+            list_ok,
+            reply,
+        })
+        .is_err()
+    {
+        return false;
+    }
+    let output = response
+        .recv_timeout(REPLY_TIMEOUT)
+        .unwrap_or_else(|_| format!("ACK [{ACK_SYSTEM}@0] {{}} the player did not respond\n"));
+    writer
+        .write_all(output.as_bytes())
+        .and_then(|()| writer.flush())
+        .is_ok()
+}
+
+fn remote(app: &mut App, command: RemoteCommand) -> Response {
+    app.handle_remote(command).map_err(Ack::from)
+}
+
+fn argument(args: &[String]) -> std::result::Result<&String, Ack> {
+    args.first()
+        .ok_or_else(|| Ack::new(ACK_ARG, "missing argument"))
+}
+
+fn parse<T: std::str::FromStr>(value: &str) -> std::result::Result<T, Ack> {
+    value
+        .parse()
+        .map_err(|_| Ack::new(ACK_ARG, format!("Integer expected: {value}")))
+}
+
+fn parse_seconds(value: &str) -> std::result::Result<f64, Ack> {
+    let seconds = value
+        .parse::<f64>()
+        .ok()
+        .filter(|seconds| seconds.is_finite())
+        .ok_or_else(|| Ack::new(ACK_ARG, format!("Number expected: {value}")))?;
+    if seconds.abs() > f64::from(u32::MAX) {
+        return Err(Ack::new(ACK_ARG, format!("Number out of range: {value}")));
+    }
+    Ok(seconds)
+}
+
+fn parse_flag(args: &[String]) -> std::result::Result<bool, Ack> {
+    match argument(args)?.as_str() {
+        "0" => Ok(false),
+        "1" => Ok(true),
+        value => Err(Ack::new(
+            ACK_ARG,
+            format!("Boolean (0/1) expected: {value}"),
+        )),
+    }
+}
+
+fn parse_range(value: &str, len: usize) -> std::result::Result<std::ops::Range<usize>, Ack> {
+    match value.split_once(':') {
+        Some((start, "")) => Ok(parse(start)?..len),
+        Some((start, end)) => Ok(parse(start)?..parse(end)?),
+        None => {
+            let position: usize = parse(value)?;
+            Ok(position..position + 1)
+        }
+    }
+}
+
+fn position_for_id(id: &str) -> std::result::Result<usize, Ack> {
+    let id: usize = parse(id)?;
+    id.checked_sub(1)
+        .ok_or_else(|| Ack::new(ACK_NO_EXIST, "No such song"))
+}
+
+fn uri_path(uri: &str) -> PathBuf {
+    PathBuf::from(uri.strip_prefix("file://").unwrap_or(uri))
+}
+
+fn current_position(app: &App) -> Option<usize> {
+    app.current_track()?;
+    app.active_queue_position()
+}
+
+fn queue_entries(app: &App) -> Vec<(PathBuf, Option<&Track>)> {
+    if let Some(paths) = app.folder_queue() {
+        let current = app.current_track();
+        return paths
+            .iter()
+            .map(|path| {
+                let track = app
+                    .library_track(path)
+                    .or(current.filter(|track| track.path == *path));
+                (path.clone(), track)
+            })
+            .collect();
+    }
+    app.queue
+        .iter()
+        .filter_map(|index| app.tracks.get(*index))
+        .map(|track| (track.path.clone(), Some(track)))
+        .collect()
+}
+
+fn write_status(app: &App, playlist_version: u64, output: &mut String) {
+    let status = app.player_status();
+    let state = match status.state {
+        PlaybackState::Playing => "play",
+        PlaybackState::Paused => "pause",
+        PlaybackState::Stopped => "stop",
+    };
+    let len = app.active_queue_len();
+    let _ = writeln!(output, "volume: {}", status.volume);
+    let _ = writeln!(
+        output,
+        "repeat: {}",
+        u8::from(status.repeat != RepeatMode::Off)
+    );
+    let _ = writeln!(output, "random: {}", u8::from(status.shuffle));
+    let _ = writeln!(
+        output,
+        "single: {}",
+        u8::from(status.repeat == RepeatMode::One)
+    );
+    let _ = writeln!(output, "consume: 0");
+    let _ = writeln!(output, "playlist: {playlist_version}");
+    let _ = writeln!(output, "playlistlength: {len}");
+    let _ = writeln!(output, "state: {state}");
+
+    let Some(position) = current_position(app) else {
+        return;
+    };
+    let _ = writeln!(output, "song: {position}\nsongid: {}", position + 1);
+    if position + 1 < len {
+        let _ = writeln!(
+            output,
+            "nextsong: {}\nnextsongid: {}",
+            position + 1,
+            position + 2
+        );
+    }
+    if status.state != PlaybackState::Stopped {
+        let elapsed = status.position.as_secs_f64();
+        let total = status
+            .total
+            .or(status.track.as_ref().and_then(|track| track.duration))
+            .map_or(0.0, |total| total.as_secs_f64());
+        let _ = writeln!(output, "time: {}:{}", elapsed as u64, total.round() as u64);
+        let _ = writeln!(output, "elapsed: {elapsed:.3}");
+        let _ = writeln!(output, "duration: {total:.3}");
+    }
+}
+
+fn write_song(output: &mut String, path: &Path, track: Option<&Track>, position: Option<usize>) {
+    let _ = writeln!(output, "file: {}", single_line(&path.to_string_lossy()));
+    if let Some(track) = track {
+        for tag in [Tag::Artist, Tag::AlbumArtist, Tag::Album, Tag::Title] {
+            let _ = writeln!(output, "{}: {}", tag.name(), single_line(&tag.value(track)));
+        }
+        if let Some(track_no) = track.track_no {
+            let _ = writeln!(output, "Track: {track_no}");
+        }
+        if let Some(disc_no) = track.disc_no {
+            let _ = writeln!(output, "Disc: {disc_no}");
+        }
+        if let Some(duration) = track.duration {
+            let _ = writeln!(output, "Time: {}", duration.as_secs_f64().round() as u64);
+            let _ = writeln!(output, "duration: {:.3}", duration.as_secs_f64());
+        }
+        if let Some(range) = track.range {
+            let end = range
+                .end
+                .map(|end| format!("{:.3}", end.as_secs_f64()))
+                .unwrap_or_default();
+            let _ = writeln!(output, "Range: {:.3}-{end}", range.start.as_secs_f64());
+        }
+    } else if let Some(name) = path.file_stem() {
+        let _ = writeln!(output, "Title: {}", single_line(&name.to_string_lossy()));
+    }
+    if let Some(position) = position {
+        let _ = writeln!(output, "Pos: {position}\nId: {}", position + 1);
+    }
+}
+
+fn single_line(text: &str) -> String {
+    text.replace(['\n', '\r'], " ")
+}
+
+fn snapshot(app: &App) -> Snapshot {
+    fn hash(value: impl Hash) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        value.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    let current = app.current_track().map(|track| track.path.as_path());
+    let playlists: Vec<(&str, usize)> = app
+        .playlists
+        .iter()
+        .map(|playlist| (playlist.name.as_str(), playlist.track_paths.len()))
+        .collect();
+    let queue = match app.folder_queue() {
+        Some(paths) => hash(paths),
+        None => hash(&app.queue),
+    };
+
+    Snapshot {
+        values: [
+            hash(app.library_generation),
+            hash(playlists),
+            queue,
+            hash((app.state as u8, current, app.active_queue_position())),
+            hash(app.volume),
+            hash((app.shuffle, app.repeat as u8)),
+        ],
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Tag {
+    Any,
+    File,
+    Artist,
+    AlbumArtist,
+    Album,
+    Title,
+    Track,
+    Disc,
+}
+
+impl Tag {
+    fn parse(name: &str) -> std::result::Result<Self, Ack> {
+        Ok(match name.to_ascii_lowercase().as_str() {
+            "any" => Self::Any,
+            "file" | "base" => Self::File,
+            "artist" | "artistsort" => Self::Artist,
+            "albumartist" | "albumartistsort" => Self::AlbumArtist,
+            "album" | "albumsort" => Self::Album,
+            "title" | "titlesort" => Self::Title,
+            "track" => Self::Track,
+            "disc" => Self::Disc,
+            _ => return Err(Ack::new(ACK_ARG, format!("Unknown tag type: {name}"))),
+        })
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Any => "Any",
+            Self::File => "file",
+            Self::Artist => "Artist",
+            Self::AlbumArtist => "AlbumArtist",
+            Self::Album => "Album",
+            Self::Title => "Title",
+            Self::Track => "Track",
+            Self::Disc => "Disc",
+        }
+    }
+
+    fn value(self, track: &Track) -> String {
+        match self {
+            Self::Any => [
+                &track.artist,
+                &track.album_artist,
+                &track.album,
+                &track.title,
+            ]
+            .map(|value| value.as_ref())
+            .join(" "),
+            Self::File => track.path.to_string_lossy().into_owned(),
+            Self::Artist => track.artist.to_string(),
+            Self::AlbumArtist => track.album_artist.to_string(),
+            Self::Album => track.album.to_string(),
+            Self::Title => track.title.to_string(),
+            Self::Track => track
+                .track_no
+                .map(|value| value.to_string())
+                .unwrap_or_default(),
+            Self::Disc => track
+                .disc_no
+                .map(|value| value.to_string())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Filter {
+    All(Vec<Filter>),
+    Not(Box<Filter>),
+    Equals(Tag, String),
+    Contains(Tag, String),
+}
+
+impl Filter {
+    fn parse(args: &[String], fuzzy: bool) -> std::result::Result<Self, Ack> {
+        let args: Vec<&String> = {
+            let mut kept = Vec::new();
+            let mut iter = args.iter();
+            while let Some(arg) = iter.next() {
+                if matches!(arg.as_str(), "sort" | "window") {
+                    iter.next();
+                } else {
+                    kept.push(arg);
+                }
+            }
+            kept
+        };
+
+        if let [expression] = args.as_slice() {
+            if expression.starts_with('(') {
+                let mut parser = ExpressionParser {
+                    input: expression.as_bytes(),
+                    offset: 0,
+                };
+                let filter = parser.expression()?;
+                parser.skip_spaces();
+                if parser.offset != parser.input.len() {
+                    return Err(Ack::new(ACK_ARG, "trailing text after filter expression"));
+                }
+                return Ok(filter);
+            }
+        }
+
+        if !args.len().is_multiple_of(2) {
+            return Err(Ack::new(ACK_ARG, "incorrect number of filter arguments"));
+        }
+        let filters = args
+            .chunks(2)
+            .map(|pair| {
+                let tag = Tag::parse(pair[0])?;
+                Ok(if fuzzy {
+                    Filter::Contains(tag, pair[1].to_lowercase())
+                } else {
+                    Filter::Equals(tag, pair[1].clone())
+                })
+            })
+            .collect::<std::result::Result<_, Ack>>()?;
+        Ok(Filter::All(filters))
+    }
+
+    fn matches(&self, track: &Track) -> bool {
+        match self {
+            Self::All(filters) => filters.iter().all(|filter| filter.matches(track)),
+            Self::Not(filter) => !filter.matches(track),
+            Self::Equals(Tag::Any, value) => {
+                [Tag::Artist, Tag::AlbumArtist, Tag::Album, Tag::Title]
+                    .into_iter()
+                    .any(|tag| tag.value(track) == *value)
+            }
+            Self::Equals(tag, value) => tag.value(track) == *value,
+            Self::Contains(tag, value) => tag.value(track).to_lowercase().contains(value.as_str()),
+        }
+    }
+}
+
+struct ExpressionParser<'a> {
+    input: &'a [u8],
+    offset: usize,
+}
+
+impl ExpressionParser<'_> {
+    fn expression(&mut self) -> std::result::Result<Filter, Ack> {
+        self.expect(b'(')?;
+        self.skip_spaces();
+
+        let filter = if self.peek() == Some(b'!') {
+            self.offset += 1;
+            self.skip_spaces();
+            Filter::Not(Box::new(self.expression()?))
+        } else if self.peek() == Some(b'(') {
+            let mut filters = vec![self.expression()?];
+            loop {
+                self.skip_spaces();
+                if self.peek() == Some(b')') {
+                    break;
+                }
+                if !self.keyword("AND") {
+                    return Err(Ack::new(ACK_ARG, "expected AND in filter expression"));
+                }
+                self.skip_spaces();
+                filters.push(self.expression()?);
+            }
+            Filter::All(filters)
+        } else {
+            let tag = Tag::parse(&self.word())?;
+            self.skip_spaces();
+            let operator = self.word();
+            self.skip_spaces();
+            let value = self.quoted()?;
+            match operator.as_str() {
+                "==" => Filter::Equals(tag, value),
+                "!=" => Filter::Not(Box::new(Filter::Equals(tag, value))),
+                "contains" => Filter::Contains(tag, value.to_lowercase()),
+                _ => {
+                    return Err(Ack::new(
+                        ACK_ARG,
+                        format!("unsupported filter operator: {operator}"),
+                    ));
+                }
+            }
+        };
+
+        self.skip_spaces();
+        self.expect(b')')?;
+        Ok(filter)
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.input.get(self.offset).copied()
+    }
+
+    fn skip_spaces(&mut self) {
+        while self.peek().is_some_and(|byte| byte.is_ascii_whitespace()) {
+            self.offset += 1;
+        }
+    }
+
+    fn expect(&mut self, byte: u8) -> std::result::Result<(), Ack> {
+        if self.peek() != Some(byte) {
+            return Err(Ack::new(
+                ACK_ARG,
+                format!("expected '{}' in filter expression", byte as char),
+            ));
+        }
+        self.offset += 1;
+        Ok(())
+    }
+
+    fn keyword(&mut self, keyword: &str) -> bool {
+        let end = self.offset + keyword.len();
+        if self.input.get(self.offset..end) == Some(keyword.as_bytes()) {
+            self.offset = end;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn word(&mut self) -> String {
+        let start = self.offset;
+        while self.peek().is_some_and(|byte| {
+            !byte.is_ascii_whitespace() && !matches!(byte, b'(' | b')' | b'"' | b'\'')
+        }) {
+            self.offset += 1;
+        }
+        String::from_utf8_lossy(&self.input[start..self.offset]).into_owned()
+    }
+
+    fn quoted(&mut self) -> std::result::Result<String, Ack> {
+        let Some(quote) = self.peek().filter(|byte| matches!(byte, b'"' | b'\'')) else {
+            return Err(Ack::new(
+                ACK_ARG,
+                "expected a quoted value in filter expression",
+            ));
+        };
+        self.offset += 1;
+        let mut value = Vec::new();
+        while let Some(byte) = self.peek() {
+            self.offset += 1;
+            match byte {
+                b'\\' => {
+                    if let Some(escaped) = self.peek() {
+                        value.push(escaped);
+                        self.offset += 1;
+                    }
+                }
+                byte if byte == quote => return Ok(String::from_utf8_lossy(&value).into_owned()),
+                byte => value.push(byte),
+            }
+        }
+        Err(Ack::new(
+            ACK_ARG,
+            "unterminated string in filter expression",
+        ))
+    }
+}
+
+fn tokenize(line: &str) -> std::result::Result<Vec<String>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = line.trim().chars().peekable();
+
+    while let Some(&character) = chars.peek() {
+        if character.is_whitespace() {
+            chars.next();
+            continue;
+        }
+        let mut token = String::new();
+        if character == '"' {
+            chars.next();
+            loop {
+                match chars.next() {
+                    Some('\\') => match chars.next() {
+                        Some(escaped) => token.push(escaped),
+                        None => return Err("unterminated string".to_owned()),
+                    },
+                    Some('"') => break,
+                    Some(character) => token.push(character),
+                    None => return Err("unterminated string".to_owned()),
+                }
+            }
+        } else {
+            while let Some(&character) = chars.peek() {
+                if character.is_whitespace() {
+                    break;
+                }
+                token.push(character);
+                chars.next();
+            }
+        }
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process};
+
+    use super::*;
+    use crate::{app::LaunchOptions, storage};
+
+    fn write_wav(path: &Path) {
+        let samples = [0_u8; 800];
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(b"RIFF");
+        bytes.extend_from_slice(&(36 + samples.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(b"WAVEfmt ");
+        bytes.extend_from_slice(&16_u32.to_le_bytes());
+        bytes.extend_from_slice(&1_u16.to_le_bytes());
+        bytes.extend_from_slice(&1_u16.to_le_bytes());
+        bytes.extend_from_slice(&8000_u32.to_le_bytes());
+        bytes.extend_from_slice(&16000_u32.to_le_bytes());
+        bytes.extend_from_slice(&2_u16.to_le_bytes());
+        bytes.extend_from_slice(&16_u16.to_le_bytes());
+        bytes.extend_from_slice(b"data");
+        bytes.extend_from_slice(&(samples.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&samples);
+        fs::write(path, bytes).unwrap();
+    }
+
+    struct Client {
+        reader: BufReader<TcpStream>,
+        writer: TcpStream,
+    }
+
+    impl Client {
+        fn connect(port: u16) -> Self {
+            let writer = TcpStream::connect((Ipv4Addr::LOCALHOST, port)).unwrap();
+            writer.set_read_timeout(Some(REPLY_TIMEOUT)).unwrap();
+            let mut client = Self {
+                reader: BufReader::new(writer.try_clone().unwrap()),
+                writer,
+            };
+            assert_eq!(client.line(), GREETING);
+            client
+        }
+
+        fn line(&mut self) -> String {
+            let mut line = String::new();
+            self.reader.read_line(&mut line).unwrap();
+            line.trim_end_matches('\n').to_owned()
+        }
+
+        fn send(&mut self, lines: &[&str]) -> Vec<String> {
+            for line in lines {
+                writeln!(self.writer, "{line}").unwrap();
+            }
+            let mut reply = Vec::new();
+            loop {
+                let line = self.line();
+                let done = line == "OK" || line.starts_with("ACK ");
+                reply.push(line);
+                if done {
+                    return reply;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn scripted_client_sees_mpd_framing() {
+        let dir = env::temp_dir().join(format!("musa-mpd-test-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        storage::set_config_dir(dir.join("config"));
+        write_wav(&dir.join("song.wav"));
+        let song = dir.join("song.wav").canonicalize().unwrap();
+
+        let mut app = App::new(LaunchOptions::default());
+        app.load_library(vec![Track::from_path(song.clone())]);
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let mut server = MpdServer::serve(listener).unwrap();
+        server.update(&app);
+
+        let (rescan, rescans) = mpsc::channel();
+        let file = song.to_string_lossy().into_owned();
+        let client = thread::spawn(move || {
+            let mut client = Client::connect(port);
+            assert_eq!(client.send(&["ping"]), ["OK"]);
+
+            let status = client.send(&["status"]);
+            assert!(status.contains(&"playlistlength: 0".to_owned()), "{status:?}");
+            assert!(status.contains(&"state: stop".to_owned()), "{status:?}");
+            assert_eq!(status.last().unwrap(), "OK");
+            assert_eq!(client.send(&["currentsong"]), ["OK"]);
+
+            assert_eq!(client.send(&[&format!("add \"{file}\"")]), ["OK"]);
+            let info = client.send(&["playlistinfo"]);
+            assert_eq!(info.first().unwrap(), &format!("file: {file}"));
+            assert_eq!(info[info.len() - 3..], ["Pos: 0", "Id: 1", "OK"], "{info:?}");
+
+            let list = client.send(&[
+                "command_list_ok_begin",
+                "ping",
+                "getvol",
+                "command_list_end",
+            ]);
+            assert_eq!(list.len(), 4, "{list:?}");
+            assert_eq!(list[0], "list_OK");
+            assert!(list[1].starts_with("volume: "), "{list:?}");
+            assert_eq!(list[2..], ["list_OK", "OK"]);
+            assert_eq!(
+                client.send(&["command_list_begin", "ping", "bogus", "ping", "command_list_end"]),
+                ["ACK [5@1] {bogus} unknown command \"bogus\""]
+            );
+            assert_eq!(
+                client.send(&["setvol x"]),
+                ["ACK [2@0] {setvol} Integer expected: x"]
+            );
+
+            assert_eq!(client.send(&["setvol 55"]), ["OK"]);
+            assert_eq!(client.send(&["idle mixer"]), ["changed: mixer", "OK"]);
+
+            writeln!(client.writer, "idle database").unwrap();
+            rescan.send(()).unwrap();
+            assert_eq!(client.send(&[]), ["changed: database", "OK"]);
+            writeln!(client.writer, "close").unwrap();
+            assert_eq!(client.reader.read_line(&mut String::new()).unwrap(), 0);
+        });
+
+        let deadline = Instant::now() + REPLY_TIMEOUT;
+        while !client.is_finished() && Instant::now() < deadline {
+            for request in server.requests() {
+                server.respond(&mut app, request);
+            }
+            if rescans.try_recv().is_ok() {
+                app.load_library(vec![Track::from_path(song.clone())]);
+            }
+            server.update(&app);
+            thread::sleep(Duration::from_millis(5));
+        }
+        let result = client.join();
+        let _ = fs::remove_dir_all(&dir);
+        result.unwrap();
+    }
+}
@@ -0,0 +1,567 @@
+use std::{
+    collections::HashMap,
+    process,
+    sync::{
+        Arc, Mutex, MutexGuard,
+        mpsc::{self, Receiver, Sender},
+    },
+    time::{Duration, Instant},
+};
+
+use zbus::{
+    blocking::{Connection, connection},
+    fdo, interface,
+    zvariant::{ObjectPath, OwnedObjectPath, OwnedValue, Value},
+};
+
+use crate::app::{PlaybackState, PlayerStatus, RemoteCommand, RepeatMode};
+
+const BUS_NAME: &str = "org.mpris.MediaPlayer2.musa";
+const OBJECT_PATH: &str = "/org/mpris/MediaPlayer2";
+const PLAYER_INTERFACE: &str = "org.mpris.MediaPlayer2.Player";
+const NO_TRACK: &str = "/org/mpris/MediaPlayer2/TrackList/NoTrack";
+const SEEK_TOLERANCE: Duration = Duration::from_millis(1500);
+
+struct Shared {
+    status: PlayerStatus,
+    track_id: u64,
+}
+
+impl Shared {
+    fn track_path(&self) -> OwnedObjectPath {
+        let path = if self.status.track.is_some() {
+            format!("/org/musa/track/{}", self.track_id)
+        } else {
+            NO_TRACK.to_owned()
+        };
+        ObjectPath::try_from(path)
+            .map(OwnedObjectPath::from)
+            .unwrap_or_default()
+    }
+}
+
+pub struct MprisServer {
+    connection: Connection,
+    shared: Arc<Mutex<Shared>>,
+    commands: Receiver<RemoteCommand>,
+    published: Option<Published>,
+}
+
+struct Published {
+    state: PlaybackState,
+    track: Option<std::path::PathBuf>,
+    total: Option<Duration>,
+    volume: u8,
+    shuffle: bool,
+    repeat: RepeatMode,
+    position: Duration,
+    at: Instant,
+}
+
+impl MprisServer {
+    pub fn start() -> zbus::Result<Self> {
+        Self::start_on(connection::Builder::session)
+    }
+
+    fn start_on(
+        bus: impl Fn() -> zbus::Result<connection::Builder<'static>>,
+    ) -> zbus::Result<Self> {
+        let shared = Arc::new(Mutex::new(Shared {
+            status: PlayerStatus {
+                state: PlaybackState::Stopped,
+                track: None,
+                position: Duration::ZERO,
+                total: None,
+                volume: 0,
+                shuffle: false,
+                repeat: RepeatMode::Off,
+            },
+            track_id: 0,
+        }));
+        let (sender, commands) = mpsc::channel();
+
+        let connection = match Self::connect(&bus, BUS_NAME, &shared, &sender) {
+            Err(zbus::Error::NameTaken) => Self::connect(
+                &bus,
+                &format!("{BUS_NAME}.instance{}", process::id()),
+                &shared,
+                &sender,
+            )?,
+            result => result?,
+        };
+
+        Ok(Self {
+            connection,
+            shared,
+            commands,
+            published: None,
+        })
+    }
+
+    fn connect(
+        bus: impl Fn() -> zbus::Result<connection::Builder<'static>>,
+        name: &str,
+        shared: &Arc<Mutex<Shared>>,
+        sender: &Sender<RemoteCommand>,
+    ) -> zbus::Result<Connection> {
+        bus()?
+            .serve_at(OBJECT_PATH, Root)?
+            .serve_at(
+                OBJECT_PATH,
+                Player {
+                    shared: Arc::clone(shared),
+                    commands: sender.clone(),
+                },
+            )?
+            .name(name.to_owned())?
+            .build()
+    }
+
+    pub fn commands(&self) -> Vec<RemoteCommand> {
+        self.commands.try_iter().collect()
+    }
+
+    pub fn publish(&mut self, status: &PlayerStatus) {
+        let track = status.track.as_ref().map(|track| track.path.clone());
+        let mut changed: HashMap<&str, Value> = HashMap::new();
+        let mut seeked = false;
+
+        {
+            let mut shared = lock(&self.shared);
+            let previous = self.published.as_ref();
+            let track_changed = previous.is_none_or(|previous| previous.track != track);
+            if track_changed {
+                shared.track_id += 1;
+            }
+            shared.status = status.clone();
+
+            if track_changed || previous.is_some_and(|previous| previous.total != status.total) {
+                changed.insert("Metadata", Value::from(metadata(&shared)));
+            }
+            if previous.is_none_or(|previous| previous.state != status.state) {
+                changed.insert("PlaybackStatus", Value::from(playback_status(status.state)));
+            }
+            if previous.is_none_or(|previous| previous.volume != status.volume) {
+                changed.insert("Volume", Value::from(volume(status.volume)));
+            }
+            if previous.is_none_or(|previous| previous.shuffle != status.shuffle) {
+                changed.insert("Shuffle", Value::from(status.shuffle));
+            }
+            if previous.is_none_or(|previous| previous.repeat != status.repeat) {
+                changed.insert("LoopStatus", Value::from(loop_status(status.repeat)));
+            }
+
+            if let Some(previous) = previous.filter(|_| !track_changed) {
+                let expected = if previous.state == PlaybackState::Playing {
+                    previous.position + previous.at.elapsed()
+                } else {
+                    previous.position
+                };
+                seeked = status.state != PlaybackState::Stopped
+                    && status.position.abs_diff(expected) > SEEK_TOLERANCE;
+            }
+        }
+
+        self.published = Some(Published {
+            state: status.state,
+            track,
+            total: status.total,
+            volume: status.volume,
+            shuffle: status.shuffle,
+            repeat: status.repeat,
+            position: status.position,
+            at: Instant::now(),
+        });
+
+        if !changed.is_empty() {
+            let _ = self.connection.emit_signal(
+                None::<&str>,
+                OBJECT_PATH,
+                "org.freedesktop.DBus.Properties",
+                "PropertiesChanged",
+                &(PLAYER_INTERFACE, changed, Vec::<&str>::new()),
+            );
+        }
+        if seeked {
+            let _ = self.connection.emit_signal(
+                None::<&str>,
+                OBJECT_PATH,
+                PLAYER_INTERFACE,
+                "Seeked",
+                &(micros(status.position),),
+            );
+        }
+    }
+}
+
+struct Root;
+
+#[interface(name = "org.mpris.MediaPlayer2")]
+impl Root {
+    fn raise(&self) {}
+
+    fn quit(&self) {}
+
+    #[zbus(property)]
+    fn can_quit(&self) -> bool {
+        false
+    }
+
+    #[zbus(property)]
+    fn can_raise(&self) -> bool {
+        false
+    }
+
+    #[zbus(property)]
+    fn has_track_list(&self) -> bool {
+        false
+    }
+
+    #[zbus(property)]
+    fn identity(&self) -> &str {
+        "Musa"
+    }
+
+    #[zbus(property)]
+    fn supported_uri_schemes(&self) -> Vec<&str> {
+        vec!["file"]
+    }
+
+    #[zbus(property)]
+    fn supported_mime_types(&self) -> Vec<&str> {
+        vec![
+            "audio/flac",
+            "audio/mpeg",
+            "audio/ogg",
+            "audio/wav",
+            "audio/mp4",
+            "audio/aac",
+        ]
+    }
+}
+
+struct Player {
+    shared: Arc<Mutex<Shared>>,
+    commands: Sender<RemoteCommand>,
+}
+
+impl Player {
+    fn send(&self, command: RemoteCommand) -> fdo::Result<()> {
+        self.commands
+            .send(command)
+            .map_err(|_| fdo::Error::Failed("player is shutting down".to_owned()))
+    }
+}
+
+#[interface(name = "org.mpris.MediaPlayer2.Player")]
+impl Player {
+    fn next(&self) -> fdo::Result<()> {
+        self.send(RemoteCommand::Next)
+    }
+
+    fn previous(&self) -> fdo::Result<()> {
+        self.send(RemoteCommand::Previous)
+    }
+
+    fn pause(&self) -> fdo::Result<()> {
+        self.send(RemoteCommand::Pause)
+    }
+
+    fn play_pause(&self) -> fdo::Result<()> {
+        self.send(RemoteCommand::PlayPause)
+    }
+
+    fn stop(&self) -> fdo::Result<()> {
+        self.send(RemoteCommand::Stop)
+    }
+
+    fn play(&self) -> fdo::Result<()> {
+        self.send(RemoteCommand::Play)
+    }
+
+    fn seek(&self, offset: i64) -> fdo::Result<()> {
+        self.send(RemoteCommand::Seek(offset as f64 / 1_000_000.0))
+    }
+
+    fn set_position(&self, track_id: ObjectPath<'_>, position: i64) -> fdo::Result<()> {
+        let shared = lock(&self.shared);
+        if shared.status.track.is_none() || *shared.track_path() != track_id || position < 0 {
+            return Ok(());
+        }
+        if shared
+            .status
+            .total
+            .is_some_and(|total| position as u64 > total.as_micros() as u64)
+        {
+            return Ok(());
+        }
+        drop(shared);
+        self.send(RemoteCommand::SetPosition(position as f64 / 1_000_000.0))
+    }
+
+    fn open_uri(&self, _uri: &str) -> fdo::Result<()> {
+        Err(fdo::Error::NotSupported("opening URIs is not supported".to_owned()))
+    }
+
+    #[zbus(signal)]
+    async fn seeked(emitter: &zbus::object_server::SignalEmitter<'_>, position: i64)
+    -> zbus::Result<()>;
+
+    #[zbus(property)]
+    fn playback_status(&self) -> &'static str {
+        playback_status(lock(&self.shared).status.state)
+    }
+
+    #[zbus(property)]
+    fn loop_status(&self) -> &'static str {
+        loop_status(lock(&self.shared).status.repeat)
+    }
+
+    #[zbus(property)]
+    fn set_loop_status(&self, value: &str) -> fdo::Result<()> {
+        let repeat = match value {
+            "None" => RepeatMode::Off,
+            "Track" => RepeatMode::One,
+            "Playlist" => RepeatMode::All,
+            _ => return Err(fdo::Error::InvalidArgs(format!("unknown loop status {value}"))),
+        };
+        self.send(RemoteCommand::SetRepeat(repeat))
+    }
+
+    #[zbus(property)]
+    fn rate(&self) -> f64 {
+        1.0
+    }
+
+    #[zbus(property)]
+    fn set_rate(&self, _value: f64) {}
+
+    #[zbus(property)]
+    fn shuffle(&self) -> bool {
+        lock(&self.shared).status.shuffle
+    }
+
+    #[zbus(property)]
+    fn set_shuffle(&self, value: bool) -> fdo::Result<()> {
+        self.send(RemoteCommand::SetShuffle(value))
+    }
+
+    #[zbus(property)]
+    fn metadata(&self) -> HashMap<String, OwnedValue> {
+        metadata(&lock(&self.shared))
+    }
+
+    #[zbus(property)]
+    fn volume(&self) -> f64 {
+        volume(lock(&self.shared).status.volume)
+    }
+
+    #[zbus(property)]
+    fn set_volume(&self, value: f64) -> fdo::Result<()> {
+        let volume = (value.clamp(0.0, 1.0) * 100.0).round() as u8;
+        self.send(RemoteCommand::SetVolume(volume))
+    }
+
+    #[zbus(property(emits_changed_signal = "false"))]
+    fn position(&self) -> i64 {
+        micros(lock(&self.shared).status.position)
+    }
+
+    #[zbus(property)]
+    fn minimum_rate(&self) -> f64 {
+        1.0
+    }
+
+    #[zbus(property)]
+    fn maximum_rate(&self) -> f64 {
+        1.0
+    }
+
+    #[zbus(property)]
+    fn can_go_next(&self) -> bool {
+        true
+    }
+
+    #[zbus(property)]
+    fn can_go_previous(&self) -> bool {
+        true
+    }
+
+    #[zbus(property)]
+    fn can_play(&self) -> bool {
+        true
+    }
+
+    #[zbus(property)]
+    fn can_pause(&self) -> bool {
+        true
+    }
+
+    #[zbus(property)]
+    fn can_seek(&self) -> bool {
+        lock(&self.shared).status.track.is_some()
+    }
+
+    #[zbus(property)]
+    fn can_control(&self) -> bool {
+        true
+    }
+}
+
+fn lock(shared: &Mutex<Shared>) -> MutexGuard<'_, Shared> {
+    shared.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn metadata(shared: &Shared) -> HashMap<String, OwnedValue> {
+    let mut metadata = HashMap::new();
+    let mut insert = |key: &str, value: Value<'_>| {
+        if let Ok(value) = value.try_into_owned() {
+            metadata.insert(key.to_owned(), value);
+        }
+    };
+    fill_metadata(shared, &mut insert);
+    metadata
+}
+
+fn fill_metadata(shared: &Shared, insert: &mut impl FnMut(&str, Value<'_>)) {
+    insert("mpris:trackid", Value::from(shared.track_path()));
+    let Some(track) = &shared.status.track else {
+        return;
+    };
+
+    if let Some(total) = shared.status.total.or(track.duration) {
+        insert("mpris:length", Value::from(micros(total)));
+    }
+    insert("xesam:title", Value::from(track.title.to_string()));
+    insert("xesam:artist", Value::from(vec![track.artist.to_string()]));
+    insert("xesam:album", Value::from(track.album.to_string()));
+    insert(
+        "xesam:albumArtist",
+        Value::from(vec![track.album_artist.to_string()]),
+    );
+    if let Some(track_no) = track.track_no {
+        insert("xesam:trackNumber", Value::from(track_no as i32));
+    }
+    if let Some(disc_no) = track.disc_no {
+        insert("xesam:discNumber", Value::from(disc_no as i32));
+    }
+    insert(
+        "xesam:url",
+        Value::from(format!("file://{}", track.path.display())),
+    );
+}
+
+fn playback_status(state: PlaybackState) -> &'static str {
+    match state {
+        PlaybackState::Playing => "Playing",
+        PlaybackState::Paused => "Paused",
+        PlaybackState::Stopped => "Stopped",
+    }
+}
+
+fn loop_status(repeat: RepeatMode) -> &'static str {
+    match repeat {
+        RepeatMode::Off => "None",
+        RepeatMode::One => "Track",
+        RepeatMode::All => "Playlist",
+    }
+}
+
+fn volume(volume: u8) -> f64 {
+    volume as f64 / 100.0
+}
+
+fn micros(duration: Duration) -> i64 {
+    duration.as_micros().min(i64::MAX as u128) as i64
+}
+
+#[cfg(all(test, feature = "mpris"))]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader},
+        path::PathBuf,
+        process::{Child, Command, Stdio},
+    };
+
+    use zbus::{blocking::Proxy, blocking::fdo::PropertiesProxy, names::InterfaceName};
+
+    use super::*;
+    use crate::library::Track;
+
+    struct Bus(Child);
+
+    impl Drop for Bus {
+        fn drop(&mut self) {
+            let _ = self.0.kill();
+            let _ = self.0.wait();
+        }
+    }
+
+    #[test]
+    fn player_interface_on_a_private_bus() {
+        let spawned = Command::new("dbus-daemon")
+            .args(["--session", "--nofork", "--print-address=1"])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn();
+        let Ok(child) = spawned else {
+            eprintln!("skipping: dbus-daemon is not available");
+            return;
+        };
+        let mut bus = Bus(child);
+        let mut address = String::new();
+        BufReader::new(bus.0.stdout.take().unwrap())
+            .read_line(&mut address)
+            .unwrap();
+        let address = address.trim();
+
+        let mut server = MprisServer::start_on(|| connection::Builder::address(address)).unwrap();
+        let mut track = Track::from_path(PathBuf::from("/music/song.flac"));
+        track.title = "Song".into();
+        track.artist = "Artist".into();
+        server.publish(&PlayerStatus {
+            state: PlaybackState::Paused,
+            track: Some(track),
+            position: Duration::from_secs(5),
+            total: Some(Duration::from_secs(200)),
+            volume: 40,
+            shuffle: false,
+            repeat: RepeatMode::Off,
+        });
+
+        let client = connection::Builder::address(address)
+            .unwrap()
+            .build()
+            .unwrap();
+        let properties = PropertiesProxy::builder(&client)
+            .destination(BUS_NAME)
+            .unwrap()
+            .path(OBJECT_PATH)
+            .unwrap()
+            .build()
+            .unwrap();
+        let interface = InterfaceName::try_from(PLAYER_INTERFACE).unwrap();
+
+        let status = properties.get(interface.clone(), "PlaybackStatus").unwrap();
+        assert_eq!(String::try_from(status).unwrap(), "Paused");
+        let metadata = properties.get(interface, "Metadata").unwrap();
+        let mut metadata = HashMap::<String, OwnedValue>::try_from(metadata).unwrap();
+        let mut field = |key: &str| metadata.remove(key).unwrap();
+        assert_eq!(String::try_from(field("xesam:title")).unwrap(), "Song");
+        assert_eq!(
+            Vec::<String>::try_from(field("xesam:artist")).unwrap(),
+            ["Artist"]
+        );
+        assert_eq!(i64::try_from(field("mpris:length")).unwrap(), 200_000_000);
+        assert_eq!(
+            OwnedObjectPath::try_from(field("mpris:trackid"))
+                .unwrap()
+                .as_str(),
+            "/org/musa/track/1"
+        );
+
+        let player = Proxy::new(&client, BUS_NAME, OBJECT_PATH, PLAYER_INTERFACE).unwrap();
+        player.call_method("PlayPause", &()).unwrap();
+        assert_eq!(server.commands(), [RemoteCommand::PlayPause]);
+    }
+}
@@ -0,0 +1,452 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use crate::library::Track;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PlaylistFormat {
+    M3u,
+    Pls,
+    Xspf,
+}
+
+impl PlaylistFormat {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "m3u" | "m3u8" => Some(Self::M3u),
+            "pls" => Some(Self::Pls),
+            "xspf" => Some(Self::Xspf),
+            _ => None,
+        }
+    }
+
+    pub fn from_path(path: &Path) -> Option<Self> {
+        path.extension()
+            .and_then(|extension| extension.to_str())
+            .and_then(Self::from_name)
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::M3u => "m3u8",
+            Self::Pls => "pls",
+            Self::Xspf => "xspf",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Entry {
+    pub path: PathBuf,
+    pub title: Option<String>,
+    pub duration: Option<Duration>,
+    pub canonical: Option<PathBuf>,
+}
+
+#[derive(Clone, Debug)]
+pub struct PlaylistFile {
+    pub path: PathBuf,
+    pub entries: Vec<Entry>,
+}
+
+pub fn is_playlist(path: &Path) -> bool {
+    PlaylistFormat::from_path(path).is_some()
+}
+
+pub fn read(path: &Path) -> io::Result<Vec<PathBuf>> {
+    Ok(read_entries(path)?
+        .into_iter()
+        .map(|entry| entry.path)
+        .collect())
+}
+
+pub fn read_entries(path: &Path) -> io::Result<Vec<Entry>> {
+    let Some(format) = PlaylistFormat::from_path(path) else {
+        return Err(io::Error::other(format!(
+            "unsupported playlist format: {}",
+            path.display()
+        )));
+    };
+
+    let bytes = fs::read(path)?;
+    let text = String::from_utf8_lossy(&bytes);
+    let text = text.trim_start_matches('\u{feff}');
+    let base = path.parent().unwrap_or(Path::new(""));
+    let mut entries = match format {
+        PlaylistFormat::M3u => parse_m3u(base, text),
+        PlaylistFormat::Pls => parse_pls(base, text),
+        PlaylistFormat::Xspf => parse_xspf(base, text),
+    };
+    for entry in &mut entries {
+        entry.canonical = fs::canonicalize(&entry.path)
+            .ok()
+            .filter(|canonical| *canonical != entry.path);
+    }
+    Ok(entries)
+}
+
+pub fn write<'a>(
+    output: &mut impl Write,
+    format: PlaylistFormat,
+    name: &str,
+    entries: impl IntoIterator<Item = (&'a Path, Option<&'a Track>)>,
+) -> io::Result<usize> {
+    let mut skipped = 0;
+    let entries = entries.into_iter().filter(|(_, track)| {
+        let virtual_track = track.is_some_and(|track| track.range.is_some());
+        skipped += usize::from(virtual_track);
+        !virtual_track
+    });
+    match format {
+        PlaylistFormat::M3u => write_m3u(output, entries),
+        PlaylistFormat::Pls => write_pls(output, entries),
+        PlaylistFormat::Xspf => write_xspf(output, name, entries),
+    }?;
+    Ok(skipped)
+}
+
+fn parse_m3u(base: &Path, text: &str) -> Vec<Entry> {
+    let mut entries = Vec::new();
+    let mut info: Option<(Option<Duration>, Option<String>)> = None;
+    for line in text.lines().map(str::trim) {
+        if let Some(extinf) = line.strip_prefix("#EXTINF:") {
+            let (seconds, title) = extinf.split_once(',').unwrap_or((extinf, ""));
+            let seconds = seconds.split_whitespace().next().unwrap_or_default();
+            info = Some((parse_seconds(seconds), non_empty(title)));
+            continue;
+        }
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let (duration, title) = info.take().unwrap_or_default();
+        if let Some(path) = resolve_location(base, line) {
+            entries.push(Entry {
+                path,
+                title,
+                duration,
+                canonical: None,
+            });
+        }
+    }
+    entries
+}
+
+fn parse_pls(base: &Path, text: &str) -> Vec<Entry> {
+    let mut entries: BTreeMap<u32, Entry> = BTreeMap::new();
+    for line in text.lines().map(str::trim) {
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        let key = key.trim().to_ascii_lowercase();
+        let value = value.trim();
+        let (field, number) =
+            key.split_at(key.find(|c: char| c.is_ascii_digit()).unwrap_or(key.len()));
+        let Ok(number) = number.parse::<u32>() else {
+            continue;
+        };
+        let entry = entries.entry(number).or_default();
+        match field {
+            "file" => entry.path = resolve_location(base, value).unwrap_or_default(),
+            "title" => entry.title = non_empty(value),
+            "length" => entry.duration = parse_seconds(value),
+            _ => {}
+        }
+    }
+
+    entries
+        .into_values()
+        .filter(|entry| !entry.path.as_os_str().is_empty())
+        .collect()
+}
+
+fn parse_xspf(base: &Path, text: &str) -> Vec<Entry> {
+    let mut entries = Vec::new();
+    let mut rest = text;
+    while let Some(start) = find_tag(rest, "track") {
+        rest = &rest[start..];
+        let end = rest.find("</track>").unwrap_or(rest.len());
+        let track = &rest[..end];
+        rest = &rest[end..];
+
+        let Some(path) =
+            element_text(track, "location").and_then(|location| resolve_location(base, &location))
+        else {
+            continue;
+        };
+        let title = element_text(track, "title").and_then(|title| non_empty(&title));
+        let title = match (element_text(track, "creator"), title) {
+            (Some(creator), Some(title)) if !creator.is_empty() => {
+                Some(format!("{creator} - {title}"))
+            }
+            (_, title) => title,
+        };
+        let duration = element_text(track, "duration")
+            .and_then(|duration| duration.parse::<u64>().ok())
+            .map(Duration::from_millis);
+        entries.push(Entry {
+            path,
+            title,
+            duration,
+            canonical: None,
+        });
+    }
+    entries
+}
+
+fn write_m3u<'a>(
+    output: &mut impl Write,
+    entries: impl IntoIterator<Item = (&'a Path, Option<&'a Track>)>,
+) -> io::Result<()> {
+    writeln!(output, "#EXTM3U")?;
+    for (path, track) in entries {
+        if let Some(track) = track {
+            let seconds = track
+                .duration
+                .map_or(-1, |duration| duration.as_secs() as i64);
+            writeln!(
+                output,
+                "#EXTINF:{seconds},{} - {}",
+                track.artist, track.title
+            )?;
+        }
+        writeln!(output, "{}", path.display())?;
+    }
+    Ok(())
+}
+
+fn write_pls<'a>(
+    output: &mut impl Write,
+    entries: impl IntoIterator<Item = (&'a Path, Option<&'a Track>)>,
+) -> io::Result<()> {
+    writeln!(output, "[playlist]")?;
+    let mut count = 0;
+    for (path, track) in entries {
+        count += 1;
+        writeln!(output, "File{count}={}", path.display())?;
+        if let Some(track) = track {
+            writeln!(output, "Title{count}={} - {}", track.artist, track.title)?;
+            let seconds = track
+                .duration
+                .map_or(-1, |duration| duration.as_secs() as i64);
+            writeln!(output, "Length{count}={seconds}")?;
+        }
+    }
+    writeln!(output, "NumberOfEntries={count}")?;
+    writeln!(output, "Version=2")
+}
+
+fn write_xspf<'a>(
+    output: &mut impl Write,
+    name: &str,
+    entries: impl IntoIterator<Item = (&'a Path, Option<&'a Track>)>,
+) -> io::Result<()> {
+    writeln!(output, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(
+        output,
+        r#"<playlist version="1" xmlns="http://xspf.org/ns/0/">"#
+    )?;
+    writeln!(output, "  <title>{}</title>", escape_xml(name))?;
+    writeln!(output, "  <trackList>")?;
+    for (path, track) in entries {
+        writeln!(output, "    <track>")?;
+        writeln!(
+            output,
+            "      <location>{}</location>",
+            escape_xml(&file_uri(path))
+        )?;
+        if let Some(track) = track {
+            writeln!(output, "      <title>{}</title>", escape_xml(&track.title))?;
+            writeln!(
+                output,
+                "      <creator>{}</creator>",
+                escape_xml(&track.artist)
+            )?;
+            writeln!(output, "      <album>{}</album>", escape_xml(&track.album))?;
+            if let Some(number) = track.track_no {
+                writeln!(output, "      <trackNum>{number}</trackNum>")?;
+            }
+            if let Some(duration) = track.duration {
+                writeln!(
+                    output,
+                    "      <duration>{}</duration>",
+                    duration.as_millis()
+                )?;
+            }
+        }
+        writeln!(output, "    </track>")?;
+    }
+    writeln!(output, "  </trackList>")?;
+    writeln!(output, "</playlist>")
+}
+
+fn resolve_location(base: &Path, location: &str) -> Option<PathBuf> {
+    let location = location.trim();
+    if location.is_empty() {
+        return None;
+    }
+    if let Some(rest) = location.strip_prefix("file://") {
+        let rest = rest.strip_prefix("localhost").unwrap_or(rest);
+        return Some(resolve_entry(base, &percent_decode(rest)));
+    }
+    if has_scheme(location) {
+        return None;
+    }
+    Some(resolve_entry(base, location))
+}
+
+fn resolve_entry(base: &Path, entry: &str) -> PathBuf {
+    let path = Path::new(entry);
+    if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        base.join(path)
+    }
+}
+
+fn has_scheme(location: &str) -> bool {
+    location.split_once("://").is_some_and(|(scheme, _)| {
+        scheme.len() > 1
+            && scheme
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+    })
+}
+
+fn parse_seconds(text: &str) -> Option<Duration> {
+    text.trim()
+        .parse::<f64>()
+        .ok()
+        .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+}
+
+fn non_empty(text: &str) -> Option<String> {
+    let text = text.trim();
+    (!text.is_empty()).then(|| text.to_owned())
+}
+
+fn find_tag(text: &str, name: &str) -> Option<usize> {
+    let open = format!("<{name}");
+    let mut offset = 0;
+    while let Some(position) = text[offset..].find(&open) {
+        let start = offset + position;
+        let next = text[start + open.len()..].chars().next();
+        if matches!(next, Some('>' | '/' | ' ' | '\t' | '\r' | '\n')) {
+            return Some(start);
+        }
+        offset = start + open.len();
+    }
+    None
+}
+
+fn element_text(text: &str, name: &str) -> Option<String> {
+    let start = find_tag(text, name)?;
+    let content = &text[start..];
+    let open_end = content.find('>')?;
+    if content[..open_end].ends_with('/') {
+        return Some(String::new());
+    }
+    let content = &content[open_end + 1..];
+    let end = content.find(&format!("</{name}>"))?;
+    let content = content[..end].trim();
+    let content = content
+        .strip_prefix("<![CDATA[")
+        .and_then(|inner| inner.strip_suffix("]]>"))
+        .map_or_else(|| unescape_xml(content), str::to_owned);
+    Some(content)
+}
+
+fn escape_xml(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for character in text.chars() {
+        match character {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(character),
+        }
+    }
+    escaped
+}
+
+fn unescape_xml(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        unescaped.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let Some(end) = rest.find(';') else {
+            break;
+        };
+        let entity = &rest[1..end];
+        let character = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => entity
+                .strip_prefix("#x")
+                .or_else(|| entity.strip_prefix("#X"))
+                .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                .or_else(|| {
+                    entity
+                        .strip_prefix('#')
+                        .and_then(|decimal| decimal.parse().ok())
+                })
+                .and_then(char::from_u32),
+        };
+        match character {
+            Some(character) => {
+                unescaped.push(character);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                unescaped.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    unescaped.push_str(rest);
+    unescaped
+}
+
+fn file_uri(path: &Path) -> String {
+    let mut uri = "file://".to_owned();
+    for byte in path.to_string_lossy().bytes() {
+        if byte.is_ascii_alphanumeric() || matches!(byte, b'/' | b'-' | b'_' | b'.' | b'~') {
+            uri.push(byte as char);
+        } else {
+            uri.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    uri
+}
+
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        if bytes[index] == b'%' {
+            if let Some(byte) = text
+                .get(index + 1..index + 3)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            {
+                decoded.push(byte);
+                index += 3;
+                continue;
+            }
+        }
+        decoded.push(bytes[index]);
+        index += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
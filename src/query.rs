@@ -0,0 +1,512 @@
+use std::fmt;
+
+use crate::{
+    library::Track,
+    smart::{self, Range},
+};
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Query {
+    text: String,
+    any: Vec<Vec<Term>>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Term {
+    negated: bool,
+    filter: Filter,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Filter {
+    Text(String),
+    Artist(String),
+    Album(String),
+    Title(String),
+    Path(String),
+    Genre(String),
+    Year(Range),
+    Duration(Range),
+}
+
+enum Token {
+    Or,
+    Term(Term),
+}
+
+const FIELDS: [&str; 7] = [
+    "artist", "album", "title", "path", "genre", "year", "duration",
+];
+
+const FIELD_COUNT: usize = 7;
+const WEIGHTS: [u32; FIELD_COUNT] = [5, 5, 5, 3, 1, 1, 1];
+const FUZZY_FIELDS: usize = 4;
+const TITLE: usize = 0;
+const ARTIST: usize = 1;
+const ALBUM_ARTIST: usize = 2;
+const ALBUM: usize = 3;
+const GENRE: usize = 4;
+const PATH: usize = 5;
+
+impl Query {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut any = vec![Vec::new()];
+        let mut after_or = false;
+        for token in tokenize(text)? {
+            match token {
+                Token::Or => {
+                    if any.last().is_some_and(Vec::is_empty) {
+                        return Err("OR needs a search term on both sides".to_owned());
+                    }
+                    any.push(Vec::new());
+                    after_or = true;
+                }
+                Token::Term(term) => {
+                    if let Some(all) = any.last_mut() {
+                        all.push(term);
+                    }
+                    after_or = false;
+                }
+            }
+        }
+        if after_or {
+            return Err("OR needs a search term on both sides".to_owned());
+        }
+        any.retain(|all| !all.is_empty());
+        Ok(Self {
+            text: text.trim().to_owned(),
+            any,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.any.is_empty()
+    }
+
+    fn is_plain(&self) -> bool {
+        self.any
+            .iter()
+            .flatten()
+            .all(|term| matches!(term.filter, Filter::Text(_)))
+    }
+
+    /// Whether every match of this query is also a match of `previous`, so
+    /// its exact results can be found among the earlier ones.
+    pub fn narrows(&self, previous: &Self) -> bool {
+        let ([all], [before]) = (self.any.as_slice(), previous.any.as_slice()) else {
+            return false;
+        };
+        all.len() >= before.len()
+            && all
+                .iter()
+                .zip(before)
+                .all(|(term, before)| term.narrows(before))
+    }
+
+    pub fn matches(&self, track: &Track) -> bool {
+        self.score(&SearchText::new(track), track, true).is_some()
+    }
+
+    pub fn score(&self, text: &SearchText, track: &Track, fuzzy: bool) -> Option<u32> {
+        self.evaluate(fuzzy, |filter, fuzzy| filter.score(text, track, fuzzy))
+    }
+
+    pub fn score_text(&self, fields: &[&str], fuzzy: bool) -> Option<u32> {
+        if !self.is_plain() {
+            return None;
+        }
+        let mut slots = [""; FIELD_COUNT];
+        for (slot, field) in slots.iter_mut().zip(fields) {
+            *slot = field;
+        }
+        let text = SearchText::from_fields(slots);
+        self.evaluate(fuzzy, |filter, fuzzy| match filter {
+            Filter::Text(needle) => text.text_score(needle, fuzzy),
+            _ => 0,
+        })
+    }
+
+    fn evaluate(&self, fuzzy: bool, score: impl Fn(&Filter, bool) -> u32) -> Option<u32> {
+        self.any
+            .iter()
+            .filter_map(|all| {
+                all.iter().try_fold(0, |total, term| {
+                    if term.negated {
+                        (score(&term.filter, false) == 0).then_some(total)
+                    } else {
+                        match score(&term.filter, fuzzy) {
+                            0 => None,
+                            points => Some(total + points),
+                        }
+                    }
+                })
+            })
+            .max()
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct SearchText {
+    text: String,
+    ends: [usize; FIELD_COUNT],
+    letters: u64,
+}
+
+impl SearchText {
+    pub fn new(track: &Track) -> Self {
+        let year = track.year.map(|year| year.to_string());
+        let other = [
+            track.tags.composer.as_deref(),
+            track.tags.conductor.as_deref(),
+            track.tags.comment.as_deref(),
+            track.tags.label.as_deref(),
+            track.format.codec.as_deref(),
+            year.as_deref(),
+        ]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>()
+        .join("\n");
+        Self::from_fields([
+            &track.title,
+            &track.artist,
+            &track.album_artist,
+            &track.album,
+            track.genre.as_deref().unwrap_or_default(),
+            &track.path.to_string_lossy(),
+            &other,
+        ])
+    }
+
+    fn from_fields(fields: [&str; FIELD_COUNT]) -> Self {
+        let mut text = String::new();
+        let mut ends = [0; FIELD_COUNT];
+        for (index, field) in fields.into_iter().enumerate() {
+            if index > 0 {
+                text.push('\n');
+            }
+            fold_into(&mut text, field);
+            ends[index] = text.len();
+        }
+        let letters = letters(&text[..ends[FUZZY_FIELDS - 1]]);
+        Self {
+            text,
+            ends,
+            letters,
+        }
+    }
+
+    fn field(&self, index: usize) -> &str {
+        let start = if index == 0 {
+            0
+        } else {
+            self.ends[index - 1] + 1
+        };
+        &self.text[start..self.ends[index]]
+    }
+
+    fn text_score(&self, needle: &str, fuzzy: bool) -> u32 {
+        let mut best = 0;
+        for (index, _) in self.text.match_indices(needle) {
+            let end = index + needle.len();
+            let field = self.ends.partition_point(|field_end| *field_end < end);
+            let start = if field == 0 {
+                0
+            } else {
+                self.ends[field - 1] + 1
+            };
+            let points = if index == start && end == self.ends[field] {
+                20
+            } else if starts_word(&self.text, index) {
+                12
+            } else {
+                8
+            };
+            best = best.max(WEIGHTS[field] * points);
+        }
+        if best > 0 || !fuzzy {
+            return best;
+        }
+
+        let needle_letters = letters(needle);
+        let missing = (needle_letters & !self.letters).count_ones();
+        let typos = match needle.chars().count() {
+            0..4 => 0,
+            4..8 => 1,
+            _ => 2,
+        };
+        if typos > 0
+            && missing as usize <= typos
+            && self.text[..self.ends[FUZZY_FIELDS - 1]]
+                .split(|character: char| !character.is_alphanumeric())
+                .any(|word| {
+                    (needle_letters & !letters(word)).count_ones() as usize <= typos
+                        && within_distance(word.as_bytes(), needle.as_bytes(), typos)
+                })
+        {
+            return 3;
+        }
+        if missing == 0
+            && needle.len() >= 3
+            && (0..FUZZY_FIELDS).any(|field| is_subsequence(self.field(field), needle))
+        {
+            return 2;
+        }
+        0
+    }
+}
+
+impl fmt::Display for Query {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.write_str(&self.text)
+    }
+}
+
+impl Term {
+    fn narrows(&self, previous: &Self) -> bool {
+        if self == previous {
+            return true;
+        }
+        if self.negated || previous.negated {
+            return false;
+        }
+        match (&self.filter, &previous.filter) {
+            (Filter::Text(needle), Filter::Text(before))
+            | (Filter::Artist(needle), Filter::Artist(before))
+            | (Filter::Album(needle), Filter::Album(before))
+            | (Filter::Title(needle), Filter::Title(before))
+            | (Filter::Path(needle), Filter::Path(before))
+            | (Filter::Genre(needle), Filter::Genre(before)) => needle.contains(before.as_str()),
+            _ => false,
+        }
+    }
+}
+
+impl Filter {
+    fn parse(field: Option<&str>, value: String) -> Result<Self, String> {
+        let folded = fold(&value);
+        let filter = match field {
+            None => Self::Text(folded),
+            Some("artist") => Self::Artist(folded),
+            Some("album") => Self::Album(folded),
+            Some("title") => Self::Title(folded),
+            Some("path") => Self::Path(folded),
+            Some("genre") => Self::Genre(folded),
+            Some("year") => {
+                Self::Year(Range::parse(&value, smart::parse_number).ok_or_else(|| {
+                    format!("invalid year '{value}'; try 1997, 1990..1999, or >2000")
+                })?)
+            }
+            Some(_) => {
+                Self::Duration(Range::parse(&value, smart::parse_seconds).ok_or_else(|| {
+                    format!("invalid duration '{value}'; try 3:30, >10m, or 2m..5m")
+                })?)
+            }
+        };
+        Ok(filter)
+    }
+
+    fn score(&self, text: &SearchText, track: &Track, fuzzy: bool) -> u32 {
+        match self {
+            Self::Text(needle) => text.text_score(needle, fuzzy),
+            Self::Artist(needle) => field_score(text.field(ARTIST), needle)
+                .max(field_score(text.field(ALBUM_ARTIST), needle)),
+            Self::Album(needle) => field_score(text.field(ALBUM), needle),
+            Self::Title(needle) => field_score(text.field(TITLE), needle),
+            Self::Path(needle) => field_score(text.field(PATH), needle),
+            Self::Genre(needle) => field_score(text.field(GENRE), needle),
+            Self::Year(range) => u32::from(
+                track
+                    .year
+                    .is_some_and(|year| range.contains(u64::from(year))),
+            ),
+            Self::Duration(range) => u32::from(
+                track
+                    .duration
+                    .is_some_and(|duration| range.contains(duration.as_secs())),
+            ),
+        }
+    }
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = text.trim_start();
+    while !rest.is_empty() {
+        let negated = rest.starts_with('-');
+        if negated {
+            rest = &rest[1..];
+            if rest.is_empty() || rest.starts_with(char::is_whitespace) {
+                return Err("'-' must be followed by a search term".to_owned());
+            }
+        }
+
+        let mut field = None;
+        if let Some((name, value)) = rest.split_once(':') {
+            let name = name.to_ascii_lowercase();
+            if let Some(known) = FIELDS.iter().find(|known| **known == name) {
+                field = Some(*known);
+                rest = value;
+            }
+        }
+
+        let (value, quoted) = if let Some(quoted) = rest.strip_prefix('"') {
+            let Some(end) = quoted.find('"') else {
+                return Err("unclosed quote".to_owned());
+            };
+            rest = &quoted[end + 1..];
+            (&quoted[..end], true)
+        } else {
+            let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            let value = &rest[..end];
+            rest = &rest[end..];
+            (value, false)
+        };
+        rest = rest.trim_start();
+
+        if !negated && field.is_none() && !quoted && value == "OR" {
+            tokens.push(Token::Or);
+            continue;
+        }
+        if value.trim().is_empty() {
+            return Err(match field {
+                Some(field) => format!("'{field}:' needs a value"),
+                None => "empty quotes".to_owned(),
+            });
+        }
+        tokens.push(Token::Term(Term {
+            negated,
+            filter: Filter::parse(field, value.to_owned())?,
+        }));
+    }
+    Ok(tokens)
+}
+
+fn field_score(field: &str, needle: &str) -> u32 {
+    if field == needle {
+        return 20;
+    }
+    let mut score = 0;
+    for (index, _) in field.match_indices(needle) {
+        if starts_word(field, index) {
+            return 12;
+        }
+        score = 8;
+    }
+    score
+}
+
+fn starts_word(text: &str, index: usize) -> bool {
+    text[..index]
+        .chars()
+        .next_back()
+        .is_none_or(|character| !character.is_alphanumeric())
+}
+
+fn letters(text: &str) -> u64 {
+    text.chars().fold(0, |letters, character| {
+        letters
+            | match character {
+                'a'..='z' => 1 << (character as u32 - 'a' as u32),
+                '0'..='9' => 1 << (26 + character as u32 - '0' as u32),
+                _ => 1 << 36,
+            }
+    })
+}
+
+fn is_subsequence(field: &str, needle: &str) -> bool {
+    let mut characters = field.chars();
+    needle
+        .chars()
+        .filter(|character| !character.is_whitespace())
+        .all(|wanted| characters.any(|character| character == wanted))
+}
+
+fn within_distance(word: &[u8], needle: &[u8], limit: usize) -> bool {
+    const MAX: usize = 32;
+    if word.is_empty()
+        || word.len() > MAX
+        || needle.len() > MAX
+        || word.len().abs_diff(needle.len()) > limit
+    {
+        return false;
+    }
+    let mut before = [0; MAX + 1];
+    let mut previous: [usize; MAX + 1] = std::array::from_fn(|index| index);
+    let mut current = [0; MAX + 1];
+    for row in 1..=word.len() {
+        current[0] = row;
+        let mut smallest = row;
+        for column in 1..=needle.len() {
+            let cost = usize::from(word[row - 1] != needle[column - 1]);
+            let mut distance = (previous[column] + 1)
+                .min(current[column - 1] + 1)
+                .min(previous[column - 1] + cost);
+            if row > 1
+                && column > 1
+                && word[row - 1] == needle[column - 2]
+                && word[row - 2] == needle[column - 1]
+            {
+                distance = distance.min(before[column - 2] + 1);
+            }
+            current[column] = distance;
+            smallest = smallest.min(distance);
+        }
+        if smallest > limit {
+            return false;
+        }
+        before = previous;
+        previous = current;
+    }
+    previous[needle.len()] <= limit
+}
+
+fn fold(text: &str) -> String {
+    let mut folded = String::with_capacity(text.len());
+    fold_into(&mut folded, text);
+    folded
+}
+
+fn fold_into(folded: &mut String, text: &str) {
+    const LATIN: &str = "aaaaaa.ceeeeiiiidnooooo.ouuuuy..aaaaaa.ceeeeiiiidnooooo.ouuuuy.yaaaaaaccccccccddddeeeeeeeeeegggggggghhhhiiiiiiiiii..jjkkkllllllllllnnnnnnnnnoooooo..rrrrrrssssssssttttttuuuuuuuuuuuuwwyyyzzzzzzs";
+    for character in text.chars().flat_map(char::to_lowercase) {
+        match character {
+            'æ' => folded.push_str("ae"),
+            'œ' => folded.push_str("oe"),
+            'ß' => folded.push_str("ss"),
+            'þ' => folded.push_str("th"),
+            'ĳ' => folded.push_str("ij"),
+            '\u{300}'..='\u{36f}' => {}
+            '\u{c0}'..='\u{17f}' => match LATIN.as_bytes()[character as usize - 0xc0] {
+                b'.' => folded.push(character),
+                base => folded.push(char::from(base)),
+            },
+            character => folded.push(character),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Query;
+
+    fn narrows(text: &str, previous: &str) -> bool {
+        Query::parse(text)
+            .unwrap()
+            .narrows(&Query::parse(previous).unwrap())
+    }
+
+    #[test]
+    fn only_stricter_queries_narrow_the_previous_one() {
+        assert!(narrows("beat", "bea"));
+        assert!(narrows("beatles help", "beatles"));
+        assert!(narrows("artist:beatles", "artist:beat"));
+        assert!(narrows("-live beatles", "-live beat"));
+
+        assert!(!narrows("bea", "beat"));
+        assert!(!narrows("beatles OR help", "beatles"));
+        assert!(!narrows("-live", "-liv"));
+        assert!(!narrows("title:beat", "artist:beat"));
+        assert!(!narrows("year:1990..1999", "year:1990"));
+    }
+}
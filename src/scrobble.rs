@@ -0,0 +1,905 @@
+use std::{
+    io::{self, Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    path::PathBuf,
+    process::{Command, Stdio},
+    sync::mpsc::{self, Receiver, TryRecvError},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{cli::json_string, storage};
+
+const BATCH_SIZE: usize = 50;
+const TIMEOUT: Duration = Duration::from_secs(20);
+const FIRST_RETRY: Duration = Duration::from_secs(60);
+const LAST_RETRY: Duration = Duration::from_secs(30 * 60);
+pub const MIN_DURATION: Duration = Duration::from_secs(30);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Service {
+    ListenBrainz,
+    LastFm,
+}
+
+impl Service {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "listenbrainz" => Some(Self::ListenBrainz),
+            "lastfm" => Some(Self::LastFm),
+            _ => None,
+        }
+    }
+
+    pub fn key(self) -> &'static str {
+        match self {
+            Self::ListenBrainz => "listenbrainz",
+            Self::LastFm => "lastfm",
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::ListenBrainz => "ListenBrainz",
+            Self::LastFm => "Last.fm",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ScrobbleSettings {
+    pub listenbrainz_token: Option<String>,
+    pub listenbrainz_url: String,
+    pub lastfm_api_key: Option<String>,
+    pub lastfm_api_secret: Option<String>,
+    pub lastfm_session_key: Option<String>,
+    pub lastfm_url: String,
+}
+
+impl Default for ScrobbleSettings {
+    fn default() -> Self {
+        Self {
+            listenbrainz_token: None,
+            listenbrainz_url: "https://api.listenbrainz.org".to_owned(),
+            lastfm_api_key: None,
+            lastfm_api_secret: None,
+            lastfm_session_key: None,
+            lastfm_url: "https://ws.audioscrobbler.com/2.0/".to_owned(),
+        }
+    }
+}
+
+impl ScrobbleSettings {
+    pub fn services(&self) -> Vec<Service> {
+        let mut services = Vec::new();
+        if self.listenbrainz_token.is_some() {
+            services.push(Service::ListenBrainz);
+        }
+        if self.lastfm_api_key.is_some()
+            && self.lastfm_api_secret.is_some()
+            && self.lastfm_session_key.is_some()
+        {
+            services.push(Service::LastFm);
+        }
+        services
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Scrobble {
+    pub service: Service,
+    pub listened_at: u64,
+    pub artist: String,
+    pub title: String,
+    pub album: String,
+    pub duration: Option<Duration>,
+}
+
+enum Failure {
+    Rejected(String),
+    Failed(String),
+}
+
+struct Request {
+    url: String,
+    headers: Vec<(&'static str, String)>,
+    body: String,
+}
+
+struct Submission {
+    service: Service,
+    indices: Vec<usize>,
+    receiver: Receiver<Result<(), Failure>>,
+}
+
+pub struct Scrobbler {
+    settings: ScrobbleSettings,
+    path: PathBuf,
+    queue: Vec<Scrobble>,
+    in_flight: Option<Submission>,
+    retry_at: Option<Instant>,
+    retry_delay: Duration,
+}
+
+impl Scrobbler {
+    pub fn new(settings: ScrobbleSettings, path: PathBuf) -> Self {
+        Self {
+            settings,
+            queue: storage::load_scrobbles(&path),
+            path,
+            in_flight: None,
+            retry_at: None,
+            retry_delay: FIRST_RETRY,
+        }
+    }
+
+    pub fn pending(&self) -> usize {
+        let services = self.settings.services();
+        self.queue
+            .iter()
+            .filter(|scrobble| services.contains(&scrobble.service))
+            .count()
+    }
+
+    pub fn push(
+        &mut self,
+        listened_at: u64,
+        artist: &str,
+        title: &str,
+        album: &str,
+        duration: Option<Duration>,
+    ) -> io::Result<()> {
+        let scrobbles: Vec<Scrobble> = self
+            .settings
+            .services()
+            .into_iter()
+            .map(|service| Scrobble {
+                service,
+                listened_at,
+                artist: artist.to_owned(),
+                title: title.to_owned(),
+                album: album.to_owned(),
+                duration,
+            })
+            .collect();
+        if scrobbles.is_empty() {
+            return Ok(());
+        }
+        storage::append_scrobbles(&self.path, &scrobbles)?;
+        self.queue.extend(scrobbles);
+        Ok(())
+    }
+
+    pub fn poll(&mut self) -> Option<String> {
+        if let Some(submission) = &self.in_flight {
+            let result = match submission.receiver.try_recv() {
+                Ok(result) => result,
+                Err(TryRecvError::Empty) => return None,
+                Err(TryRecvError::Disconnected) => {
+                    Err(Failure::Failed("submission stopped".to_owned()))
+                }
+            };
+            let submission = self.in_flight.take()?;
+            return self.finish(submission.service, &submission.indices, result);
+        }
+
+        if self
+            .retry_at
+            .is_some_and(|retry_at| Instant::now() < retry_at)
+        {
+            return None;
+        }
+        let (service, indices, request) = self.next_batch()?;
+        let (sender, receiver) = mpsc::channel();
+        let spawned = thread::Builder::new()
+            .name("musa-scrobble".to_owned())
+            .spawn(move || {
+                let _ = sender.send(submit(service, &request));
+            });
+        match spawned {
+            Ok(_) => {
+                self.in_flight = Some(Submission {
+                    service,
+                    indices,
+                    receiver,
+                });
+                None
+            }
+            Err(error) => self.finish(service, &indices, Err(Failure::Failed(error.to_string()))),
+        }
+    }
+
+    pub fn retry_now(&mut self) {
+        self.retry_at = None;
+        self.retry_delay = FIRST_RETRY;
+    }
+
+    pub fn flush(&mut self) -> (usize, Vec<String>) {
+        let mut sent = 0;
+        let mut messages = Vec::new();
+        while let Some((service, indices, request)) = self.next_batch() {
+            let result = submit(service, &request);
+            let failed = matches!(result, Err(Failure::Failed(_)));
+            if result.is_ok() {
+                sent += indices.len();
+            }
+            messages.extend(self.finish(service, &indices, result));
+            if failed {
+                break;
+            }
+        }
+        (sent, messages)
+    }
+
+    fn next_batch(&self) -> Option<(Service, Vec<usize>, Request)> {
+        let services = self.settings.services();
+        let service = self
+            .queue
+            .iter()
+            .map(|scrobble| scrobble.service)
+            .find(|service| services.contains(service))?;
+        let indices: Vec<usize> = (0..self.queue.len())
+            .filter(|index| self.queue[*index].service == service)
+            .take(BATCH_SIZE)
+            .collect();
+        let batch: Vec<&Scrobble> = indices.iter().map(|index| &self.queue[*index]).collect();
+        let request = match service {
+            Service::ListenBrainz => self.listenbrainz_request(&batch),
+            Service::LastFm => self.lastfm_request(&batch),
+        };
+        Some((service, indices, request))
+    }
+
+    fn finish(
+        &mut self,
+        service: Service,
+        indices: &[usize],
+        result: Result<(), Failure>,
+    ) -> Option<String> {
+        let message = match result {
+            Ok(()) => None,
+            Err(Failure::Failed(error)) => {
+                let delay = self.retry_delay;
+                self.retry_at = Some(Instant::now() + delay);
+                self.retry_delay = (delay * 2).min(LAST_RETRY);
+                return Some(format!(
+                    "Scrobbling to {} failed: {error}; retrying in {} min",
+                    service.label(),
+                    delay.as_secs().div_ceil(60)
+                ));
+            }
+            Err(Failure::Rejected(error)) => Some(format!(
+                "{} rejected {} scrobbles: {error}",
+                service.label(),
+                indices.len()
+            )),
+        };
+
+        self.retry_at = None;
+        self.retry_delay = FIRST_RETRY;
+        let mut position = 0;
+        self.queue.retain(|_| {
+            let keep = !indices.contains(&position);
+            position += 1;
+            keep
+        });
+        if let Err(error) = storage::save_scrobbles(&self.path, &self.queue) {
+            return Some(format!("Could not save the scrobble queue: {error}"));
+        }
+        message
+    }
+
+    fn listenbrainz_request(&self, batch: &[&Scrobble]) -> Request {
+        let listens: Vec<String> = batch
+            .iter()
+            .map(|scrobble| {
+                let mut metadata = format!(
+                    "\"artist_name\": {}, \"track_name\": {}",
+                    json_string(&scrobble.artist),
+                    json_string(&scrobble.title)
+                );
+                if !scrobble.album.is_empty() {
+                    metadata.push_str(&format!(
+                        ", \"release_name\": {}",
+                        json_string(&scrobble.album)
+                    ));
+                }
+                let mut info = "\"media_player\": \"musa\"".to_owned();
+                if let Some(duration) = scrobble.duration {
+                    info.push_str(&format!(", \"duration_ms\": {}", duration.as_millis()));
+                }
+                format!(
+                    "{{\"listened_at\": {}, \"track_metadata\": {{{metadata}, \"additional_info\": {{{info}}}}}}}",
+                    scrobble.listened_at
+                )
+            })
+            .collect();
+        let listen_type = if batch.len() == 1 { "single" } else { "import" };
+        Request {
+            url: format!(
+                "{}/1/submit-listens",
+                self.settings.listenbrainz_url.trim_end_matches('/')
+            ),
+            headers: vec![
+                ("Content-Type", "application/json".to_owned()),
+                (
+                    "Authorization",
+                    format!(
+                        "Token {}",
+                        self.settings.listenbrainz_token.as_deref().unwrap_or("")
+                    ),
+                ),
+            ],
+            body: format!(
+                "{{\"listen_type\": \"{listen_type}\", \"payload\": [{}]}}",
+                listens.join(", ")
+            ),
+        }
+    }
+
+    fn lastfm_request(&self, batch: &[&Scrobble]) -> Request {
+        let mut params: Vec<(String, String)> = vec![
+            ("method".to_owned(), "track.scrobble".to_owned()),
+            (
+                "api_key".to_owned(),
+                self.settings.lastfm_api_key.clone().unwrap_or_default(),
+            ),
+            (
+                "sk".to_owned(),
+                self.settings.lastfm_session_key.clone().unwrap_or_default(),
+            ),
+        ];
+        for (index, scrobble) in batch.iter().enumerate() {
+            params.push((format!("artist[{index}]"), scrobble.artist.clone()));
+            params.push((format!("track[{index}]"), scrobble.title.clone()));
+            params.push((
+                format!("timestamp[{index}]"),
+                scrobble.listened_at.to_string(),
+            ));
+            if !scrobble.album.is_empty() {
+                params.push((format!("album[{index}]"), scrobble.album.clone()));
+            }
+            if let Some(duration) = scrobble.duration {
+                params.push((format!("duration[{index}]"), duration.as_secs().to_string()));
+            }
+        }
+        params.sort();
+
+        let mut signature = String::new();
+        for (key, value) in &params {
+            signature.push_str(key);
+            signature.push_str(value);
+        }
+        signature.push_str(self.settings.lastfm_api_secret.as_deref().unwrap_or(""));
+        params.push(("api_sig".to_owned(), md5_hex(signature.as_bytes())));
+        params.push(("format".to_owned(), "json".to_owned()));
+
+        let body: Vec<String> = params
+            .iter()
+            .map(|(key, value)| format!("{}={}", form_encode(key), form_encode(value)))
+            .collect();
+        Request {
+            url: self.settings.lastfm_url.clone(),
+            headers: vec![(
+                "Content-Type",
+                "application/x-www-form-urlencoded".to_owned(),
+            )],
+            body: body.join("&"),
+        }
+    }
+}
+
+fn submit(service: Service, request: &Request) -> Result<(), Failure> {
+    let (status, body) = post(request).map_err(|error| Failure::Failed(error.to_string()))?;
+    let summary = || {
+        let text: String = body.split_whitespace().collect::<Vec<_>>().join(" ");
+        let text: String = text.chars().take(120).collect();
+        format!("HTTP {status} {text}").trim_end().to_owned()
+    };
+
+    let error_code = (service == Service::LastFm)
+        .then(|| lastfm_error(&body))
+        .flatten();
+    match (status, error_code) {
+        (200..=299, None) => Ok(()),
+        (_, Some(6 | 7)) | (400, _) => Err(Failure::Rejected(summary())),
+        _ => Err(Failure::Failed(summary())),
+    }
+}
+
+fn lastfm_error(body: &str) -> Option<u32> {
+    let rest = &body[body.find("\"error\"")? + "\"error\"".len()..];
+    let rest = rest.trim_start().strip_prefix(':')?.trim_start();
+    let digits: String = rest
+        .chars()
+        .take_while(|character| character.is_ascii_digit())
+        .collect();
+    digits.parse().ok()
+}
+
+fn post(request: &Request) -> io::Result<(u16, String)> {
+    if let Some(rest) = request.url.strip_prefix("http://") {
+        post_plain(rest, request)
+    } else if request.url.starts_with("https://") {
+        post_with_curl(request)
+    } else {
+        Err(io::Error::other(format!(
+            "unsupported endpoint {}",
+            request.url
+        )))
+    }
+}
+
+fn post_plain(url: &str, request: &Request) -> io::Result<(u16, String)> {
+    let (authority, path) = match url.find('/') {
+        Some(slash) => (&url[..slash], &url[slash..]),
+        None => (url, "/"),
+    };
+    let address = if authority.rsplit_once(':').is_some_and(|(_, port)| {
+        !port.is_empty() && port.chars().all(|character| character.is_ascii_digit())
+    }) {
+        authority.to_owned()
+    } else {
+        format!("{authority}:80")
+    };
+    let address = address
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| io::Error::other(format!("cannot resolve {authority}")))?;
+
+    let mut stream = TcpStream::connect_timeout(&address, TIMEOUT)?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+
+    let mut message = format!(
+        "POST {path} HTTP/1.1\r\nHost: {authority}\r\nUser-Agent: musa/{}\r\nConnection: close\r\nContent-Length: {}\r\n",
+        env!("CARGO_PKG_VERSION"),
+        request.body.len()
+    );
+    for (name, value) in &request.headers {
+        message.push_str(&format!("{name}: {value}\r\n"));
+    }
+    message.push_str("\r\n");
+    message.push_str(&request.body);
+    stream.write_all(message.as_bytes())?;
+
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response)?;
+    let response = String::from_utf8_lossy(&response);
+    let status = response
+        .lines()
+        .next()
+        .and_then(|line| line.split_whitespace().nth(1))
+        .and_then(|code| code.parse().ok())
+        .ok_or_else(|| io::Error::other("invalid HTTP response"))?;
+    let body = response
+        .split_once("\r\n\r\n")
+        .map_or("", |(_, body)| body)
+        .to_owned();
+    Ok((status, body))
+}
+
+fn post_with_curl(request: &Request) -> io::Result<(u16, String)> {
+    let mut config = format!(
+        "url = {}\nrequest = \"POST\"\nmax-time = {}\nuser-agent = {}\nwrite-out = \"\\n%{{http_code}}\"\n",
+        curl_quote(&request.url),
+        TIMEOUT.as_secs(),
+        curl_quote(&format!("musa/{}", env!("CARGO_PKG_VERSION")))
+    );
+    for (name, value) in &request.headers {
+        config.push_str(&format!(
+            "header = {}\n",
+            curl_quote(&format!("{name}: {value}"))
+        ));
+    }
+    config.push_str(&format!("data-binary = {}\n", curl_quote(&request.body)));
+
+    let mut child = Command::new("curl")
+        .args(["--silent", "--show-error", "--config", "-"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|error| io::Error::other(format!("https endpoints need curl: {error}")))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(config.as_bytes())?;
+    }
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        let error = String::from_utf8_lossy(&output.stderr);
+        return Err(io::Error::other(error.trim().to_owned()));
+    }
+
+    let output = String::from_utf8_lossy(&output.stdout);
+    let (body, status) = output.rsplit_once('\n').unwrap_or(("", &output));
+    let status = status
+        .trim()
+        .parse()
+        .map_err(|_| io::Error::other("invalid HTTP response"))?;
+    Ok((status, body.to_owned()))
+}
+
+fn curl_quote(text: &str) -> String {
+    let mut quoted = String::with_capacity(text.len() + 2);
+    quoted.push('"');
+    for character in text.chars() {
+        match character {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            character => quoted.push(character),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+fn form_encode(text: &str) -> String {
+    let mut encoded = String::with_capacity(text.len());
+    for byte in text.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(byte as char)
+            }
+            b' ' => encoded.push('+'),
+            byte => encoded.push_str(&format!("%{byte:02X}")),
+        }
+    }
+    encoded
+}
+
+fn md5_hex(input: &[u8]) -> String {
+    const SHIFTS: [u32; 64] = [
+        7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22, 5, 9, 14, 20, 5, 9, 14, 20, 5,
+        9, 14, 20, 5, 9, 14, 20, 4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23, 6, 10,
+        15, 21, 6, 10, 15, 21, 6, 10, 15, 21, 6, 10, 15, 21,
+    ];
+    let constants: Vec<u32> = (0..64)
+        .map(|index: i32| ((f64::from(index + 1)).sin().abs() * 4_294_967_296.0) as u32)
+        .collect();
+
+    let mut message = input.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&((input.len() as u64).wrapping_mul(8)).to_le_bytes());
+
+    let mut state: [u32; 4] = [0x6745_2301, 0xefcd_ab89, 0x98ba_dcfe, 0x1032_5476];
+    for chunk in message.chunks(64) {
+        let words: Vec<u32> = chunk
+            .chunks(4)
+            .map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]]))
+            .collect();
+        let [mut a, mut b, mut c, mut d] = state;
+        for index in 0..64 {
+            let (mix, word) = match index / 16 {
+                0 => ((b & c) | (!b & d), index),
+                1 => ((d & b) | (!d & c), (5 * index + 1) % 16),
+                2 => (b ^ c ^ d, (3 * index + 5) % 16),
+                _ => (c ^ (b | !d), (7 * index) % 16),
+            };
+            let rotated = a
+                .wrapping_add(mix)
+                .wrapping_add(constants[index])
+                .wrapping_add(words[word])
+                .rotate_left(SHIFTS[index]);
+            a = d;
+            d = c;
+            c = b;
+            b = b.wrapping_add(rotated);
+        }
+        state[0] = state[0].wrapping_add(a);
+        state[1] = state[1].wrapping_add(b);
+        state[2] = state[2].wrapping_add(c);
+        state[3] = state[3].wrapping_add(d);
+    }
+
+    state
+        .iter()
+        .flat_map(|word| word.to_le_bytes())
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        env, fs,
+        io::{BufRead, BufReader},
+        net::{Ipv4Addr, TcpListener},
+        path::Path,
+        thread::JoinHandle,
+    };
+
+    use super::*;
+
+    fn queue_path(name: &str) -> PathBuf {
+        let path =
+            env::temp_dir().join(format!("musa-scrobbles-{name}-{}.txt", std::process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    fn scrobbler(settings: ScrobbleSettings) -> Scrobbler {
+        Scrobbler::new(settings, queue_path("unused"))
+    }
+
+    fn listenbrainz(url: &str) -> ScrobbleSettings {
+        ScrobbleSettings {
+            listenbrainz_token: Some("token".to_owned()),
+            listenbrainz_url: url.to_owned(),
+            ..ScrobbleSettings::default()
+        }
+    }
+
+    fn push(scrobbler: &mut Scrobbler, title: &str) {
+        scrobbler
+            .push(
+                1_700_000_000,
+                "Artist",
+                title,
+                "Album",
+                Some(Duration::from_secs(200)),
+            )
+            .unwrap();
+    }
+
+    /// Answers one connection per response, in order, and returns the
+    /// request lines it saw.
+    fn serve(responses: &[(u16, &str)]) -> (String, JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let responses: Vec<(u16, String)> = responses
+            .iter()
+            .map(|(status, body)| (*status, (*body).to_owned()))
+            .collect();
+        let server = thread::spawn(move || {
+            let mut requests = Vec::new();
+            for (status, body) in responses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut length = 0;
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                requests.push(line.trim_end().to_owned());
+                loop {
+                    line.clear();
+                    reader.read_line(&mut line).unwrap();
+                    if line == "\r\n" {
+                        break;
+                    }
+                    if let Some(value) = line.strip_prefix("Content-Length:") {
+                        length = value.trim().parse().unwrap();
+                    }
+                }
+                reader.read_exact(&mut vec![0; length]).unwrap();
+                write!(
+                    stream,
+                    "HTTP/1.1 {status} Status\r\nContent-Length: {}\r\n\r\n{body}",
+                    body.len()
+                )
+                .unwrap();
+            }
+            requests
+        });
+        (url, server)
+    }
+
+    fn saved(path: &Path) -> Vec<String> {
+        storage::load_scrobbles(path)
+            .into_iter()
+            .map(|scrobble| scrobble.title)
+            .collect()
+    }
+
+    fn form_decode(text: &str) -> String {
+        let mut bytes = Vec::new();
+        let mut rest = text.as_bytes();
+        while let Some((&byte, tail)) = rest.split_first() {
+            match byte {
+                b'+' => bytes.push(b' '),
+                b'%' => {
+                    let hex = std::str::from_utf8(&tail[..2]).unwrap();
+                    bytes.push(u8::from_str_radix(hex, 16).unwrap());
+                    rest = &tail[2..];
+                    continue;
+                }
+                byte => bytes.push(byte),
+            }
+            rest = tail;
+        }
+        String::from_utf8(bytes).unwrap()
+    }
+
+    fn curl_unquote(text: &str) -> String {
+        let inner = text.strip_prefix('"').unwrap().strip_suffix('"').unwrap();
+        let mut unquoted = String::new();
+        let mut characters = inner.chars();
+        while let Some(character) = characters.next() {
+            if character != '\\' {
+                assert_ne!(character, '"', "unescaped quote in {text}");
+                unquoted.push(character);
+                continue;
+            }
+            unquoted.push(match characters.next().unwrap() {
+                'n' => '\n',
+                'r' => '\r',
+                't' => '\t',
+                escaped => escaped,
+            });
+        }
+        unquoted
+    }
+
+    #[test]
+    fn md5_matches_rfc_1321_vectors() {
+        let vectors = [
+            ("", "d41d8cd98f00b204e9800998ecf8427e"),
+            ("a", "0cc175b9c0f1b6a831c399e269772661"),
+            ("abc", "900150983cd24fb0d6963f7d28e17f72"),
+            ("message digest", "f96b697d7cb7938d525a2f31aaf161d0"),
+            (
+                "abcdefghijklmnopqrstuvwxyz",
+                "c3fcd3d76192e4007dfb496cca67e13b",
+            ),
+            (
+                "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789",
+                "d174ab98d277d9f5a5611c2c9f419d9f",
+            ),
+            (
+                "12345678901234567890123456789012345678901234567890123456789012345678901234567890",
+                "57edf4a22be3c955ac49da2e2107b67a",
+            ),
+        ];
+        for (input, digest) in vectors {
+            assert_eq!(md5_hex(input.as_bytes()), digest, "md5 of {input:?}");
+        }
+    }
+
+    #[test]
+    fn lastfm_request_is_signed_over_sorted_parameters() {
+        let scrobbler = scrobbler(ScrobbleSettings {
+            lastfm_api_key: Some("key".to_owned()),
+            lastfm_api_secret: Some("secret".to_owned()),
+            lastfm_session_key: Some("sess".to_owned()),
+            ..ScrobbleSettings::default()
+        });
+        let scrobble = Scrobble {
+            service: Service::LastFm,
+            listened_at: 1_700_000_000,
+            artist: "Sigur Rós".to_owned(),
+            title: "Hoppípolla".to_owned(),
+            album: "Takk...".to_owned(),
+            duration: Some(Duration::from_secs(268)),
+        };
+
+        let request = scrobbler.lastfm_request(&[&scrobble]);
+        assert_eq!(request.url, "https://ws.audioscrobbler.com/2.0/");
+        assert_eq!(
+            request.body,
+            "album%5B0%5D=Takk...&api_key=key&artist%5B0%5D=Sigur+R%C3%B3s\
+             &duration%5B0%5D=268&method=track.scrobble&sk=sess\
+             &timestamp%5B0%5D=1700000000&track%5B0%5D=Hopp%C3%ADpolla\
+             &api_sig=26523b3745233a477cdaf5f75210ff50&format=json"
+        );
+    }
+
+    #[test]
+    fn form_encode_and_curl_quote_round_trip() {
+        let samples = [
+            "",
+            "plain",
+            "a b+c&d=e%f",
+            "Björk / Sigur Rós ~ 東京",
+            "quote \" backslash \\ tab\tnewline\nreturn\r",
+        ];
+        for sample in samples {
+            assert_eq!(form_decode(&form_encode(sample)), sample);
+            assert_eq!(curl_unquote(&curl_quote(sample)), sample);
+        }
+        assert_eq!(form_encode("a b&c"), "a+b%26c");
+        assert_eq!(curl_quote("say \"hi\"\n"), "\"say \\\"hi\\\"\\n\"");
+    }
+
+    #[test]
+    fn accepted_batches_leave_the_queue() {
+        let (url, server) = serve(&[(200, "{\"status\": \"ok\"}")]);
+        let path = queue_path("accepted");
+        let mut scrobbler = Scrobbler::new(listenbrainz(&url), path.clone());
+        push(&mut scrobbler, "One");
+        push(&mut scrobbler, "Two");
+        assert_eq!(saved(&path), ["One", "Two"]);
+
+        assert_eq!(scrobbler.flush(), (2, Vec::new()));
+        assert_eq!(scrobbler.pending(), 0);
+        assert!(saved(&path).is_empty());
+        assert_eq!(server.join().unwrap(), ["POST /1/submit-listens HTTP/1.1"]);
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn rejected_batches_are_dropped_with_a_message() {
+        let (url, server) = serve(&[
+            (400, "bad listen"),
+            (200, "{\"error\": 6, \"message\": \"Invalid parameters\"}"),
+        ]);
+        let path = queue_path("rejected");
+        let settings = ScrobbleSettings {
+            lastfm_api_key: Some("key".to_owned()),
+            lastfm_api_secret: Some("secret".to_owned()),
+            lastfm_session_key: Some("session".to_owned()),
+            lastfm_url: url.clone(),
+            ..listenbrainz(&url)
+        };
+        let mut scrobbler = Scrobbler::new(settings, path.clone());
+        push(&mut scrobbler, "One");
+        assert_eq!(scrobbler.pending(), 2);
+
+        let (sent, messages) = scrobbler.flush();
+        assert_eq!(sent, 0);
+        assert_eq!(
+            messages,
+            [
+                "ListenBrainz rejected 1 scrobbles: HTTP 400 bad listen",
+                "Last.fm rejected 1 scrobbles: HTTP 200 {\"error\": 6, \"message\": \"Invalid parameters\"}",
+            ]
+        );
+        assert_eq!(scrobbler.pending(), 0);
+        assert!(saved(&path).is_empty());
+        assert_eq!(scrobbler.retry_delay, FIRST_RETRY);
+        server.join().unwrap();
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn failed_batches_stay_queued_and_back_off() {
+        let closed = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let unreachable = format!("http://{}/", closed.local_addr().unwrap());
+        drop(closed);
+        let path = queue_path("failed");
+        let mut scrobbler = Scrobbler::new(listenbrainz(&unreachable), path.clone());
+        push(&mut scrobbler, "One");
+
+        let (sent, messages) = scrobbler.flush();
+        assert_eq!(sent, 0);
+        assert_eq!(messages.len(), 1);
+        assert!(messages[0].starts_with("Scrobbling to ListenBrainz failed: "));
+        assert!(messages[0].ends_with("; retrying in 1 min"), "{messages:?}");
+        assert_eq!(scrobbler.retry_delay, FIRST_RETRY * 2);
+
+        let (url, server) = serve(&[(503, "busy"); 5]);
+        scrobbler.settings.listenbrainz_url = url.clone();
+        let mut delays = Vec::new();
+        for _ in 0..5 {
+            assert_eq!(scrobbler.flush().0, 0);
+            delays.push(scrobbler.retry_delay.as_secs() / 60);
+        }
+        assert_eq!(delays, [4, 8, 16, 30, 30]);
+        assert_eq!(scrobbler.pending(), 1);
+        assert_eq!(saved(&path), ["One"]);
+        server.join().unwrap();
+
+        // The background submitter waits out the delay unless told not to.
+        assert_eq!(scrobbler.poll(), None);
+        assert!(scrobbler.in_flight.is_none());
+        let (url, server) = serve(&[(200, "{}")]);
+        scrobbler.settings.listenbrainz_url = url;
+        scrobbler.retry_now();
+        assert_eq!(scrobbler.poll(), None);
+        assert!(scrobbler.in_flight.is_some());
+        server.join().unwrap();
+        while scrobbler.in_flight.is_some() {
+            assert_eq!(scrobbler.poll(), None);
+            thread::sleep(Duration::from_millis(5));
+        }
+        assert_eq!(scrobbler.pending(), 0);
+        assert_eq!(
+            (scrobbler.retry_at, scrobbler.retry_delay),
+            (None, FIRST_RETRY)
+        );
+        let _ = fs::remove_file(path);
+    }
+}
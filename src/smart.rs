@@ -0,0 +1,400 @@
+use std::{
+    cmp::Ordering,
+    fmt,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    history::PlayStats,
+    library::{Track, compare_text},
+    query::Query,
+};
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SmartPlaylist {
+    pub rules: Vec<Rule>,
+    pub sort: Sort,
+    pub limit: Option<usize>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Rule {
+    Artist(String),
+    Album(String),
+    AlbumArtist(String),
+    Genre(String),
+    Path(PathBuf),
+    Year(Range),
+    Duration(Range),
+    Added(u64),
+    Plays(Range),
+    Skips(Range),
+    Played(u64),
+    Rating(Range),
+    Query(Query),
+    Invalid(String),
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Range {
+    pub min: Option<u64>,
+    pub max: Option<u64>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Sort {
+    pub key: SortKey,
+    pub descending: bool,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SortKey {
+    #[default]
+    Library,
+    Title,
+    Artist,
+    Album,
+    Year,
+    Duration,
+    Added,
+    Plays,
+    Skips,
+    LastPlayed,
+    Rating,
+}
+
+const SORT_KEYS: [(SortKey, &str); 11] = [
+    (SortKey::Library, "library"),
+    (SortKey::Title, "title"),
+    (SortKey::Artist, "artist"),
+    (SortKey::Album, "album"),
+    (SortKey::Year, "year"),
+    (SortKey::Duration, "duration"),
+    (SortKey::Added, "added"),
+    (SortKey::Plays, "plays"),
+    (SortKey::Skips, "skips"),
+    (SortKey::LastPlayed, "lastplayed"),
+    (SortKey::Rating, "rating"),
+];
+
+impl SmartPlaylist {
+    pub fn select(&self, tracks: &[Track], stats: impl Fn(&Path) -> PlayStats) -> Vec<usize> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_secs());
+        let rules: Vec<Rule> = self.rules.iter().map(Rule::folded).collect();
+        let mut selected: Vec<usize> = (0..tracks.len())
+            .filter(|index| {
+                let track = &tracks[*index];
+                rules.iter().all(|rule| rule.matches(track, now, &stats))
+            })
+            .collect();
+
+        if self.sort.key != SortKey::Library {
+            selected.sort_by(|left, right| {
+                self.sort
+                    .key
+                    .compare(&tracks[*left], &tracks[*right], &stats)
+            });
+        }
+        if self.sort.descending {
+            selected.reverse();
+        }
+        if let Some(limit) = self.limit {
+            selected.truncate(limit);
+        }
+        selected
+    }
+
+    pub fn summary(&self) -> String {
+        let mut parts: Vec<String> = self
+            .rules
+            .iter()
+            .map(|rule| match rule {
+                Rule::Invalid(text) => format!("invalid rule '{text}'"),
+                rule => rule.to_string(),
+            })
+            .collect();
+        if parts.is_empty() {
+            parts.push("all songs".to_owned());
+        }
+        if self.sort != Sort::default() {
+            parts.push(format!("sorted by {}", self.sort));
+        }
+        if let Some(limit) = self.limit {
+            parts.push(format!("first {limit}"));
+        }
+        parts.join(", ")
+    }
+}
+
+impl Rule {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let text = text.trim();
+        let Some((field, value)) = text.split_once(':') else {
+            return Err(format!("rule '{text}' must look like field:value"));
+        };
+        let value = value.trim();
+        if value.is_empty() {
+            return Err(format!("rule '{text}' has no value"));
+        }
+        let invalid = || format!("invalid value in rule '{text}'");
+        let rule = match field.trim().to_ascii_lowercase().as_str() {
+            "artist" => Self::Artist(value.to_owned()),
+            "album" => Self::Album(value.to_owned()),
+            "albumartist" | "album_artist" => Self::AlbumArtist(value.to_owned()),
+            "genre" => Self::Genre(value.to_owned()),
+            "path" => Self::Path(PathBuf::from(value)),
+            "year" => Self::Year(Range::parse(value, parse_number).ok_or_else(invalid)?),
+            "duration" => Self::Duration(Range::parse(value, parse_seconds).ok_or_else(invalid)?),
+            "added" => Self::Added(parse_days(value).ok_or_else(invalid)?),
+            "plays" => Self::Plays(Range::parse(value, parse_number).ok_or_else(invalid)?),
+            "skips" => Self::Skips(Range::parse(value, parse_number).ok_or_else(invalid)?),
+            "played" => Self::Played(parse_days(value).ok_or_else(invalid)?),
+            "rating" => Self::Rating(Range::parse(value, parse_number).ok_or_else(invalid)?),
+            "query" => Self::Query(
+                Query::parse(value).map_err(|error| format!("invalid query in rule '{text}': {error}"))?,
+            ),
+            field => return Err(format!("unknown rule field '{field}'")),
+        };
+        Ok(rule)
+    }
+
+    fn folded(&self) -> Self {
+        match self {
+            Self::Artist(text) => Self::Artist(text.to_lowercase()),
+            Self::Album(text) => Self::Album(text.to_lowercase()),
+            Self::AlbumArtist(text) => Self::AlbumArtist(text.to_lowercase()),
+            Self::Genre(text) => Self::Genre(text.to_lowercase()),
+            rule => rule.clone(),
+        }
+    }
+
+    fn matches(&self, track: &Track, now: u64, stats: &impl Fn(&Path) -> PlayStats) -> bool {
+        let contains = |field: &str, needle: &str| field.to_lowercase().contains(needle);
+        match self {
+            Self::Artist(needle) => contains(&track.artist, needle),
+            Self::Album(needle) => contains(&track.album, needle),
+            Self::AlbumArtist(needle) => contains(&track.album_artist, needle),
+            Self::Genre(needle) => track
+                .genre
+                .as_deref()
+                .is_some_and(|genre| contains(genre, needle)),
+            Self::Path(prefix) => track.path.starts_with(prefix),
+            Self::Year(range) => track
+                .year
+                .is_some_and(|year| range.contains(u64::from(year))),
+            Self::Duration(range) => track
+                .duration
+                .is_some_and(|duration| range.contains(duration.as_secs())),
+            Self::Added(days) => track
+                .modified
+                .is_some_and(|modified| modified >= now.saturating_sub(days.saturating_mul(86_400))),
+            Self::Plays(range) => range.contains(u64::from(stats(&track.path).plays)),
+            Self::Skips(range) => range.contains(u64::from(stats(&track.path).skips)),
+            Self::Played(days) => stats(&track.path)
+                .last_played
+                .is_some_and(|played| played >= now.saturating_sub(days.saturating_mul(86_400))),
+            Self::Rating(range) => range.contains(u64::from(track.rating.unwrap_or(0))),
+            Self::Query(query) => query.matches(track),
+            Self::Invalid(_) => false,
+        }
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Artist(text) => write!(formatter, "artist:{text}"),
+            Self::Album(text) => write!(formatter, "album:{text}"),
+            Self::AlbumArtist(text) => write!(formatter, "albumartist:{text}"),
+            Self::Genre(text) => write!(formatter, "genre:{text}"),
+            Self::Path(path) => write!(formatter, "path:{}", path.display()),
+            Self::Year(range) => write!(
+                formatter,
+                "year:{}",
+                range.display(|value| value.to_string())
+            ),
+            Self::Duration(range) => {
+                write!(formatter, "duration:{}", range.display(format_seconds))
+            }
+            Self::Added(days) => write!(formatter, "added:{days}d"),
+            Self::Plays(range) => write!(
+                formatter,
+                "plays:{}",
+                range.display(|value| value.to_string())
+            ),
+            Self::Skips(range) => write!(
+                formatter,
+                "skips:{}",
+                range.display(|value| value.to_string())
+            ),
+            Self::Played(days) => write!(formatter, "played:{days}d"),
+            Self::Rating(range) => write!(
+                formatter,
+                "rating:{}",
+                range.display(|value| value.to_string())
+            ),
+            Self::Query(query) => write!(formatter, "query:{query}"),
+            Self::Invalid(text) => formatter.write_str(text),
+        }
+    }
+}
+
+impl Range {
+    pub fn parse(text: &str, value: fn(&str) -> Option<u64>) -> Option<Self> {
+        let text = text.trim();
+        if let Some((min, max)) = text.split_once("..") {
+            let bound = |text: &str| match text.trim() {
+                "" => Some(None),
+                text => value(text).map(Some),
+            };
+            let range = Self {
+                min: bound(min)?,
+                max: bound(max)?,
+            };
+            return (range.min.is_some() || range.max.is_some()).then_some(range);
+        }
+        if let Some(rest) = text.strip_prefix(">=") {
+            return value(rest.trim()).map(|min| Self {
+                min: Some(min),
+                max: None,
+            });
+        }
+        if let Some(rest) = text.strip_prefix("<=") {
+            return value(rest.trim()).map(|max| Self {
+                min: None,
+                max: Some(max),
+            });
+        }
+        if let Some(rest) = text.strip_prefix('>') {
+            return value(rest.trim()).map(|min| Self {
+                min: Some(min.saturating_add(1)),
+                max: None,
+            });
+        }
+        if let Some(rest) = text.strip_prefix('<') {
+            return value(rest.trim()).and_then(|max| {
+                Some(Self {
+                    min: None,
+                    max: Some(max.checked_sub(1)?),
+                })
+            });
+        }
+        value(text).map(|exact| Self {
+            min: Some(exact),
+            max: Some(exact),
+        })
+    }
+
+    pub fn contains(&self, value: u64) -> bool {
+        self.min.is_none_or(|min| value >= min) && self.max.is_none_or(|max| value <= max)
+    }
+
+    fn display(&self, value: impl Fn(u64) -> String) -> String {
+        match (self.min, self.max) {
+            (Some(min), Some(max)) if min == max => value(min),
+            (min, max) => format!(
+                "{}..{}",
+                min.map(&value).unwrap_or_default(),
+                max.map(&value).unwrap_or_default()
+            ),
+        }
+    }
+}
+
+impl Sort {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let text = text.trim();
+        let (key, descending) = match text.split_once(':') {
+            Some((key, "desc")) => (key, true),
+            Some((key, "asc")) => (key, false),
+            Some(_) => return Err(format!("sort order in '{text}' must be asc or desc")),
+            None => (text, false),
+        };
+        let key = key.trim().to_ascii_lowercase();
+        SORT_KEYS
+            .iter()
+            .find(|(_, name)| *name == key)
+            .map(|(key, _)| Self {
+                key: *key,
+                descending,
+            })
+            .ok_or_else(|| format!("unknown sort field '{key}'"))
+    }
+}
+
+impl fmt::Display for Sort {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = SORT_KEYS
+            .iter()
+            .find(|(key, _)| *key == self.key)
+            .map_or("library", |(_, name)| name);
+        if self.descending {
+            write!(formatter, "{name}:desc")
+        } else {
+            formatter.write_str(name)
+        }
+    }
+}
+
+impl SortKey {
+    fn compare(self, left: &Track, right: &Track, stats: &impl Fn(&Path) -> PlayStats) -> Ordering {
+        match self {
+            Self::Library => Ordering::Equal,
+            Self::Title => compare_text(&left.title, &right.title),
+            Self::Artist => compare_text(&left.artist, &right.artist),
+            Self::Album => compare_text(&left.album, &right.album),
+            Self::Year => left.year.cmp(&right.year),
+            Self::Duration => left.duration.cmp(&right.duration),
+            Self::Added => left.modified.cmp(&right.modified),
+            Self::Plays => stats(&left.path).plays.cmp(&stats(&right.path).plays),
+            Self::Skips => stats(&left.path).skips.cmp(&stats(&right.path).skips),
+            Self::LastPlayed => stats(&left.path)
+                .last_played
+                .cmp(&stats(&right.path).last_played),
+            Self::Rating => left.rating.cmp(&right.rating),
+        }
+    }
+}
+
+pub fn parse_seconds(text: &str) -> Option<u64> {
+    let text = text.trim();
+    if let Some((minutes, seconds)) = text.split_once(':') {
+        return minutes
+            .parse::<u64>()
+            .ok()?
+            .checked_mul(60)?
+            .checked_add(seconds.parse::<u64>().ok()?);
+    }
+    let (number, scale) = match text.char_indices().last()? {
+        (index, 's') => (&text[..index], 1),
+        (index, 'm') => (&text[..index], 60),
+        (index, 'h') => (&text[..index], 3600),
+        _ => (text, 1),
+    };
+    number.trim().parse::<u64>().ok()?.checked_mul(scale)
+}
+
+fn format_seconds(seconds: u64) -> String {
+    if seconds > 0 && seconds.is_multiple_of(60) {
+        format!("{}m", seconds / 60)
+    } else {
+        format!("{seconds}s")
+    }
+}
+
+fn parse_days(text: &str) -> Option<u64> {
+    let text = text.trim();
+    let (number, scale) = match text.char_indices().last()? {
+        (index, 'd') => (&text[..index], 1),
+        (index, 'w') => (&text[..index], 7),
+        _ => (text, 1),
+    };
+    number.trim().parse::<u64>().ok()?.checked_mul(scale)
+}
+
+pub fn parse_number(text: &str) -> Option<u64> {
+    text.trim().parse().ok()
+}
@@ -1,17 +1,175 @@
 use std::{
+    borrow::Cow,
     collections::HashSet,
-    env, fs,
-    fs::File,
+    env,
+    ffi::OsString,
+    fs,
+    fs::{File, OpenOptions},
     io::{self, BufRead, BufReader, BufWriter, Write},
     path::{Path, PathBuf},
+    sync::{Arc, OnceLock},
+    time::Duration,
 };
 
-use crate::app::Theme;
+use crate::{
+    app::{RepeatMode, Theme},
+    audio::{FadeCurve, GainMode},
+    library::{AudioFormat, CachedTrack, FileStamp, ReplayGain, Track, TrackRange, TrackTags},
+    scrobble::{Scrobble, ScrobbleSettings, Service},
+    smart::{Rule, SmartPlaylist, Sort},
+};
+
+const TRACK_CACHE_HEADER: &str = "musa-track-cache\t6";
+const SESSION_HEADER: &str = "musa-session\t1";
+const HISTORY_HEADER: &str = "musa-history\t1";
+const SCROBBLES_HEADER: &str = "musa-scrobbles\t1";
+
+static CONFIG_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+#[derive(Clone, Debug)]
+pub struct Settings {
+    pub scan_threads: usize,
+    pub crossfade_seconds: f64,
+    pub crossfade_curve: FadeCurve,
+    pub crossfade_skips: bool,
+    pub gain_mode: GainMode,
+    pub gain_preamp: f32,
+    pub prevent_clipping: bool,
+    pub mpd_port: Option<u16>,
+    pub album_artists: bool,
+    pub artist_separators: Vec<String>,
+    pub scrobble: ScrobbleSettings,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            scan_threads: 0,
+            crossfade_seconds: 0.0,
+            crossfade_curve: FadeCurve::default(),
+            crossfade_skips: true,
+            gain_mode: GainMode::Off,
+            gain_preamp: 0.0,
+            prevent_clipping: true,
+            mpd_port: None,
+            album_artists: false,
+            artist_separators: [";", "/", "feat.", "ft.", "featuring"]
+                .map(str::to_owned)
+                .to_vec(),
+            scrobble: ScrobbleSettings::default(),
+        }
+    }
+}
+
+pub fn load_settings() -> Settings {
+    let mut settings = Settings::default();
+    let Ok(file) = File::open(config_dir().join("settings.txt")) else {
+        return settings;
+    };
+
+    for line in BufReader::new(file).lines().map_while(Result::ok) {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        let value = value.trim();
+        match key.trim() {
+            "scan_threads" => {
+                if let Ok(threads) = value.parse() {
+                    settings.scan_threads = threads;
+                }
+            }
+            "crossfade" => {
+                if let Some(seconds) = value.parse::<f64>().ok().filter(|seconds| seconds.is_finite()) {
+                    settings.crossfade_seconds = seconds.clamp(0.0, 30.0);
+                }
+            }
+            "crossfade_curve" => {
+                if let Some(curve) = FadeCurve::parse(value) {
+                    settings.crossfade_curve = curve;
+                }
+            }
+            "crossfade_skips" => {
+                if let Some(enabled) = parse_bool(value) {
+                    settings.crossfade_skips = enabled;
+                }
+            }
+            "replaygain" => {
+                if let Some(mode) = GainMode::parse(value) {
+                    settings.gain_mode = mode;
+                }
+            }
+            "replaygain_preamp" => {
+                if let Some(preamp) = value
+                    .trim_end_matches("dB")
+                    .trim()
+                    .parse::<f32>()
+                    .ok()
+                    .filter(|preamp| preamp.is_finite())
+                {
+                    settings.gain_preamp = preamp.clamp(-15.0, 15.0);
+                }
+            }
+            "replaygain_prevent_clipping" => {
+                if let Some(enabled) = parse_bool(value) {
+                    settings.prevent_clipping = enabled;
+                }
+            }
+            "mpd_port" => {
+                if let Ok(port) = value.parse::<u16>() {
+                    settings.mpd_port = (port != 0).then_some(port);
+                }
+            }
+            "album_artists" => {
+                if let Some(enabled) = parse_bool(value) {
+                    settings.album_artists = enabled;
+                }
+            }
+            "artist_separators" => {
+                settings.artist_separators =
+                    value.split_whitespace().map(str::to_owned).collect();
+            }
+            "listenbrainz_token" => settings.scrobble.listenbrainz_token = non_empty(value),
+            "listenbrainz_url" => {
+                if let Some(url) = non_empty(value) {
+                    settings.scrobble.listenbrainz_url = url;
+                }
+            }
+            "lastfm_api_key" => settings.scrobble.lastfm_api_key = non_empty(value),
+            "lastfm_api_secret" => settings.scrobble.lastfm_api_secret = non_empty(value),
+            "lastfm_session_key" => settings.scrobble.lastfm_session_key = non_empty(value),
+            "lastfm_url" => {
+                if let Some(url) = non_empty(value) {
+                    settings.scrobble.lastfm_url = url;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    settings
+}
+
+fn non_empty(value: &str) -> Option<String> {
+    (!value.is_empty()).then(|| value.to_owned())
+}
+
+fn parse_bool(value: &str) -> Option<bool> {
+    match value.to_ascii_lowercase().as_str() {
+        "true" | "yes" | "on" | "1" => Some(true),
+        "false" | "no" | "off" | "0" => Some(false),
+        _ => None,
+    }
+}
 
 #[derive(Clone, Debug)]
 pub struct StoredPlaylist {
     pub name: String,
     pub tracks: Vec<PathBuf>,
+    pub smart: Option<SmartPlaylist>,
 }
 
 pub fn load_roots() -> Vec<PathBuf> {
@@ -88,6 +246,7 @@ pub fn load_playlists() -> Vec<StoredPlaylist> {
                 current = Some(StoredPlaylist {
                     name: value,
                     tracks: Vec::new(),
+                    smart: None,
                 });
             }
             "T" => {
@@ -95,6 +254,21 @@ pub fn load_playlists() -> Vec<StoredPlaylist> {
                     playlist.tracks.push(PathBuf::from(value));
                 }
             }
+            "R" | "S" | "L" => {
+                let Some(smart) = current
+                    .as_mut()
+                    .map(|playlist| playlist.smart.get_or_insert_with(SmartPlaylist::default))
+                else {
+                    continue;
+                };
+                match kind {
+                    "R" => smart
+                        .rules
+                        .push(Rule::parse(&value).unwrap_or(Rule::Invalid(value))),
+                    "S" => smart.sort = Sort::parse(&value).unwrap_or_default(),
+                    _ => smart.limit = value.parse().ok(),
+                }
+            }
             _ => {}
         }
     }
@@ -108,16 +282,29 @@ pub fn load_playlists() -> Vec<StoredPlaylist> {
 
 pub fn save_playlists<'a, I>(playlists: I) -> io::Result<()>
 where
-    I: IntoIterator<Item = (&'a str, &'a [PathBuf])>,
+    I: IntoIterator<Item = (&'a str, &'a [PathBuf], Option<&'a SmartPlaylist>)>,
 {
     atomic_write("playlists.txt", |writer| {
         let mut escaped = String::new();
-        for (name, tracks) in playlists {
+        for (name, tracks, smart) in playlists {
             writer.write_all(b"P\t")?;
             escape_field_into(name, &mut escaped);
             writer.write_all(escaped.as_bytes())?;
             writer.write_all(b"\n")?;
 
+            if let Some(smart) = smart {
+                for rule in &smart.rules {
+                    escape_field_into(&rule.to_string(), &mut escaped);
+                    writeln!(writer, "R\t{escaped}")?;
+                }
+                writeln!(writer, "S\t{}", smart.sort)?;
+                if let Some(limit) = smart.limit {
+                    writeln!(writer, "L\t{limit}")?;
+                }
+                writer.write_all(b"\n")?;
+                continue;
+            }
+
             for path in tracks {
                 writer.write_all(b"T\t")?;
                 escape_field_into(&path.to_string_lossy(), &mut escaped);
@@ -130,6 +317,438 @@ where
     })
 }
 
+#[derive(Clone, Debug)]
+pub struct Session {
+    pub volume: u8,
+    pub shuffle: bool,
+    pub repeat: RepeatMode,
+    pub folder: bool,
+    pub current: Option<PathBuf>,
+    pub position: Duration,
+    pub queue_pos: Option<usize>,
+    pub queue_base: Vec<PathBuf>,
+    pub queue: Vec<PathBuf>,
+    pub recent: Vec<PathBuf>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Play {
+    pub path: PathBuf,
+    pub played_at: u64,
+    pub listened: Duration,
+    pub skipped: bool,
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Self {
+            volume: 70,
+            shuffle: false,
+            repeat: RepeatMode::Off,
+            folder: false,
+            current: None,
+            position: Duration::ZERO,
+            queue_pos: None,
+            queue_base: Vec::new(),
+            queue: Vec::new(),
+            recent: Vec::new(),
+        }
+    }
+}
+
+pub fn load_session() -> Session {
+    let mut session = Session::default();
+    let Ok(file) = File::open(config_dir().join("session.txt")) else {
+        return session;
+    };
+
+    let mut lines = BufReader::new(file).lines().map_while(Result::ok);
+    if lines.next().as_deref() != Some(SESSION_HEADER) {
+        return session;
+    }
+
+    for line in lines {
+        let Some((kind, value)) = line.split_once('\t') else {
+            continue;
+        };
+        let value = unescape_field(value);
+        match kind {
+            "volume" => {
+                if let Ok(volume) = value.parse::<u8>() {
+                    session.volume = volume.min(100);
+                }
+            }
+            "shuffle" => session.shuffle = value == "1",
+            "repeat" => {
+                session.repeat = match value.as_str() {
+                    "all" => RepeatMode::All,
+                    "one" => RepeatMode::One,
+                    _ => RepeatMode::Off,
+                };
+            }
+            "folder" => session.folder = value == "1",
+            "current" => session.current = Some(PathBuf::from(value)),
+            "position_ms" => {
+                if let Ok(millis) = value.parse() {
+                    session.position = Duration::from_millis(millis);
+                }
+            }
+            "queue_pos" => session.queue_pos = value.parse().ok(),
+            "B" => session.queue_base.push(PathBuf::from(value)),
+            "Q" => session.queue.push(PathBuf::from(value)),
+            "R" => session.recent.push(PathBuf::from(value)),
+            _ => {}
+        }
+    }
+
+    if !session.shuffle || session.queue.len() != session.queue_base.len() {
+        session.queue.clone_from(&session.queue_base);
+    }
+    session
+}
+
+pub fn save_session(session: &Session) -> io::Result<()> {
+    atomic_write("session.txt", |writer| {
+        writeln!(writer, "{SESSION_HEADER}")?;
+        writeln!(writer, "volume\t{}", session.volume)?;
+        writeln!(writer, "shuffle\t{}", u8::from(session.shuffle))?;
+        writeln!(writer, "repeat\t{}", session.repeat.label())?;
+        writeln!(writer, "folder\t{}", u8::from(session.folder))?;
+        writeln!(writer, "position_ms\t{}", session.position.as_millis())?;
+        if let Some(queue_pos) = session.queue_pos {
+            writeln!(writer, "queue_pos\t{queue_pos}")?;
+        }
+
+        let mut escaped = String::new();
+        let mut write_path = |writer: &mut BufWriter<File>, kind: &str, path: &Path| {
+            escape_field_into(&path.to_string_lossy(), &mut escaped);
+            writeln!(writer, "{kind}\t{escaped}")
+        };
+        if let Some(current) = &session.current {
+            write_path(writer, "current", current)?;
+        }
+        for path in &session.queue_base {
+            write_path(writer, "B", path)?;
+        }
+        if session.shuffle {
+            for path in &session.queue {
+                write_path(writer, "Q", path)?;
+            }
+        }
+        for path in &session.recent {
+            write_path(writer, "R", path)?;
+        }
+        Ok(())
+    })
+}
+
+pub fn load_history() -> Vec<Play> {
+    let Ok(file) = File::open(config_dir().join("history.txt")) else {
+        return Vec::new();
+    };
+
+    let mut lines = BufReader::new(file).lines().map_while(Result::ok);
+    if lines.next().as_deref() != Some(HISTORY_HEADER) {
+        return Vec::new();
+    }
+
+    lines
+        .filter_map(|line| {
+            let mut fields = line.split('\t');
+            let path = PathBuf::from(unescape_field(fields.next()?));
+            let played_at = fields.next()?.parse().ok()?;
+            let listened = Duration::from_millis(fields.next()?.parse().ok()?);
+            let skipped = fields.next()? == "1";
+            Some(Play {
+                path,
+                played_at,
+                listened,
+                skipped,
+            })
+        })
+        .collect()
+}
+
+pub fn append_play(play: &Play) -> io::Result<()> {
+    let dir = config_dir();
+    fs::create_dir_all(&dir)?;
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(dir.join("history.txt"))?;
+
+    let mut line = String::new();
+    if file.metadata()?.len() == 0 {
+        line.push_str(HISTORY_HEADER);
+        line.push('\n');
+    }
+    let mut escaped = String::new();
+    escape_field_into(&play.path.to_string_lossy(), &mut escaped);
+    line.push_str(&format!(
+        "{escaped}\t{}\t{}\t{}\n",
+        play.played_at,
+        play.listened.as_millis(),
+        u8::from(play.skipped)
+    ));
+    file.write_all(line.as_bytes())
+}
+
+pub fn load_scrobbles(path: &Path) -> Vec<Scrobble> {
+    let Ok(file) = File::open(path) else {
+        return Vec::new();
+    };
+
+    let mut lines = BufReader::new(file).lines().map_while(Result::ok);
+    if lines.next().as_deref() != Some(SCROBBLES_HEADER) {
+        return Vec::new();
+    }
+
+    lines
+        .filter_map(|line| {
+            let mut fields = line.split('\t');
+            let service = Service::parse(fields.next()?)?;
+            let listened_at = fields.next()?.parse().ok()?;
+            let artist = unescape_field(fields.next()?);
+            let title = unescape_field(fields.next()?);
+            let album = unescape_field(fields.next()?);
+            let duration = fields
+                .next()?
+                .parse()
+                .ok()
+                .map(Duration::from_millis);
+            Some(Scrobble {
+                service,
+                listened_at,
+                artist,
+                title,
+                album,
+                duration,
+            })
+        })
+        .collect()
+}
+
+pub fn save_scrobbles(path: &Path, scrobbles: &[Scrobble]) -> io::Result<()> {
+    atomic_write_at(path, |writer| {
+        writeln!(writer, "{SCROBBLES_HEADER}")?;
+        for scrobble in scrobbles {
+            writer.write_all(scrobble_line(scrobble).as_bytes())?;
+        }
+        Ok(())
+    })
+}
+
+pub fn append_scrobbles(path: &Path, scrobbles: &[Scrobble]) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+
+    let mut lines = String::new();
+    if file.metadata()?.len() == 0 {
+        lines.push_str(SCROBBLES_HEADER);
+        lines.push('\n');
+    }
+    for scrobble in scrobbles {
+        lines.push_str(&scrobble_line(scrobble));
+    }
+    file.write_all(lines.as_bytes())
+}
+
+fn scrobble_line(scrobble: &Scrobble) -> String {
+    let mut line = format!("{}\t{}", scrobble.service.key(), scrobble.listened_at);
+    let mut escaped = String::new();
+    for field in [&scrobble.artist, &scrobble.title, &scrobble.album] {
+        escape_field_into(field, &mut escaped);
+        line.push('\t');
+        line.push_str(&escaped);
+    }
+    line.push('\t');
+    if let Some(duration) = scrobble.duration {
+        line.push_str(&duration.as_millis().to_string());
+    }
+    line.push('\n');
+    line
+}
+
+pub fn load_track_cache() -> Vec<CachedTrack> {
+    let Ok(file) = File::open(config_dir().join("tracks.cache")) else {
+        return Vec::new();
+    };
+
+    let mut lines = BufReader::new(file).lines().map_while(Result::ok);
+    if lines.next().as_deref() != Some(TRACK_CACHE_HEADER) {
+        return Vec::new();
+    }
+
+    lines.filter_map(|line| parse_cached_track(&line)).collect()
+}
+
+pub fn clear_track_cache() -> io::Result<()> {
+    match fs::remove_file(config_dir().join("tracks.cache")) {
+        Err(error) if error.kind() != io::ErrorKind::NotFound => Err(error),
+        _ => Ok(()),
+    }
+}
+
+pub fn save_track_cache(entries: &[CachedTrack]) -> io::Result<()> {
+    atomic_write("tracks.cache", |writer| {
+        writeln!(writer, "{TRACK_CACHE_HEADER}")?;
+        let mut escaped = String::new();
+        for entry in entries {
+            let track = &entry.track;
+            escape_path_into(&track.path, &mut escaped);
+            write!(
+                writer,
+                "{escaped}\t{}\t{}\t",
+                entry.stamp.size, entry.stamp.modified
+            )?;
+            escape_path_into(&track.album_dir, &mut escaped);
+            writer.write_all(escaped.as_bytes())?;
+            let fields = [
+                Cow::Borrowed(track.title.as_ref()),
+                track.artist.as_ref().into(),
+                track.album_artist.as_ref().into(),
+                track.album.as_ref().into(),
+                u8::from(track.compilation).to_string().into(),
+                u8::from(track.tagged_artist).to_string().into(),
+                u8::from(track.tagged_album).to_string().into(),
+                optional_field(track.track_no).into(),
+                optional_field(track.disc_no).into(),
+                optional_field(track.duration.map(|duration| duration.as_millis())).into(),
+                track.genre.as_deref().unwrap_or_default().into(),
+                optional_field(track.year).into(),
+                optional_field(track.rating).into(),
+                optional_field(track.modified).into(),
+                track.tags.composer.as_deref().unwrap_or_default().into(),
+                track.tags.conductor.as_deref().unwrap_or_default().into(),
+                track.tags.comment.as_deref().unwrap_or_default().into(),
+                track.tags.label.as_deref().unwrap_or_default().into(),
+                track.tags.date.as_deref().unwrap_or_default().into(),
+                optional_field(track.tags.track_total).into(),
+                optional_field(track.tags.disc_total).into(),
+                track.tags.musicbrainz_recording_id.as_deref().unwrap_or_default().into(),
+                track.tags.musicbrainz_release_id.as_deref().unwrap_or_default().into(),
+                track.tags.musicbrainz_release_group_id.as_deref().unwrap_or_default().into(),
+                track.tags.musicbrainz_artist_id.as_deref().unwrap_or_default().into(),
+                track.tags.musicbrainz_album_artist_id.as_deref().unwrap_or_default().into(),
+                track.format.codec.as_deref().unwrap_or_default().into(),
+                optional_field(track.format.sample_rate).into(),
+                optional_field(track.format.bit_depth).into(),
+                optional_field(track.format.bitrate).into(),
+                optional_field(track.format.channels).into(),
+                optional_field(track.replay_gain.track_gain).into(),
+                optional_field(track.replay_gain.track_peak).into(),
+                optional_field(track.replay_gain.album_gain).into(),
+                optional_field(track.replay_gain.album_peak).into(),
+                optional_field(track.range.map(|range| range.start.as_millis())).into(),
+                optional_field(track.range.and_then(|range| range.end).map(|end| end.as_millis()))
+                    .into(),
+            ];
+            for field in &fields {
+                escape_field_into(field, &mut escaped);
+                writer.write_all(b"\t")?;
+                writer.write_all(escaped.as_bytes())?;
+            }
+            writer.write_all(b"\n")?;
+        }
+        Ok(())
+    })
+}
+
+fn parse_cached_track(line: &str) -> Option<CachedTrack> {
+    let mut fields = line.split('\t');
+    let path = unescape_path(fields.next()?);
+    let size = fields.next()?.parse().ok()?;
+    let stamp_modified = fields.next()?.parse().ok()?;
+    let album_dir = unescape_path(fields.next()?);
+    let mut fields = fields.map(unescape_field);
+    let title = fields.next()?;
+    let artist = fields.next()?;
+    let album_artist = fields.next()?;
+    let album = fields.next()?;
+    let compilation = fields.next()? == "1";
+    let tagged_artist = fields.next()? == "1";
+    let tagged_album = fields.next()? == "1";
+    let track_no = fields.next()?.parse().ok();
+    let disc_no = fields.next()?.parse().ok();
+    let duration = fields.next()?.parse().ok().map(Duration::from_millis);
+    let genre = text_field(fields.next()?);
+    let year = fields.next()?.parse().ok();
+    let rating = fields.next()?.parse().ok();
+    let modified = fields.next()?.parse().ok();
+    let tags = TrackTags {
+        composer: text_field(fields.next()?),
+        conductor: text_field(fields.next()?),
+        comment: text_field(fields.next()?),
+        label: text_field(fields.next()?),
+        date: text_field(fields.next()?),
+        track_total: fields.next()?.parse().ok(),
+        disc_total: fields.next()?.parse().ok(),
+        musicbrainz_recording_id: text_field(fields.next()?),
+        musicbrainz_release_id: text_field(fields.next()?),
+        musicbrainz_release_group_id: text_field(fields.next()?),
+        musicbrainz_artist_id: text_field(fields.next()?),
+        musicbrainz_album_artist_id: text_field(fields.next()?),
+    };
+    let format = AudioFormat {
+        codec: text_field(fields.next()?),
+        sample_rate: fields.next()?.parse().ok(),
+        bit_depth: fields.next()?.parse().ok(),
+        bitrate: fields.next()?.parse().ok(),
+        channels: fields.next()?.parse().ok(),
+    };
+    let replay_gain = ReplayGain {
+        track_gain: fields.next()?.parse().ok(),
+        track_peak: fields.next()?.parse().ok(),
+        album_gain: fields.next()?.parse().ok(),
+        album_peak: fields.next()?.parse().ok(),
+    };
+    let range_start = fields.next().and_then(|field| field.parse().ok()).map(Duration::from_millis);
+    let range_end = fields.next().and_then(|field| field.parse().ok()).map(Duration::from_millis);
+    let range = range_start.map(|start| TrackRange {
+        start,
+        end: range_end,
+    });
+
+    Some(CachedTrack {
+        stamp: FileStamp {
+            size,
+            modified: stamp_modified,
+        },
+        track: Track {
+            path,
+            album_dir: Arc::from(album_dir.into_boxed_path()),
+            title: Arc::from(title),
+            artist: Arc::from(artist),
+            album_artist: Arc::from(album_artist),
+            album: Arc::from(album),
+            compilation,
+            tagged_artist,
+            tagged_album,
+            track_no,
+            disc_no,
+            duration,
+            genre,
+            year,
+            rating,
+            modified,
+            tags,
+            format,
+            replay_gain,
+            range,
+        },
+    })
+}
+
+fn text_field(value: String) -> Option<Arc<str>> {
+    (!value.is_empty()).then(|| Arc::from(value))
+}
+
+fn optional_field<T: ToString>(value: Option<T>) -> String {
+    value.map(|value| value.to_string()).unwrap_or_default()
+}
+
 fn push_valid_playlist(playlists: &mut Vec<StoredPlaylist>, mut playlist: StoredPlaylist) {
     playlist.name = playlist.name.trim().to_owned();
     if playlist.name.is_empty()
@@ -147,6 +766,10 @@ fn push_valid_playlist(playlists: &mut Vec<StoredPlaylist>, mut playlist: Stored
 
 fn escape_field_into(value: &str, output: &mut String) {
     output.clear();
+    push_escaped(value, output);
+}
+
+fn push_escaped(value: &str, output: &mut String) {
     output.reserve(value.len());
     for character in value.chars() {
         match character {
@@ -159,6 +782,59 @@ fn escape_field_into(value: &str, output: &mut String) {
     }
 }
 
+/// Paths that are not valid UTF-8 keep their raw bytes as `\xHH` escapes, so
+/// they still match the scanned file on the next start.
+fn escape_path_into(path: &Path, output: &mut String) {
+    output.clear();
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+
+        for chunk in path.as_os_str().as_bytes().utf8_chunks() {
+            push_escaped(chunk.valid(), output);
+            for byte in chunk.invalid() {
+                output.push_str(&format!("\\x{byte:02x}"));
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    push_escaped(&path.to_string_lossy(), output);
+}
+
+#[cfg(unix)]
+fn unescape_path(value: &str) -> PathBuf {
+    use std::os::unix::ffi::OsStringExt;
+
+    let digit = |byte: u8| (byte as char).to_digit(16).unwrap_or(0) as u8;
+    let mut bytes = Vec::with_capacity(value.len());
+    let mut rest = value.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        rest = tail;
+        if byte != b'\\' {
+            bytes.push(byte);
+            continue;
+        }
+        let (decoded, used) = match rest {
+            [b't', ..] => (b'\t', 1),
+            [b'n', ..] => (b'\n', 1),
+            [b'r', ..] => (b'\r', 1),
+            [b'\\', ..] => (b'\\', 1),
+            [b'x', high, low, ..] if high.is_ascii_hexdigit() && low.is_ascii_hexdigit() => {
+                ((digit(*high) << 4) | digit(*low), 3)
+            }
+            _ => (b'\\', 0),
+        };
+        bytes.push(decoded);
+        rest = &rest[used..];
+    }
+    PathBuf::from(OsString::from_vec(bytes))
+}
+
+#[cfg(not(unix))]
+fn unescape_path(value: &str) -> PathBuf {
+    PathBuf::from(unescape_field(value))
+}
+
 fn unescape_field(value: &str) -> String {
     let mut result = String::with_capacity(value.len());
     let mut characters = value.chars();
@@ -189,11 +865,20 @@ fn atomic_write(
     file_name: &str,
     write: impl FnOnce(&mut BufWriter<File>) -> io::Result<()>,
 ) -> io::Result<()> {
-    let dir = config_dir();
-    fs::create_dir_all(&dir)?;
+    atomic_write_at(&config_dir().join(file_name), write)
+}
+
+fn atomic_write_at(
+    target: &Path,
+    write: impl FnOnce(&mut BufWriter<File>) -> io::Result<()>,
+) -> io::Result<()> {
+    let dir = target.parent().unwrap_or(Path::new("."));
+    fs::create_dir_all(dir)?;
 
-    let target = dir.join(file_name);
-    let temporary = dir.join(format!(".{file_name}.tmp"));
+    let mut file_name = OsString::from(".");
+    file_name.push(target.file_name().unwrap_or_default());
+    file_name.push(".tmp");
+    let temporary = dir.join(file_name);
     let file = File::create(&temporary)?;
     let mut writer = BufWriter::new(file);
     write(&mut writer)?;
@@ -214,7 +899,23 @@ pub fn home_dir() -> PathBuf {
         .unwrap_or_else(|| PathBuf::from("."))
 }
 
+pub fn scrobbles_path() -> PathBuf {
+    config_dir().join("scrobbles.txt")
+}
+
+#[cfg(unix)]
+pub fn control_socket_path() -> PathBuf {
+    config_dir().join("control.sock")
+}
+
+pub fn set_config_dir(path: PathBuf) {
+    let _ = CONFIG_DIR.set(path);
+}
+
 fn config_dir() -> PathBuf {
+    if let Some(path) = CONFIG_DIR.get() {
+        return path.clone();
+    }
     if let Some(path) = env::var_os("MUSA_CONFIG_DIR") {
         return PathBuf::from(path);
     }
@@ -226,3 +927,28 @@ fn config_dir() -> PathBuf {
     }
     home_dir().join(Path::new(".config")).join("musa")
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn cached_paths_round_trip_bytes_that_are_not_utf8() {
+        use std::os::unix::ffi::OsStringExt;
+
+        let mut escaped = String::new();
+        for bytes in [
+            b"/music/Bj\xf6rk/J\xf3ga.flac".to_vec(),
+            b"/music/back\\xslash\\\\x41/tab\there.mp3".to_vec(),
+            "/music/Sigur Rós/Hoppípolla.flac".as_bytes().to_vec(),
+        ] {
+            let path = PathBuf::from(OsString::from_vec(bytes));
+            escape_path_into(&path, &mut escaped);
+            assert!(!escaped.contains('\t'), "{escaped}");
+            assert_eq!(unescape_path(&escaped), path, "{escaped}");
+        }
+        escape_path_into(Path::new("/a\\xff"), &mut escaped);
+        assert_eq!(escaped, "/a\\\\xff");
+    }
+}
@@ -1,4 +1,7 @@
-use std::io::{self, Stdout, Write};
+use std::{
+    borrow::Cow,
+    io::{self, Stdout, Write},
+};
 
 use crossterm::{
     QueueableCommand,
@@ -14,7 +17,8 @@ use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
 
 use crate::{
     app::{
-        App, DetailView, FolderFocus, PlaybackState, ScanPhase, SearchItem, Theme, View,
+        App, DetailView, FolderFocus, HomeSection, PlaybackState, ScanPhase, SearchItem, Theme, View,
+        YearGrouping,
     },
     library::{DirectoryEntryKind, Track},
 };
@@ -214,7 +218,7 @@ impl Canvas {
 
     fn render_diff(
         &self,
-        output: &mut Stdout,
+        output: &mut impl Write,
         previous: &mut Vec<Cell>,
         force: bool,
     ) -> io::Result<()> {
@@ -290,38 +294,28 @@ impl Canvas {
     }
 }
 
-pub struct Terminal {
-    output: Stdout,
+pub struct Screen<W: Write> {
+    output: W,
     canvas: Canvas,
     previous: Vec<Cell>,
     force_redraw: bool,
 }
 
-impl Terminal {
-    pub fn enter() -> io::Result<Self> {
-        terminal::enable_raw_mode()?;
-        let mut output = io::stdout();
-        if let Err(error) = execute!(
-            output,
-            EnterAlternateScreen,
-            DisableLineWrap,
-            Hide,
-            Clear(ClearType::All),
-            SetTitle("MUSA")
-        ) {
-            let _ = terminal::disable_raw_mode();
-            return Err(error);
-        }
-        Ok(Self {
+impl<W: Write> Screen<W> {
+    pub fn new(output: W) -> Self {
+        Self {
             output,
             canvas: Canvas::empty(),
             previous: Vec::new(),
             force_redraw: true,
-        })
+        }
     }
 
-    pub fn draw(&mut self, app: &App) -> io::Result<()> {
-        let (width, height) = terminal::size()?;
+    pub fn get_ref(&self) -> &W {
+        &self.output
+    }
+
+    pub fn draw(&mut self, app: &App, width: u16, height: u16) -> io::Result<()> {
         let palette = Palette::for_theme(app.theme);
         let resized = self.canvas.reset(width, height, palette);
 
@@ -344,10 +338,40 @@ impl Terminal {
     }
 }
 
+pub struct Terminal {
+    screen: Screen<Stdout>,
+}
+
+impl Terminal {
+    pub fn enter() -> io::Result<Self> {
+        terminal::enable_raw_mode()?;
+        let mut output = io::stdout();
+        if let Err(error) = execute!(
+            output,
+            EnterAlternateScreen,
+            DisableLineWrap,
+            Hide,
+            Clear(ClearType::All),
+            SetTitle("MUSA")
+        ) {
+            let _ = terminal::disable_raw_mode();
+            return Err(error);
+        }
+        Ok(Self {
+            screen: Screen::new(output),
+        })
+    }
+
+    pub fn draw(&mut self, app: &App) -> io::Result<()> {
+        let (width, height) = terminal::size()?;
+        self.screen.draw(app, width, height)
+    }
+}
+
 impl Drop for Terminal {
     fn drop(&mut self) {
         let _ = execute!(
-            self.output,
+            self.screen.output,
             ResetColor,
             SetAttribute(Attribute::Reset),
             EndSynchronizedUpdate,
@@ -406,8 +430,9 @@ fn draw_sidebar(
     canvas.fill(0, 0, width, height, Style::new(palette.text, palette.sidebar));
     canvas.text(2, 1, "MUSA", width.saturating_sub(4), Style::new(palette.accent, palette.sidebar).bold());
 
+    let spacing = if height >= 4 + View::ALL.len() as u16 * 2 + 8 { 2 } else { 1 };
     for (index, view) in View::ALL.iter().copied().enumerate() {
-        let row = 4 + index as u16 * 2;
+        let row = 4 + index as u16 * spacing;
         let active = app.view == view;
         let background = if active { palette.selected } else { palette.sidebar };
         canvas.fill(0, row, width, 1, Style::new(palette.text, background));
@@ -537,8 +562,10 @@ fn draw_content(
         View::Songs => draw_songs(canvas, app, palette, x, y, width, height),
         View::Albums => draw_albums(canvas, app, palette, x, y, width, height),
         View::Artists => draw_artists(canvas, app, palette, x, y, width, height),
+        View::Genres | View::Years => draw_categories(canvas, app, palette, x, y, width, height),
         View::Playlists => draw_playlists(canvas, app, palette, x, y, width, height),
         View::Folders => draw_folders(canvas, app, palette, x, y, width, height),
+        View::Queue => draw_queue(canvas, app, palette, x, y, width, height),
     }
 }
 
@@ -568,15 +595,31 @@ fn draw_home(
     }
 
     let list_y = y + 7;
-    canvas.text(x + 3, list_y, "Recently played", width.saturating_sub(6), Style::new(palette.text, palette.background).bold());
-    let recent = app.recent_indices();
-    if recent.is_empty() {
+    let sections = [
+        (HomeSection::Recent, "Recently played"),
+        (HomeSection::MostPlayed, "Most played"),
+    ];
+    let mut label_x = x + 3;
+    for (section, label) in sections {
+        let style = if app.home_section == section {
+            Style::new(palette.text, palette.background).bold()
+        } else {
+            Style::new(palette.faint, palette.background)
+        };
+        canvas.text(label_x, list_y, label, width.saturating_sub(label_x - x + 3), style);
+        label_x += label.chars().count() as u16 + 3;
+    }
+    let rows = app.home_indices();
+    if rows.is_empty() {
         empty_message(
             canvas,
             x,
             list_y + 3,
             width,
-            "Your recently played songs will appear here",
+            match app.home_section {
+                HomeSection::Recent => "Your recently played songs will appear here",
+                HomeSection::MostPlayed => "Songs you play often will appear here",
+            },
             palette,
         );
     } else {
@@ -584,13 +627,16 @@ fn draw_home(
             canvas,
             app,
             palette,
-            TrackRows::Indices(recent),
+            TrackRows::Indices(rows),
             app.selected,
             x + 2,
             list_y + 2,
             width.saturating_sub(4),
             height.saturating_sub(10),
-            TrackColumns::Album,
+            match app.home_section {
+                HomeSection::Recent => TrackColumns::Album,
+                HomeSection::MostPlayed => TrackColumns::Plays,
+            },
         );
     }
 }
@@ -622,6 +668,108 @@ fn draw_songs(
     );
 }
 
+fn draw_queue(
+    canvas: &mut Canvas,
+    app: &App,
+    palette: Palette,
+    x: u16,
+    y: u16,
+    width: u16,
+    height: u16,
+) {
+    let len = app.selection_len();
+    let position = app.active_queue_position();
+    let subtitle = match position {
+        Some(position) if app.state != PlaybackState::Stopped => {
+            format!("{} songs  -  playing {} of {}", len, position + 1, len)
+        }
+        _ => format!("{len} songs"),
+    };
+    draw_collection_header(
+        canvas,
+        palette,
+        x,
+        y,
+        width,
+        if app.folder_queue().is_some() { "FOLDER QUEUE" } else { "QUEUE" },
+        "Up next",
+        &subtitle,
+    );
+    if len == 0 {
+        empty_message(
+            canvas,
+            x,
+            y + 7,
+            width,
+            "The queue is empty; press A to add or N to play next",
+            palette,
+        );
+        return;
+    }
+
+    let Some(paths) = app.folder_queue() else {
+        draw_track_table(
+            canvas,
+            app,
+            palette,
+            TrackRows::Indices(&app.queue),
+            app.selected,
+            x + 2,
+            y + 5,
+            width.saturating_sub(4),
+            height.saturating_sub(6),
+            TrackColumns::Album,
+        );
+        return;
+    };
+
+    let inner_x = x + 2;
+    let inner_width = width.saturating_sub(4);
+    let folder_x = inner_x + inner_width * 58 / 100;
+    canvas.text(inner_x + 2, y + 5, "#", 5, Style::new(palette.faint, palette.background));
+    canvas.text(inner_x + 9, y + 5, "FILE", folder_x.saturating_sub(inner_x + 10), Style::new(palette.faint, palette.background));
+    canvas.text(folder_x, y + 5, "FOLDER", inner_width.saturating_sub(folder_x - inner_x), Style::new(palette.faint, palette.background));
+    canvas.hline(inner_x, y + 6, inner_width, '-', Style::new(palette.border, palette.background));
+
+    let visible = height.saturating_sub(8) as usize;
+    let start = window_start(app.selected, paths.len(), visible);
+    for (row, index) in (start..paths.len()).take(visible).enumerate() {
+        let path = &paths[index];
+        let row_y = y + 7 + row as u16;
+        let is_selected = index == app.selected;
+        let is_current = position == Some(index);
+        let background = row_background(is_selected, is_current, palette);
+        canvas.fill(inner_x, row_y, inner_width, 1, Style::new(palette.text, background));
+        if is_current {
+            canvas.text(inner_x + 1, row_y, ">", 1, Style::new(palette.accent, background).bold());
+        }
+        canvas.text(inner_x + 3, row_y, &format!("{:>5}", index + 1), 5, Style::new(palette.muted, background));
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy())
+            .unwrap_or_default();
+        canvas.text(
+            inner_x + 9,
+            row_y,
+            &name,
+            folder_x.saturating_sub(inner_x + 10),
+            selected_style(is_selected, background, palette),
+        );
+        let folder = path
+            .parent()
+            .and_then(|parent| parent.file_name())
+            .map(|name| name.to_string_lossy())
+            .unwrap_or_default();
+        canvas.text(
+            folder_x,
+            row_y,
+            &folder,
+            inner_width.saturating_sub(folder_x - inner_x + 1),
+            Style::new(palette.muted, background),
+        );
+    }
+}
+
 fn draw_search(
     canvas: &mut Canvas,
     app: &App,
@@ -680,6 +828,18 @@ fn draw_search(
                     false,
                 )
             }
+            SearchItem::Genre(index) | SearchItem::Year(index) => {
+                let (kind, category) = match item {
+                    SearchItem::Genre(_) => ("GENRE", &app.genres[index]),
+                    _ => ("YEAR", &app.years[index]),
+                };
+                (
+                    kind,
+                    category.name.as_ref(),
+                    format!("{} albums, {} songs", category.album_count, category.tracks.len()),
+                    false,
+                )
+            }
             SearchItem::Track(index) => {
                 let track = &app.tracks[index];
                 (
@@ -831,6 +991,77 @@ fn draw_artists(
     }
 }
 
+fn draw_categories(
+    canvas: &mut Canvas,
+    app: &App,
+    palette: Palette,
+    x: u16,
+    y: u16,
+    width: u16,
+    height: u16,
+) {
+    let categories = app.categories();
+    let (kind, column) = match (app.view, app.year_grouping) {
+        (View::Years, YearGrouping::Year) => ("YEAR", "YEAR"),
+        (View::Years, YearGrouping::Decade) => ("DECADE", "DECADE"),
+        _ => ("GENRE", "GENRE"),
+    };
+    if let Some(DetailView::Genre(index) | DetailView::Year(index)) = app.detail {
+        if let Some(category) = categories.get(index) {
+            draw_collection_header(
+                canvas,
+                palette,
+                x,
+                y,
+                width,
+                kind,
+                &category.name,
+                &format!("{} albums  -  {} songs", category.album_count, category.tracks.len()),
+            );
+            draw_track_table(
+                canvas,
+                app,
+                palette,
+                TrackRows::Indices(&category.tracks),
+                app.selected,
+                x + 2,
+                y + 5,
+                width.saturating_sub(4),
+                height.saturating_sub(6),
+                TrackColumns::Album,
+            );
+        }
+        return;
+    }
+
+    if categories.is_empty() {
+        empty_library(canvas, x, y, width, palette);
+        return;
+    }
+
+    let inner_x = x + 2;
+    let inner_width = width.saturating_sub(4);
+    canvas.text(inner_x + 3, y + 1, "#", 4, Style::new(palette.faint, palette.background));
+    canvas.text(inner_x + 9, y + 1, column, inner_width / 2, Style::new(palette.faint, palette.background));
+    canvas.text_right(inner_x + inner_width - 12, y + 1, "ALBUMS", 8, Style::new(palette.faint, palette.background));
+    canvas.text_right(inner_x + inner_width - 1, y + 1, "SONGS", 8, Style::new(palette.faint, palette.background));
+    canvas.hline(inner_x, y + 2, inner_width, '-', Style::new(palette.border, palette.background));
+
+    let visible = height.saturating_sub(4) as usize;
+    let start = window_start(app.selected, categories.len(), visible);
+    for (row, position) in (start..categories.len()).take(visible).enumerate() {
+        let category = &categories[position];
+        let row_y = y + 3 + row as u16;
+        let selected = position == app.selected;
+        let background = row_background(selected, false, palette);
+        canvas.fill(inner_x, row_y, inner_width, 1, Style::new(palette.text, background));
+        canvas.text(inner_x + 3, row_y, &format!("{:>4}", position + 1), 4, Style::new(palette.muted, background));
+        canvas.text(inner_x + 9, row_y, &category.name, inner_width.saturating_sub(34), selected_style(selected, background, palette));
+        canvas.text_right(inner_x + inner_width - 12, row_y, &category.album_count.to_string(), 8, Style::new(palette.muted, background));
+        canvas.text_right(inner_x + inner_width - 1, row_y, &category.tracks.len().to_string(), 8, Style::new(palette.muted, background));
+    }
+}
+
 fn draw_playlists(
     canvas: &mut Canvas,
     app: &App,
@@ -843,7 +1074,7 @@ fn draw_playlists(
     if let Some(DetailView::Playlist(index)) = app.detail {
         if let Some(playlist) = app.playlists.get(index) {
             let missing = playlist.track_paths.len().saturating_sub(playlist.tracks.len());
-            let subtitle = if missing == 0 {
+            let mut subtitle = if missing == 0 {
                 format!(
                     "{} songs  -  {}",
                     playlist.tracks.len(),
@@ -857,13 +1088,23 @@ fn draw_playlists(
                     format_duration(playlist.duration)
                 )
             };
+            if let Some(source) = &playlist.source {
+                subtitle.push_str(&format!("  -  {}", source.display()));
+            }
+            if let Some(smart) = &playlist.smart {
+                subtitle.push_str(&format!("  -  {}", smart.summary()));
+            }
             draw_collection_header(
                 canvas,
                 palette,
                 x,
                 y,
                 width,
-                "PLAYLIST",
+                match (&playlist.source, &playlist.smart) {
+                    (Some(_), _) => "PLAYLIST FILE",
+                    (None, Some(_)) => "SMART PLAYLIST",
+                    (None, None) => "PLAYLIST",
+                },
                 &playlist.name,
                 &subtitle,
             );
@@ -921,6 +1162,17 @@ fn draw_playlists(
         canvas.fill(inner_x, row_y, inner_width, 1, Style::new(palette.text, background));
         canvas.text(inner_x + 3, row_y, &format!("{:>4}", position + 1), 4, Style::new(palette.muted, background));
         canvas.text(inner_x + 9, row_y, &playlist.name, inner_width.saturating_sub(32), selected_style(selected, background, palette));
+        let tag = match (&playlist.source, &playlist.smart) {
+            (Some(_), _) => Some("file"),
+            (None, Some(_)) => Some("smart"),
+            (None, None) => None,
+        };
+        if let Some(tag) = tag {
+            let name_width = playlist.name.chars().count() as u16;
+            if name_width + 9 < inner_width.saturating_sub(32) {
+                canvas.text(inner_x + 10 + name_width, row_y, tag, 5, Style::new(palette.faint, background));
+            }
+        }
         let songs = if playlist.tracks.len() == playlist.track_paths.len() {
             playlist.tracks.len().to_string()
         } else {
@@ -1009,6 +1261,16 @@ fn draw_folders(
                         selected_style(selected, background, palette),
                     );
                 }
+                DirectoryEntryKind::Playlist(_) => {
+                    canvas.text(browser_x + 2, row_y, "PLS", 3, Style::new(palette.accent, background).bold());
+                    canvas.text(
+                        browser_x + 6,
+                        row_y,
+                        &entry.name,
+                        browser_width.saturating_sub(8),
+                        selected_style(selected, background, palette),
+                    );
+                }
                 DirectoryEntryKind::Track(track) => {
                     let is_current = app
                         .current_track()
@@ -1104,6 +1366,7 @@ impl TrackRows<'_> {
 enum TrackColumns {
     Album,
     TrackNumber,
+    Plays,
 }
 
 #[allow(clippy::too_many_arguments)]
@@ -1124,9 +1387,15 @@ fn draw_track_table(
     }
 
     let title_x = x + 9;
-    let middle_x = x + width * 58 / 100;
     let time_width = 8;
     let time_x = x + width.saturating_sub(time_width + 1);
+    let format_width = if width >= 130 { 14 } else { 0 };
+    let year_width = if width >= 90 { 6 } else { 0 };
+    let genre_width = if width >= 110 { 16 } else { 0 };
+    let format_x = time_x - format_width;
+    let year_x = format_x - year_width;
+    let genre_x = year_x - genre_width;
+    let middle_x = x + (genre_x - x) * 58 / 100;
 
     canvas.text(x + 2, y, "#", 5, Style::new(palette.faint, palette.background));
     canvas.text(title_x, y, "TITLE", middle_x.saturating_sub(title_x + 1), Style::new(palette.faint, palette.background));
@@ -1136,10 +1405,20 @@ fn draw_track_table(
         match columns {
             TrackColumns::Album => "ALBUM",
             TrackColumns::TrackNumber => "ARTIST",
+            TrackColumns::Plays => "PLAYS",
         },
-        time_x.saturating_sub(middle_x + 1),
+        genre_x.saturating_sub(middle_x + 1),
         Style::new(palette.faint, palette.background),
     );
+    for (column_x, column_width, label) in [
+        (genre_x, genre_width, "GENRE"),
+        (year_x, year_width, "YEAR"),
+        (format_x, format_width, "FORMAT"),
+    ] {
+        if column_width > 0 {
+            canvas.text(column_x, y, label, column_width - 1, Style::new(palette.faint, palette.background));
+        }
+    }
     canvas.text_right(x + width - 1, y, "TIME", time_width, Style::new(palette.faint, palette.background));
     canvas.hline(x, y + 1, width, '-', Style::new(palette.border, palette.background));
 
@@ -1170,7 +1449,7 @@ fn draw_track_table(
         canvas.text(x + 3, row_y, &format!("{:>5}", position + 1), 5, Style::new(palette.muted, background));
 
         match columns {
-            TrackColumns::Album => canvas.text(
+            TrackColumns::Album | TrackColumns::Plays => canvas.text(
                 title_x,
                 row_y,
                 &track.title,
@@ -1188,17 +1467,51 @@ fn draw_track_table(
                 );
             }
         }
-        let middle = match columns {
-            TrackColumns::Album => track.album.as_ref(),
-            TrackColumns::TrackNumber => track.artist.as_ref(),
+        let middle: Cow<'_, str> = match columns {
+            TrackColumns::Album => track.album.as_ref().into(),
+            TrackColumns::TrackNumber => track.artist.as_ref().into(),
+            TrackColumns::Plays => {
+                let stats = app.play_stats(track_index);
+                match stats.skips {
+                    0 => format!("{} plays", stats.plays).into(),
+                    skips => format!("{} plays, {skips} skips", stats.plays).into(),
+                }
+            }
         };
         canvas.text(
             middle_x,
             row_y,
-            middle,
-            time_x.saturating_sub(middle_x + 1),
+            &middle,
+            genre_x.saturating_sub(middle_x + 1),
             Style::new(palette.muted, background),
         );
+        if genre_width > 0 {
+            canvas.text(
+                genre_x,
+                row_y,
+                track.genre.as_deref().unwrap_or_default(),
+                genre_width - 1,
+                Style::new(palette.muted, background),
+            );
+        }
+        if year_width > 0 {
+            canvas.text(
+                year_x,
+                row_y,
+                &track.year.map(|year| year.to_string()).unwrap_or_default(),
+                year_width - 1,
+                Style::new(palette.muted, background),
+            );
+        }
+        if format_width > 0 {
+            canvas.text(
+                format_x,
+                row_y,
+                &audio_format(track),
+                format_width - 1,
+                Style::new(palette.muted, background),
+            );
+        }
         canvas.text_right(
             x + width - 1,
             row_y,
@@ -1209,6 +1522,7 @@ fn draw_track_table(
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn draw_collection_header(
     canvas: &mut Canvas,
     palette: Palette,
@@ -1252,10 +1566,11 @@ fn draw_player(
         PlaybackState::Paused | PlaybackState::Stopped => "PLAY",
     };
     let controls = format!(
-        "{}   PREV   {}   NEXT   REPEAT:{}",
+        "{}   PREV   {}   NEXT   REPEAT:{}   RG:{}",
         if app.shuffle { "SHUFFLE" } else { "shuffle" },
         play_label,
-        app.repeat.label()
+        app.repeat.label(),
+        app.gain_mode().label()
     );
     canvas.text_center(center_x, y + 1, center_width, &controls, Style::new(palette.text, palette.player).bold());
 
@@ -1325,7 +1640,8 @@ fn draw_playlist_picker(canvas: &mut Canvas, app: &App, palette: Palette) {
     };
     let width = canvas.width.min(68).saturating_sub(4).max(32);
     let max_rows = canvas.height.saturating_sub(10) as usize;
-    let list_rows = app.playlists.len().min(max_rows).max(1);
+    let count = app.stored_playlist_count();
+    let list_rows = count.min(max_rows).max(1);
     let height = (list_rows as u16 + 6).min(canvas.height.saturating_sub(4)).max(9);
     let x = (canvas.width - width) / 2;
     let y = (canvas.height - height) / 2;
@@ -1338,8 +1654,8 @@ fn draw_playlist_picker(canvas: &mut Canvas, app: &App, palette: Palette) {
     canvas.hline(x + 2, y + 3, width.saturating_sub(4), '-', Style::new(palette.border, palette.surface));
 
     let rows = height.saturating_sub(5) as usize;
-    let start = window_start(picker.selected, app.playlists.len(), rows);
-    for (row, position) in (start..app.playlists.len()).take(rows).enumerate() {
+    let start = window_start(picker.selected, count, rows);
+    for (row, position) in (start..count).take(rows).enumerate() {
         let playlist = &app.playlists[position];
         let row_y = y + 4 + row as u16;
         let selected = position == picker.selected;
@@ -1353,7 +1669,7 @@ fn draw_playlist_picker(canvas: &mut Canvas, app: &App, palette: Palette) {
 
 fn draw_help(canvas: &mut Canvas, palette: Palette) {
     let width = canvas.width.min(82).saturating_sub(4);
-    let height = canvas.height.min(24).saturating_sub(2);
+    let height = canvas.height.min(32).saturating_sub(2);
     let x = (canvas.width - width) / 2;
     let y = (canvas.height - height) / 2;
 
@@ -1362,20 +1678,26 @@ fn draw_help(canvas: &mut Canvas, palette: Palette) {
     canvas.text(x + 3, y + 1, "Keyboard", width.saturating_sub(6), Style::new(palette.text, palette.surface).bold());
 
     let controls = [
-        ("1..7", "Switch Home through Folders"),
+        ("1..8, 9, 0", "Switch Home through Queue / Genres / Years"),
+        ("Tab", "Home recent / most played; Artists track / album; Years / decades"),
         ("/", "Search; in Folders, open filesystem root /"),
+        ("Search s", "Save the search as a smart playlist"),
         ("Up/Down, j/k", "Move; PgUp/PgDn ten rows; g/G first/last"),
         ("Enter / Esc", "Open or play / close detail or modal"),
         ("Space, n, p", "Play-pause / next / previous"),
         ("[ ], + -", "Seek by five seconds / change volume"),
         ("x / r / t", "Shuffle / repeat mode / dark-light theme"),
+        ("v", "Cycle ReplayGain off / track / album"),
         ("a", "Add song, album, artist, or playlist"),
         ("Playlists c/e", "Create / rename playlist"),
         ("Playlists d/D", "Remove selected song / delete playlist"),
+        ("Playlists i/w", "Import / export an M3U, PLS, or XSPF file"),
         ("Picker c/Enter", "Create target playlist / add to selected playlist"),
         ("Folders Left/Right", "Switch roots and directory browser"),
         ("Folders Enter/Backspace", "Open directory / go to parent"),
         ("Folders ~ / a / d", "Home / add folder / remove root"),
+        ("N / A", "Play selection next / add it to the end of the queue"),
+        ("Queue J/K, d, c", "Move song down/up, remove it, clear the queue"),
         ("u", "Rescan saved library folders"),
         ("? / q", "Close help / quit"),
     ];
@@ -1445,6 +1767,27 @@ fn track_number(track: &Track) -> String {
     }
 }
 
+fn audio_format(track: &Track) -> String {
+    let format = &track.format;
+    let rate = |rate: u32| {
+        if rate.is_multiple_of(1000) {
+            (rate / 1000).to_string()
+        } else {
+            format!("{:.1}", f64::from(rate) / 1000.0)
+        }
+    };
+    let quality = match (format.bit_depth, format.sample_rate, format.bitrate) {
+        (Some(depth), Some(sample_rate), _) => format!("{depth}/{}", rate(sample_rate)),
+        (None, _, Some(bitrate)) => format!("{bitrate}k"),
+        (None, Some(sample_rate), None) => format!("{}kHz", rate(sample_rate)),
+        _ => String::new(),
+    };
+    match format.codec.as_deref() {
+        Some(codec) => format!("{codec} {quality}").trim_end().to_owned(),
+        None => quality,
+    }
+}
+
 fn format_duration(duration: std::time::Duration) -> String {
     format_time(duration.as_secs_f64())
 }